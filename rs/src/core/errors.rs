@@ -22,6 +22,16 @@ pub enum GraphError {
     GraphNotFound(i32),
     #[error("Profile with ID '{0}' not found in graph container")]
     ProfileNotFound(String),
+    #[error("Graph has no routable ways for profile '{0}'")]
+    EmptyGraph(String),
+    #[error("Invalid edge weights: {0}")]
+    InvalidEdgeWeights(String),
+    #[error("Invalid profile '{0}': {1}")]
+    InvalidProfile(String, String),
+    #[error("Invalid coordinates: {0}")]
+    InvalidCoordinates(String),
+    #[error("Tag key '{0}' is not indexed; add it to indexTags")]
+    TagNotIndexed(String),
     #[error("Routing Error: {0}")]
     RoutingError(String),
     #[error("Bincode Error: {0}")]
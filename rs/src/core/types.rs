@@ -53,7 +53,26 @@ impl Hash for ProfilePenalties {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum PenaltyMatch {
+    #[default]
+    Exact,
+    Prefix,
+    Suffix,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnknownHighwayPolicy {
+    #[default]
+    Default,
+    Exclude,
+    ErrorOnBuild,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
 pub struct Profile {
     pub id: String,
     pub key: String,
@@ -64,8 +83,38 @@ pub struct Profile {
     pub oneway_tags: Vec<String>,
     #[serde(default)]
     pub except_tags: Vec<String>,
+    #[serde(default)]
+    pub pedestrian_areas: bool,
+    #[serde(default)]
+    pub access_hierarchy: HashMap<String, String>,
+    #[serde(default)]
+    pub penalty_match: PenaltyMatch,
+    #[serde(default)]
+    pub speeds: HashMap<String, f64>,
+    #[serde(default)]
+    pub default_speed: Option<f64>,
+    #[serde(default)]
+    pub exclude_motorroads: bool,
+    #[serde(default)]
+    pub penalty_expression: Option<String>,
+    #[serde(default)]
+    pub ignore_access_restrictions: bool,
+    #[serde(default)]
+    pub ignore_turn_restrictions: bool,
+    #[serde(default)]
+    pub turn_angle_weight: f64,
+    #[serde(default)]
+    pub unlit_multiplier: f64,
+    #[serde(default)]
+    pub min_width: Option<f64>,
+    #[serde(default)]
+    pub narrow_way_multiplier: Option<f64>,
+    #[serde(default)]
+    pub unknown_highway_policy: UnknownHighwayPolicy,
 }
 
+impl Eq for Profile {}
+
 impl Hash for Profile {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.id.hash(state);
@@ -74,10 +123,32 @@ impl Hash for Profile {
         self.access_tags.hash(state);
         self.oneway_tags.hash(state);
         self.except_tags.hash(state);
+        self.pedestrian_areas.hash(state);
+        let mut sorted_hierarchy: Vec<_> = self.access_hierarchy.iter().collect();
+        sorted_hierarchy.sort();
+        sorted_hierarchy.hash(state);
+        self.penalty_match.hash(state);
+        let mut sorted_speeds: Vec<_> = self.speeds.iter().collect();
+        sorted_speeds.sort_by_key(|(k, _)| *k);
+        for (key, value) in sorted_speeds {
+            key.hash(state);
+            value.to_bits().hash(state);
+        }
+        self.default_speed.map(f64::to_bits).hash(state);
+        self.exclude_motorroads.hash(state);
+        self.penalty_expression.hash(state);
+        self.ignore_access_restrictions.hash(state);
+        self.ignore_turn_restrictions.hash(state);
+        self.turn_angle_weight.to_bits().hash(state);
+        self.unlit_multiplier.to_bits().hash(state);
+        self.min_width.map(f64::to_bits).hash(state);
+        self.narrow_way_multiplier.map(f64::to_bits).hash(state);
+        self.unknown_highway_policy.hash(state);
     }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Hash, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
 pub struct OverpassOptions {
     pub query: String,
     pub server: String,
@@ -86,6 +157,7 @@ pub struct OverpassOptions {
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Hash, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
 pub struct ProtobufOptions {
     pub url: String,
     pub retries: u32,
@@ -93,10 +165,31 @@ pub struct ProtobufOptions {
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct LoadOptions {
     pub file_path: String,
     pub ttl_days: u64,
     pub profiles: Vec<Profile>,
     pub overpass: Option<OverpassOptions>,
     pub protobuf: Option<ProtobufOptions>,
+    #[serde(default)]
+    pub snap_tolerance_m: f64,
+    #[serde(default)]
+    pub contract_shape_nodes: bool,
+    #[serde(default)]
+    pub edge_based: bool,
+    #[serde(default)]
+    pub max_clones_per_node: Option<u32>,
+    #[serde(default)]
+    pub merge_coincident_nodes: bool,
+    #[serde(default)]
+    pub share_topology: bool,
+    #[serde(default)]
+    pub earth_radius_km: Option<f64>,
+    #[serde(default)]
+    pub max_outside_bounds_km: Option<f64>,
+    #[serde(default)]
+    pub share_interner: bool,
+    #[serde(default)]
+    pub index_tags: Vec<String>,
 }
@@ -0,0 +1,125 @@
+use super::types::{LoadOptions, OverpassOptions, Profile, ProtobufOptions};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone)]
+pub struct OptionsIssue {
+    pub path: String,
+    pub message: String,
+}
+
+impl OptionsIssue {
+    fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        OptionsIssue {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for OptionsIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct OptionsValidation {
+    pub options: Option<LoadOptions>,
+    pub errors: Vec<OptionsIssue>,
+    pub warnings: Vec<OptionsIssue>,
+}
+
+pub fn check_load_options(json: &str) -> OptionsValidation {
+    let mut validation = OptionsValidation::default();
+
+    let value: Value = match serde_json::from_str(json) {
+        Ok(value) => value,
+        Err(e) => {
+            validation
+                .errors
+                .push(OptionsIssue::new("", format!("invalid JSON: {}", e)));
+            return validation;
+        }
+    };
+    let Some(fields) = value.as_object() else {
+        validation
+            .errors
+            .push(OptionsIssue::new("", "expected an object"));
+        return validation;
+    };
+
+    match fields.get("ttl_days").and_then(Value::as_f64) {
+        Some(ttl_days) if ttl_days < 0.0 => validation.errors.push(OptionsIssue::new(
+            "ttl_days",
+            format!("must be 0 or more days, got {}", ttl_days),
+        )),
+        _ => check_field::<u64>(fields, "ttl_days", &mut validation.errors),
+    }
+    check_field::<String>(fields, "file_path", &mut validation.errors);
+    check_field::<Option<OverpassOptions>>(fields, "overpass", &mut validation.errors);
+    check_field::<Option<ProtobufOptions>>(fields, "protobuf", &mut validation.errors);
+    check_field::<f64>(fields, "snap_tolerance_m", &mut validation.errors);
+    check_field::<bool>(fields, "contract_shape_nodes", &mut validation.errors);
+    check_field::<bool>(fields, "edge_based", &mut validation.errors);
+    check_field::<Option<u32>>(fields, "max_clones_per_node", &mut validation.errors);
+    check_field::<bool>(fields, "merge_coincident_nodes", &mut validation.errors);
+    check_field::<bool>(fields, "share_topology", &mut validation.errors);
+    check_field::<bool>(fields, "share_interner", &mut validation.errors);
+    check_field::<Vec<String>>(fields, "index_tags", &mut validation.errors);
+    check_field::<Option<f64>>(fields, "earth_radius_km", &mut validation.errors);
+    check_field::<Option<f64>>(fields, "max_outside_bounds_km", &mut validation.errors);
+
+    if let Some(Value::Array(profiles)) = fields.get("profiles") {
+        if profiles.is_empty() {
+            validation.warnings.push(OptionsIssue::new(
+                "profiles",
+                "no profiles given, so nothing can be routed on this graph",
+            ));
+        }
+
+        let mut profile_ids: Vec<String> = Vec::new();
+        for (i, profile) in profiles.iter().enumerate() {
+            let path = format!("profiles[{}]", i);
+            match Profile::deserialize(profile) {
+                Ok(profile) if profile_ids.contains(&profile.id) => {
+                    validation.errors.push(OptionsIssue::new(
+                        format!("{}.id", path),
+                        format!("duplicate profile id '{}'", profile.id),
+                    ));
+                }
+                Ok(profile) => profile_ids.push(profile.id),
+                Err(e) => validation
+                    .errors
+                    .push(OptionsIssue::new(path, e.to_string())),
+            }
+        }
+    } else {
+        check_field::<Vec<Value>>(fields, "profiles", &mut validation.errors);
+    }
+
+    if validation.errors.is_empty() {
+        match LoadOptions::deserialize(&value) {
+            Ok(options) => validation.options = Some(options),
+            Err(e) => validation.errors.push(OptionsIssue::new("", e.to_string())),
+        }
+    }
+    validation
+}
+
+fn check_field<T: DeserializeOwned>(
+    fields: &Map<String, Value>,
+    name: &str,
+    errors: &mut Vec<OptionsIssue>,
+) {
+    if let Some(value) = fields.get(name) {
+        if let Err(e) = T::deserialize(value) {
+            errors.push(OptionsIssue::new(name, e.to_string()));
+        }
+    }
+}
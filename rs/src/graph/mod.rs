@@ -1,8 +1,12 @@
 use crate::core::errors::{GraphError, Result};
-use rstar::{RTree, RTreeObject, AABB};
+use crate::core::types::LoadOptions;
+use crate::routing::{distance, meters_per_degree, EARTH_RADIUS_KM};
+use rstar::{Envelope, PointDistance, RTree, RTreeObject, AABB};
 use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
+use std::sync::Arc;
+use std::time::SystemTime;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct RouteNode {
@@ -13,13 +17,104 @@ pub struct RouteNode {
     pub tags: FxHashMap<u32, u32>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct WayInfo {
     pub osm_id: i64,
     pub node_ids: Vec<u32>,
     pub tags: FxHashMap<u32, u32>,
 }
 
+/// Node IDs by tag value for each key listed in `index_tags`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TagIndex {
+    pub keys: Vec<String>,
+    pub nodes: FxHashMap<String, FxHashMap<String, Vec<i64>>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TurnRule {
+    pub only: Option<i64>,
+    pub forbidden: Vec<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RestrictionInfo {
+    pub relation_id: i64,
+    pub kind: u32,
+    pub from_way: i64,
+    pub via_ways: Vec<i64>,
+    pub via_nodes: Vec<i64>,
+    pub to_way: i64,
+}
+
+impl TurnRule {
+    pub fn allows(&self, to_external_id: i64) -> bool {
+        self.only.is_none_or(|only| only == to_external_id)
+            && !self.forbidden.contains(&to_external_id)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WeightOverlay {
+    pub multipliers: FxHashMap<u32, f32>,
+    pub min_multiplier: f32,
+}
+
+impl WeightOverlay {
+    pub fn heuristic_scale(&self) -> f32 {
+        if self.multipliers.is_empty() {
+            1.0
+        } else {
+            self.min_multiplier.min(1.0)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SnapDirection {
+    #[default]
+    Any,
+    Depart,
+    Arrive,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RouteAdvisories {
+    pub ferry: bool,
+    pub toll: bool,
+    pub unpaved: bool,
+    pub private_access: bool,
+}
+
+type WalkState = (u32, Option<i64>, usize, usize);
+
+const UNPAVED_SURFACES: &[&str] = &[
+    "unpaved",
+    "compacted",
+    "fine_gravel",
+    "gravel",
+    "pebblestone",
+    "rock",
+    "dirt",
+    "earth",
+    "ground",
+    "grass",
+    "mud",
+    "sand",
+    "woodchips",
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct BuildReport {
+    pub warnings: Vec<String>,
+    pub skipped_ways: u32,
+    pub skipped_restrictions: u32,
+    pub restrictions_over_clone_cap: u32,
+    pub merged_nodes: u32,
+    pub shared_topology_with: Option<String>,
+    pub unknown_highway_values: Vec<String>,
+}
+
 #[derive(Clone, Debug)]
 pub struct SpatialWay {
     pub way_idx: usize,
@@ -33,42 +128,427 @@ impl RTreeObject for SpatialWay {
     }
 }
 
+impl PointDistance for SpatialWay {
+    fn distance_2(&self, point: &[f32; 2]) -> f32 {
+        self.aabb.distance_2(point)
+    }
+}
+
+const MAX_MATCHING_WAY_CANDIDATES: usize = 1000;
+
 #[derive(Serialize, Deserialize)]
 pub struct ProcessedGraph {
-    pub nodes: Vec<RouteNode>,
-    pub ways: Vec<WayInfo>,
-    pub offsets: Vec<usize>,
-    pub edges: Vec<(u32, u16)>,
-    pub string_interner: Vec<String>,
+    pub nodes: Arc<Vec<RouteNode>>,
+    pub ways: Arc<Vec<WayInfo>>,
+    pub way_speeds: Vec<f32>,
+    pub offsets: Arc<Vec<usize>>,
+    pub edges: Arc<Vec<u32>>,
+    pub edge_costs: Vec<u16>,
+    pub edge_times: Vec<u32>,
+    pub edge_ways: Arc<Vec<u32>>,
+    pub edge_geometry_offsets: Arc<Vec<u32>>,
+    pub edge_geometry: Arc<Vec<[f32; 2]>>,
+    pub string_interner: Arc<Vec<String>>,
+    pub profile_id: String,
+    pub connector_count: u32,
+    pub max_speed_kmh: f32,
+    pub turn_angle_weight: f32,
+    pub earth_radius_km: f32,
+    pub unlit_multiplier: f32,
+    pub turn_rules: Arc<FxHashMap<(i64, i64), TurnRule>>,
+    pub restrictions: Arc<Vec<RestrictionInfo>>,
+    pub node_aliases: Arc<FxHashMap<i64, i64>>,
+    /// Topology fields left out of the cache because an earlier profile
+    /// holds the same data, as (field, owning profile) pairs.
+    pub shared_fields: Vec<(String, String)>,
+    pub tag_index: TagIndex,
+
+    #[serde(skip)]
+    pub node_id_map: Arc<FxHashMap<i64, u32>>,
+
+    #[serde(skip)]
+    pub spatial_index: Arc<RTree<SpatialWay>>,
+
+    #[serde(skip)]
+    pub in_degrees: Arc<Vec<u32>>,
+
+    #[serde(skip)]
+    pub lit_tag: Option<(u32, u32)>,
 
     #[serde(skip)]
-    pub node_id_map: FxHashMap<i64, u32>,
+    pub max_outside_bounds_km: Option<f32>,
 
     #[serde(skip)]
-    pub spatial_index: RTree<SpatialWay>,
+    pub build_report: BuildReport,
+
+    #[serde(skip)]
+    pub weight_overlay: WeightOverlay,
 }
 
+/// Calls `$apply(field, &mut mine.field, &theirs.field, args..)` for every
+/// topology field that profiles can share.
+macro_rules! for_each_topology_field {
+    ($mine:expr, $theirs:expr, $apply:ident $(, $arg:expr)*) => {{
+        $apply("nodes", &mut $mine.nodes, &$theirs.nodes $(, $arg)*);
+        $apply("ways", &mut $mine.ways, &$theirs.ways $(, $arg)*);
+        $apply("offsets", &mut $mine.offsets, &$theirs.offsets $(, $arg)*);
+        $apply("edges", &mut $mine.edges, &$theirs.edges $(, $arg)*);
+        $apply("edge_ways", &mut $mine.edge_ways, &$theirs.edge_ways $(, $arg)*);
+        $apply(
+            "edge_geometry_offsets",
+            &mut $mine.edge_geometry_offsets,
+            &$theirs.edge_geometry_offsets
+            $(, $arg)*
+        );
+        $apply("edge_geometry", &mut $mine.edge_geometry, &$theirs.edge_geometry $(, $arg)*);
+        $apply("string_interner", &mut $mine.string_interner, &$theirs.string_interner $(, $arg)*);
+        $apply("turn_rules", &mut $mine.turn_rules, &$theirs.turn_rules $(, $arg)*);
+        $apply("restrictions", &mut $mine.restrictions, &$theirs.restrictions $(, $arg)*);
+        $apply("node_aliases", &mut $mine.node_aliases, &$theirs.node_aliases $(, $arg)*);
+    }};
+}
 impl ProcessedGraph {
     pub fn new() -> Self {
         ProcessedGraph {
-            nodes: Vec::new(),
-            ways: Vec::new(),
-            offsets: Vec::new(),
-            edges: Vec::new(),
-            string_interner: Vec::new(),
-            node_id_map: FxHashMap::default(),
-            spatial_index: RTree::new(),
+            nodes: Arc::default(),
+            ways: Arc::default(),
+            way_speeds: Vec::new(),
+            offsets: Arc::default(),
+            edges: Arc::default(),
+            edge_costs: Vec::new(),
+            edge_times: Vec::new(),
+            edge_ways: Arc::default(),
+            edge_geometry_offsets: Arc::default(),
+            edge_geometry: Arc::default(),
+            string_interner: Arc::default(),
+            profile_id: String::new(),
+            connector_count: 0,
+            max_speed_kmh: 0.0,
+            turn_angle_weight: 0.0,
+            earth_radius_km: EARTH_RADIUS_KM,
+            unlit_multiplier: 1.0,
+            turn_rules: Arc::default(),
+            restrictions: Arc::default(),
+            node_aliases: Arc::default(),
+            shared_fields: Vec::new(),
+            tag_index: TagIndex::default(),
+            node_id_map: Arc::default(),
+            spatial_index: Arc::default(),
+            in_degrees: Arc::default(),
+            lit_tag: None,
+            max_outside_bounds_km: None,
+            build_report: BuildReport::default(),
+            weight_overlay: WeightOverlay::default(),
+        }
+    }
+
+    pub fn edge_range(&self, node_id: u32) -> std::ops::Range<usize> {
+        self.offsets[node_id as usize]..self.offsets[(node_id as usize) + 1]
+    }
+
+    pub fn edge_geometry(&self, edge_idx: usize) -> &[[f32; 2]] {
+        if self.edge_geometry_offsets.is_empty() {
+            return &[];
+        }
+        let start = self.edge_geometry_offsets[edge_idx] as usize;
+        let end = self.edge_geometry_offsets[edge_idx + 1] as usize;
+        &self.edge_geometry[start..end]
+    }
+
+    pub fn find_edge(&self, from: u32, to_external_id: i64) -> Option<usize> {
+        let start = self.offsets[from as usize];
+        let end = self.offsets[(from as usize) + 1];
+        (start..end).find(|&idx| self.nodes[self.edges[idx] as usize].external_id == to_external_id)
+    }
+
+    pub fn is_turn_allowed(&self, from_external_id: Option<i64>, via: u32, to: u32) -> bool {
+        let Some(from_external_id) = from_external_id else {
+            return true;
+        };
+        if self.turn_rules.is_empty() {
+            return true;
+        }
+        let via_external_id = self.nodes[via as usize].external_id;
+        self.turn_rules
+            .get(&(from_external_id, via_external_id))
+            .is_none_or(|rule| rule.allows(self.nodes[to as usize].external_id))
+    }
+
+    pub fn turn_angle(&self, from_external_id: i64, via: u32, out_edge_idx: usize) -> f32 {
+        let via_node = &self.nodes[via as usize];
+        let via_point = [via_node.lon, via_node.lat];
+
+        let from_point = match self.node_id_map.get(&from_external_id) {
+            Some(&from) => self
+                .find_edge(from, via_node.external_id)
+                .and_then(|edge_idx| self.edge_geometry(edge_idx).last().copied())
+                .unwrap_or_else(|| {
+                    let node = &self.nodes[from as usize];
+                    [node.lon, node.lat]
+                }),
+            None => return 0.0,
+        };
+        let to_point = self
+            .edge_geometry(out_edge_idx)
+            .first()
+            .copied()
+            .unwrap_or_else(|| {
+                let node = &self.nodes[self.edges[out_edge_idx] as usize];
+                [node.lon, node.lat]
+            });
+
+        let lon_scale = via_point[1].to_radians().cos();
+        let incoming = [
+            (via_point[0] - from_point[0]) * lon_scale,
+            via_point[1] - from_point[1],
+        ];
+        let outgoing = [
+            (to_point[0] - via_point[0]) * lon_scale,
+            to_point[1] - via_point[1],
+        ];
+
+        let cross = incoming[0] * outgoing[1] - incoming[1] * outgoing[0];
+        let dot = incoming[0] * outgoing[0] + incoming[1] * outgoing[1];
+        if cross == 0.0 && dot == 0.0 {
+            return 0.0;
+        }
+        cross.atan2(dot).abs().to_degrees()
+    }
+
+    pub fn apply_edge_weights(&mut self, csv: &str) -> Result<usize> {
+        let way_indices: FxHashMap<i64, u32> = self
+            .ways
+            .iter()
+            .enumerate()
+            .map(|(idx, way)| (way.osm_id, idx as u32))
+            .collect();
+
+        let mut overlay = WeightOverlay {
+            multipliers: FxHashMap::default(),
+            min_multiplier: f32::INFINITY,
+        };
+        for (line_idx, line) in csv.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (way_field, speed_field) = line.split_once(',').ok_or_else(|| {
+                GraphError::InvalidEdgeWeights(format!(
+                    "line {}: expected 'wayId,speedKph'",
+                    line_idx + 1
+                ))
+            })?;
+            let way_id = match way_field.trim().parse::<i64>() {
+                Ok(way_id) => way_id,
+                Err(_) if line_idx == 0 => continue,
+                Err(_) => {
+                    return Err(GraphError::InvalidEdgeWeights(format!(
+                        "line {}: invalid way ID '{}'",
+                        line_idx + 1,
+                        way_field.trim()
+                    )))
+                }
+            };
+            let speed_kmh = speed_field
+                .trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|speed| speed.is_finite())
+                .ok_or_else(|| {
+                    GraphError::InvalidEdgeWeights(format!(
+                        "line {}: invalid speed '{}'",
+                        line_idx + 1,
+                        speed_field.trim()
+                    ))
+                })?;
+
+            let Some(&way_idx) = way_indices.get(&way_id) else {
+                continue;
+            };
+            let base_speed = self.way_speeds[way_idx as usize];
+            let multiplier = if speed_kmh > 0.0 {
+                base_speed / speed_kmh
+            } else {
+                f32::INFINITY
+            };
+            overlay.min_multiplier = overlay.min_multiplier.min(multiplier);
+            overlay.multipliers.insert(way_idx, multiplier);
+        }
+
+        let applied = overlay.multipliers.len();
+        self.weight_overlay = overlay;
+        Ok(applied)
+    }
+
+    pub fn simplified_shape(&self, node_ids: &[i64], epsilon: f32) -> Vec<[f32; 2]> {
+        let points: Vec<[f32; 2]> = self.shape(node_ids).into_iter().flatten().collect();
+        simplify_rdp(&points, epsilon)
+    }
+
+    pub fn restrictions_at_node(&self, external_id: i64) -> Vec<&RestrictionInfo> {
+        self.restrictions
+            .iter()
+            .filter(|restriction| restriction.via_nodes.contains(&external_id))
+            .collect()
+    }
+
+    pub fn touch_memory(&self) -> u64 {
+        let mut checksum: u64 = 0;
+        for &offset in self.offsets.iter() {
+            checksum = checksum.wrapping_add(offset as u64);
+        }
+        for (i, &target) in self.edges.iter().enumerate() {
+            checksum = checksum
+                .wrapping_add(target as u64)
+                .wrapping_add(self.edge_costs[i] as u64)
+                .wrapping_add(self.edge_times[i] as u64)
+                .wrapping_add(self.edge_ways[i] as u64);
+        }
+        for node in self.nodes.iter() {
+            checksum = checksum.wrapping_add(node.lat.to_bits() as u64);
         }
+        for &geometry_offset in self.edge_geometry_offsets.iter() {
+            checksum = checksum.wrapping_add(geometry_offset as u64);
+        }
+        for point in self.edge_geometry.iter() {
+            checksum = checksum.wrapping_add(point[0].to_bits() as u64);
+        }
+        checksum
     }
 
-    pub fn neighbors(&self, node_id: u32) -> &[(u32, u16)] {
-        let start = self.offsets[node_id as usize];
-        let end = self.offsets[(node_id as usize) + 1];
-        &self.edges[start..end]
+    pub fn validate_route(&self, node_ids: &[i64]) -> Option<usize> {
+        self.route_edges(node_ids).err()
+    }
+
+    pub fn route_advisories(&self, node_ids: &[i64]) -> Result<RouteAdvisories> {
+        let edges = self.route_edges(node_ids).map_err(unwalkable_route)?;
+        let mut advisories = RouteAdvisories::default();
+        for edge_idx in edges {
+            let Some(way) = self.ways.get(self.edge_ways[edge_idx] as usize) else {
+                continue;
+            };
+            advisories.ferry |= self.tag_value(&way.tags, "route") == Some("ferry");
+            advisories.toll |= self.tag_value(&way.tags, "toll") == Some("yes");
+            advisories.unpaved |= self
+                .tag_value(&way.tags, "surface")
+                .is_some_and(|surface| UNPAVED_SURFACES.contains(&surface));
+            advisories.private_access |= self.tag_value(&way.tags, "access") == Some("private");
+        }
+        Ok(advisories)
+    }
+
+    pub fn route_edges(&self, node_ids: &[i64]) -> std::result::Result<Vec<usize>, usize> {
+        let canonical = |id: &i64| *self.node_aliases.get(id).unwrap_or(id);
+        let Some(first) = node_ids.first() else {
+            return Ok(Vec::new());
+        };
+        let Some(&start) = self.node_id_map.get(first) else {
+            return Err(0);
+        };
+
+        let mut layers: Vec<Vec<WalkState>> = vec![vec![(start, None, 0, usize::MAX)]];
+        for (i, next_id) in node_ids.iter().enumerate().skip(1) {
+            let next_id = canonical(next_id);
+            let mut next_states: Vec<WalkState> = Vec::new();
+            for (parent, &(node_id, prev_external_id, _, _)) in layers[i - 1].iter().enumerate() {
+                for edge_idx in self.edge_range(node_id) {
+                    let target = self.edges[edge_idx];
+                    if self.nodes[target as usize].external_id != next_id
+                        || self.is_edge_closed(edge_idx)
+                        || !self.is_turn_allowed(prev_external_id, node_id, target)
+                    {
+                        continue;
+                    }
+                    let from_external_id = Some(self.nodes[node_id as usize].external_id);
+                    if !next_states
+                        .iter()
+                        .any(|&(n, prev, _, _)| n == target && prev == from_external_id)
+                    {
+                        next_states.push((target, from_external_id, parent, edge_idx));
+                    }
+                }
+            }
+            if next_states.is_empty() {
+                return Err(i);
+            }
+            layers.push(next_states);
+        }
+
+        let mut edges = Vec::with_capacity(node_ids.len().saturating_sub(1));
+        let mut state_idx = 0;
+        for layer in layers.iter().skip(1).rev() {
+            let (_, _, parent, edge_idx) = layer[state_idx];
+            edges.push(edge_idx);
+            state_idx = parent;
+        }
+        edges.reverse();
+        Ok(edges)
+    }
+
+    pub fn is_edge_lit(&self, edge_idx: usize) -> bool {
+        let Some((lit_key, yes_value)) = self.lit_tag else {
+            return false;
+        };
+        self.edge_ways
+            .get(edge_idx)
+            .and_then(|&way_idx| self.ways.get(way_idx as usize))
+            .is_some_and(|way| way.tags.get(&lit_key) == Some(&yes_value))
+    }
+
+    fn is_edge_closed(&self, edge_idx: usize) -> bool {
+        self.edge_ways
+            .get(edge_idx)
+            .and_then(|way_idx| self.weight_overlay.multipliers.get(way_idx))
+            .is_some_and(|multiplier| multiplier.is_infinite())
+    }
+
+    pub fn shape(&self, node_ids: &[i64]) -> Vec<Option<[f32; 2]>> {
+        let mut shape = Vec::with_capacity(node_ids.len());
+        for (i, node_id) in node_ids.iter().enumerate() {
+            let internal_id = self.node_id_map.get(node_id).copied();
+
+            if i > 0 && !self.edge_geometry.is_empty() {
+                if let (Some(&prev_id), Some(_)) =
+                    (self.node_id_map.get(&node_ids[i - 1]), internal_id)
+                {
+                    if let Some(edge_idx) = self.find_edge(prev_id, *node_id) {
+                        shape.extend(self.edge_geometry(edge_idx).iter().map(|p| Some(*p)));
+                    }
+                }
+            }
+
+            shape.push(internal_id.map(|id| {
+                let node = &self.nodes[id as usize];
+                [node.lon, node.lat]
+            }));
+        }
+        shape
     }
 
     pub fn build_indices(&mut self) {
-        self.node_id_map = self.nodes.iter().map(|n| (n.external_id, n.id)).collect();
+        let mut node_id_map: FxHashMap<i64, u32> =
+            self.nodes.iter().map(|n| (n.external_id, n.id)).collect();
+        for (&alias, target) in self.node_aliases.iter() {
+            if let Some(&internal_id) = node_id_map.get(target) {
+                node_id_map.insert(alias, internal_id);
+            }
+        }
+        self.node_id_map = Arc::new(node_id_map);
+
+        let string_id = |value: &str| {
+            self.string_interner
+                .iter()
+                .position(|s| s == value)
+                .map(|id| id as u32)
+        };
+        self.lit_tag = string_id("lit").zip(string_id("yes"));
+
+        let mut in_degrees = vec![0u32; self.nodes.len()];
+        for &target in self.edges.iter() {
+            in_degrees[target as usize] += 1;
+        }
+        self.in_degrees = Arc::new(in_degrees);
 
         let spatial_ways: Vec<SpatialWay> = self
             .ways
@@ -93,19 +573,50 @@ impl ProcessedGraph {
             })
             .collect();
 
-        self.spatial_index = RTree::bulk_load(spatial_ways);
+        self.spatial_index = Arc::new(RTree::bulk_load(spatial_ways));
+    }
+
+    fn share_topology_with(&mut self, other: &ProcessedGraph) {
+        for_each_topology_field!(self, other, share);
+
+        if Arc::ptr_eq(&self.nodes, &other.nodes) {
+            if Arc::ptr_eq(&self.node_aliases, &other.node_aliases) {
+                self.node_id_map = Arc::clone(&other.node_id_map);
+            }
+            if Arc::ptr_eq(&self.ways, &other.ways) {
+                self.spatial_index = Arc::clone(&other.spatial_index);
+            }
+            if Arc::ptr_eq(&self.edges, &other.edges) {
+                self.in_degrees = Arc::clone(&other.in_degrees);
+            }
+        }
+    }
+
+    fn can_snap(&self, node_id: u32, direction: SnapDirection) -> bool {
+        match direction {
+            SnapDirection::Any => true,
+            SnapDirection::Depart => !self.edge_range(node_id).is_empty(),
+            SnapDirection::Arrive => self
+                .in_degrees
+                .get(node_id as usize)
+                .is_some_and(|&d| d > 0),
+        }
     }
 
     fn find_nearest_point_on_way(
         &self,
         way_info: &WayInfo,
         query_point: [f32; 2],
+        direction: SnapDirection,
     ) -> Option<(i64, f32)> {
         if way_info.node_ids.is_empty() {
             return None;
         }
 
         if way_info.node_ids.len() == 1 {
+            if !self.can_snap(way_info.node_ids[0], direction) {
+                return None;
+            }
             let node = &self.nodes[way_info.node_ids[0] as usize];
             let node_point = [node.lon, node.lat];
             let distance_sq = squared_distance(&query_point, &node_point);
@@ -116,6 +627,12 @@ impl ProcessedGraph {
         let mut nearest_node_external_id = None;
 
         for window in way_info.node_ids.windows(2) {
+            let snap1 = self.can_snap(window[0], direction);
+            let snap2 = self.can_snap(window[1], direction);
+            if !snap1 && !snap2 {
+                continue;
+            }
+
             let node1 = &self.nodes[window[0] as usize];
             let node2 = &self.nodes[window[1] as usize];
 
@@ -130,11 +647,13 @@ impl ProcessedGraph {
                 let dist_to_node1_sq = squared_distance(&query_point, &p1);
                 let dist_to_node2_sq = squared_distance(&query_point, &p2);
 
-                nearest_node_external_id = Some(if dist_to_node1_sq <= dist_to_node2_sq {
-                    node1.external_id
-                } else {
-                    node2.external_id
-                });
+                nearest_node_external_id = Some(
+                    if snap1 && (!snap2 || dist_to_node1_sq <= dist_to_node2_sq) {
+                        node1.external_id
+                    } else {
+                        node2.external_id
+                    },
+                );
             }
         }
 
@@ -142,6 +661,156 @@ impl ProcessedGraph {
     }
 
     pub fn find_nearest_node(&self, lon: f32, lat: f32) -> Result<i64> {
+        self.find_nearest_node_for(lon, lat, SnapDirection::Any)
+    }
+
+    pub fn find_nearest_node_for(
+        &self,
+        lon: f32,
+        lat: f32,
+        direction: SnapDirection,
+    ) -> Result<i64> {
+        self.check_coordinates(lon, lat)?;
+        let mut candidate = self.find_nearest_candidate(lon, lat, direction)?;
+        if candidate.is_none() && direction != SnapDirection::Any {
+            candidate = self.find_nearest_candidate(lon, lat, SnapDirection::Any)?;
+        }
+        candidate
+            .map(|(_, node_external_id)| node_external_id)
+            .ok_or_else(|| GraphError::RoutingError("No nodes found near coordinates".into()))
+    }
+
+    pub fn representative_node(&self) -> Option<i64> {
+        let bounds = self.bounds()?;
+        let center = bounds.center();
+
+        let mut best: Option<(i64, f32)> = None;
+        for spatial_way in self.spatial_index.nearest_neighbor_iter(&center) {
+            if best.is_some_and(|(_, best_sq)| spatial_way.distance_2(&center) > best_sq) {
+                break;
+            }
+
+            for &node_id in &self.ways[spatial_way.way_idx].node_ids {
+                if self.edge_range(node_id).is_empty() {
+                    continue;
+                }
+                let node = &self.nodes[node_id as usize];
+                let distance_sq = squared_distance(&center, &[node.lon, node.lat]);
+                if best.is_none_or(|(_, best_sq)| distance_sq < best_sq) {
+                    best = Some((node.external_id, distance_sq));
+                }
+            }
+        }
+        best.map(|(external_id, _)| external_id)
+    }
+
+    pub fn find_nearest_way(&self, lon: f32, lat: f32) -> Result<Option<&WayInfo>> {
+        self.check_coordinates(lon, lat)?;
+        Ok(self
+            .find_nearest_candidate(lon, lat, SnapDirection::Any)?
+            .map(|(way_idx, _)| &self.ways[way_idx]))
+    }
+
+    pub fn find_nearest_way_matching(
+        &self,
+        lon: f32,
+        lat: f32,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<Option<(&WayInfo, f32)>> {
+        self.check_coordinates(lon, lat)?;
+        if self.spatial_index.size() == 0 {
+            return Err(GraphError::EmptyGraph(self.profile_id.clone()));
+        }
+
+        let string_id = |s: &str| {
+            self.string_interner
+                .iter()
+                .position(|interned| interned == s)
+                .map(|id| id as u32)
+        };
+        let Some(key_id) = string_id(key) else {
+            return Ok(None);
+        };
+        let value_id = match value {
+            Some(value) => match string_id(value) {
+                Some(id) => Some(id),
+                None => return Ok(None),
+            },
+            None => None,
+        };
+
+        let query_point = [lon, lat];
+        let mut best: Option<(usize, [f32; 2], f32)> = None;
+        for spatial_way in self
+            .spatial_index
+            .nearest_neighbor_iter(&query_point)
+            .take(MAX_MATCHING_WAY_CANDIDATES)
+        {
+            if best.is_some_and(|(_, _, best_sq)| spatial_way.distance_2(&query_point) > best_sq) {
+                break;
+            }
+
+            let way_info = &self.ways[spatial_way.way_idx];
+            let matches = match (way_info.tags.get(&key_id), value_id) {
+                (Some(actual), Some(expected)) => *actual == expected,
+                (Some(_), None) => true,
+                (None, _) => false,
+            };
+            if !matches {
+                continue;
+            }
+
+            if let Some((point, distance_sq)) = self.closest_point_on_way(way_info, query_point) {
+                if best.is_none_or(|(_, _, best_sq)| distance_sq < best_sq) {
+                    best = Some((spatial_way.way_idx, point, distance_sq));
+                }
+            }
+        }
+
+        Ok(best.map(|(way_idx, point, _)| {
+            let distance_meters =
+                distance(lat, lon, point[1], point[0], self.earth_radius_km) * 1000.0;
+            (&self.ways[way_idx], distance_meters)
+        }))
+    }
+
+    fn closest_point_on_way(
+        &self,
+        way_info: &WayInfo,
+        query_point: [f32; 2],
+    ) -> Option<([f32; 2], f32)> {
+        let points: Vec<[f32; 2]> = way_info
+            .node_ids
+            .iter()
+            .map(|&node_id| {
+                let node = &self.nodes[node_id as usize];
+                [node.lon, node.lat]
+            })
+            .collect();
+        if let [point] = points.as_slice() {
+            return Some((*point, squared_distance(&query_point, point)));
+        }
+
+        points
+            .windows(2)
+            .map(|pair| {
+                let point = closest_point_on_segment(&query_point, &pair[0], &pair[1]);
+                (point, squared_distance(&query_point, &point))
+            })
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+    }
+
+    fn find_nearest_candidate(
+        &self,
+        lon: f32,
+        lat: f32,
+        direction: SnapDirection,
+    ) -> Result<Option<(usize, i64)>> {
+        if self.spatial_index.size() == 0 {
+            return Err(GraphError::EmptyGraph(self.profile_id.clone()));
+        }
+
         let query_point = [lon, lat];
         let mut search_radius_deg = 0.001;
         for _ in 0..5 {
@@ -156,23 +825,177 @@ impl ProcessedGraph {
             let closest_candidate = candidate_ways
                 .filter_map(|spatial_way| {
                     let way_info = &self.ways[spatial_way.way_idx];
-                    self.find_nearest_point_on_way(way_info, query_point)
+                    self.find_nearest_point_on_way(way_info, query_point, direction)
+                        .map(|(node_external_id, distance_sq)| {
+                            (spatial_way.way_idx, node_external_id, distance_sq)
+                        })
                 })
-                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+                .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(Ordering::Equal));
 
-            if let Some((node_external_id, _distance_sq)) = closest_candidate {
-                return Ok(node_external_id);
+            if let Some((way_idx, node_external_id, _distance_sq)) = closest_candidate {
+                return Ok(Some((way_idx, node_external_id)));
             }
 
             search_radius_deg *= 2.0;
         }
-        Err(GraphError::RoutingError(
-            "No nodes found near coordinates".into(),
-        ))
+        Ok(None)
+    }
+
+    pub fn way_geometry(&self, way_info: &WayInfo) -> Vec<[f32; 2]> {
+        let mut geometry = Vec::with_capacity(way_info.node_ids.len());
+        for (i, &node_id) in way_info.node_ids.iter().enumerate() {
+            let node = &self.nodes[node_id as usize];
+            if i > 0 && !self.edge_geometry.is_empty() {
+                let prev_id = way_info.node_ids[i - 1];
+                let prev_external_id = self.nodes[prev_id as usize].external_id;
+                if let Some(edge_idx) = self.find_edge(prev_id, node.external_id) {
+                    geometry.extend_from_slice(self.edge_geometry(edge_idx));
+                } else if let Some(edge_idx) = self.find_edge(node_id, prev_external_id) {
+                    geometry.extend(self.edge_geometry(edge_idx).iter().rev());
+                }
+            }
+            geometry.push([node.lon, node.lat]);
+        }
+        geometry
+    }
+
+    pub fn tag_value(&self, tags: &FxHashMap<u32, u32>, key: &str) -> Option<&str> {
+        tags.iter()
+            .find(|(key_id, _)| self.string_interner[**key_id as usize] == key)
+            .map(|(_, val_id)| self.string_interner[*val_id as usize].as_str())
+    }
+
+    /// Indexes node tags under `keys`. A cached index built for the same keys
+    /// is kept as is; returns whether the index had to be rebuilt.
+    pub fn ensure_tag_index(&mut self, keys: &[String]) -> bool {
+        if self.tag_index.keys == keys {
+            return false;
+        }
+        let mut index = TagIndex {
+            keys: keys.to_vec(),
+            nodes: FxHashMap::default(),
+        };
+        for node in self.nodes.iter() {
+            for key in keys {
+                if let Some(value) = self.tag_value(&node.tags, key) {
+                    index
+                        .nodes
+                        .entry(key.clone())
+                        .or_default()
+                        .entry(value.to_string())
+                        .or_default()
+                        .push(node.external_id);
+                }
+            }
+        }
+        for ids in index
+            .nodes
+            .values_mut()
+            .flat_map(|values| values.values_mut())
+        {
+            ids.sort_unstable();
+            ids.dedup();
+        }
+        self.tag_index = index;
+        true
+    }
+
+    pub fn nodes_by_tag(&self, key: &str, value: Option<&str>) -> Result<Vec<i64>> {
+        if !self.tag_index.keys.iter().any(|indexed| indexed == key) {
+            return Err(GraphError::TagNotIndexed(key.to_string()));
+        }
+        let Some(values) = self.tag_index.nodes.get(key) else {
+            return Ok(Vec::new());
+        };
+        Ok(match value {
+            Some(value) => values.get(value).cloned().unwrap_or_default(),
+            None => {
+                let mut ids: Vec<i64> = values.values().flatten().copied().collect();
+                ids.sort_unstable();
+                ids.dedup();
+                ids
+            }
+        })
+    }
+
+    pub fn node_elevation(&self, external_id: i64) -> Option<f32> {
+        let node = &self.nodes[*self.node_id_map.get(&external_id)? as usize];
+        parse_length_m(self.tag_value(&node.tags, "ele")?)
+    }
+
+    pub fn bounds(&self) -> Option<AABB<[f32; 2]>> {
+        if self.spatial_index.size() == 0 {
+            return None;
+        }
+        Some(self.spatial_index.root().envelope())
+    }
+
+    pub fn check_coordinates(&self, lon: f32, lat: f32) -> Result<()> {
+        let in_range =
+            |lon: f32, lat: f32| (-180.0..=180.0).contains(&lon) && (-90.0..=90.0).contains(&lat);
+        if !in_range(lon, lat) {
+            let hint = if in_range(lat, lon) {
+                "; longitude and latitude look swapped"
+            } else {
+                ""
+            };
+            return Err(GraphError::InvalidCoordinates(format!(
+                "[{}, {}] is outside longitude [-180, 180] / latitude [-90, 90]{}",
+                lon, lat, hint
+            )));
+        }
+
+        let Some(bounds) = self.bounds() else {
+            return Ok(());
+        };
+        let nearest = bounds.min_point(&[lon, lat]);
+        let outside_km = distance(lat, lon, nearest[1], nearest[0], self.earth_radius_km);
+        if outside_km <= 0.0 {
+            return Ok(());
+        }
+
+        let swapped = bounds.contains_point(&[lat, lon]);
+        if self
+            .max_outside_bounds_km
+            .is_some_and(|max_km| outside_km > max_km)
+        {
+            return Err(GraphError::InvalidCoordinates(format!(
+                "[{}, {}] is {:.1} km outside the graph of profile '{}'{}",
+                lon,
+                lat,
+                outside_km,
+                self.profile_id,
+                if swapped {
+                    "; longitude and latitude look swapped"
+                } else {
+                    ""
+                }
+            )));
+        }
+        if swapped {
+            log::warn!(
+                "[{}, {}] is {:.1} km outside the graph of profile '{}', but inside it with longitude and latitude swapped",
+                lon,
+                lat,
+                outside_km,
+                self.profile_id
+            );
+        }
+        Ok(())
+    }
+
+    pub fn find_ways_within_radius(
+        &self,
+        lon: f32,
+        lat: f32,
+        radius_meters: f32,
+    ) -> Result<Vec<&WayInfo>> {
+        self.check_coordinates(lon, lat)?;
+        Ok(self.ways_within_radius(lon, lat, radius_meters))
     }
 
-    pub fn find_ways_within_radius(&self, lon: f32, lat: f32, radius_meters: f32) -> Vec<&WayInfo> {
-        let radius_degrees = radius_meters / 111_100.0;
+    fn ways_within_radius(&self, lon: f32, lat: f32, radius_meters: f32) -> Vec<&WayInfo> {
+        let radius_degrees = radius_meters / meters_per_degree(self.earth_radius_km);
         let min_p = [lon - radius_degrees, lat - radius_degrees];
         let max_p = [lon + radius_degrees, lat + radius_degrees];
         let search_aabb = AABB::from_corners(min_p, max_p);
@@ -195,36 +1018,70 @@ impl ProcessedGraph {
         lon: f32,
         lat: f32,
         radius_meters: f32,
-    ) -> Vec<&RouteNode> {
-        let ways = self.find_ways_within_radius(lon, lat, radius_meters);
+    ) -> Result<Vec<&RouteNode>> {
+        self.check_coordinates(lon, lat)?;
+        let ways = self.ways_within_radius(lon, lat, radius_meters);
         let mut node_ids = rustc_hash::FxHashSet::default();
         for way in ways {
             node_ids.extend(way.node_ids.iter().copied());
         }
 
-        let radius_degrees = radius_meters / 111_100.0;
+        let radius_degrees = radius_meters / meters_per_degree(self.earth_radius_km);
         let radius_degrees_sq = radius_degrees * radius_degrees;
 
-        node_ids
+        Ok(node_ids
             .iter()
             .map(|&id| &self.nodes[id as usize])
             .filter(|node| {
                 let dist_sq = (node.lon - lon).powi(2) + (node.lat - lat).powi(2);
                 dist_sq <= radius_degrees_sq
             })
-            .collect()
+            .collect())
     }
+
+    pub fn node_density(&self, precision: usize) -> FxHashMap<String, u32> {
+        let mut density: FxHashMap<String, u32> = FxHashMap::default();
+        for node in self.nodes.iter() {
+            if self.node_id_map.get(&node.external_id) != Some(&node.id) {
+                continue;
+            }
+            *density
+                .entry(geohash_encode(node.lat, node.lon, precision))
+                .or_insert(0) += 1;
+        }
+        density
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GraphSource {
+    Overpass { server: String },
+    Protobuf { url: String },
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct GraphContainer {
     pub profiles: FxHashMap<String, ProcessedGraph>,
+    pub built_at: SystemTime,
+    pub source: Option<GraphSource>,
+
+    #[serde(skip)]
+    pub from_cache: bool,
+    #[serde(skip)]
+    pub cache_path: String,
+    #[serde(skip)]
+    pub ttl_days: u64,
 }
 
 impl GraphContainer {
     pub fn new() -> Self {
         GraphContainer {
             profiles: FxHashMap::default(),
+            built_at: SystemTime::now(),
+            source: None,
+            from_cache: false,
+            cache_path: String::new(),
+            ttl_days: 0,
         }
     }
 
@@ -233,6 +1090,170 @@ impl GraphContainer {
             graph.build_indices();
         }
     }
+
+    pub fn apply_load_options(&mut self, options: &LoadOptions) {
+        self.cache_path = options.file_path.clone();
+        self.ttl_days = options.ttl_days;
+        for graph in self.profiles.values_mut() {
+            graph.max_outside_bounds_km = options.max_outside_bounds_km.map(|km| km as f32);
+        }
+    }
+
+    /// Returns the number of profiles whose tag index had to be rebuilt.
+    pub fn index_tags(&mut self, keys: &[String]) -> usize {
+        self.profiles
+            .values_mut()
+            .map(|graph| graph.ensure_tag_index(keys))
+            .filter(|&rebuilt| rebuilt)
+            .count()
+    }
+
+    pub fn share_interners(&mut self) {
+        let mut graphs: Vec<&mut ProcessedGraph> = self.profiles.values_mut().collect();
+        graphs.sort_by(|a, b| a.profile_id.cmp(&b.profile_id));
+
+        for i in 1..graphs.len() {
+            let (earlier, rest) = graphs.split_at_mut(i);
+            let graph = &mut rest[0];
+            if let Some(other) = earlier
+                .iter()
+                .find(|other| other.string_interner == graph.string_interner)
+            {
+                graph.string_interner = Arc::clone(&other.string_interner);
+            }
+        }
+    }
+
+    pub fn share_topology(&mut self) {
+        let mut graphs: Vec<ProcessedGraph> =
+            std::mem::take(&mut self.profiles).into_values().collect();
+        graphs.sort_by(|a, b| a.profile_id.cmp(&b.profile_id));
+        for graph in graphs {
+            self.insert_sharing_topology(graph);
+        }
+    }
+
+    /// Adds a profile graph, first pointing any topology it has in common
+    /// with the graphs already held at their copy so its own is freed.
+    pub fn insert_sharing_topology(&mut self, mut graph: ProcessedGraph) {
+        let mut others: Vec<&ProcessedGraph> = self.profiles.values().collect();
+        others.sort_by(|a, b| a.profile_id.cmp(&b.profile_id));
+        for other in others {
+            graph.share_topology_with(other);
+            if Arc::ptr_eq(&graph.edges, &other.edges) {
+                graph.build_report.shared_topology_with = Some(other.profile_id.clone());
+                break;
+            }
+        }
+        self.profiles.insert(graph.profile_id.clone(), graph);
+    }
+
+    /// Serializes the container, writing topology shared between profiles
+    /// only once.
+    pub fn write_cache(&mut self, writer: impl std::io::Write) -> Result<()> {
+        self.detach_shared_topology();
+        let written = bincode::serialize_into(writer, &*self);
+        self.attach_shared_topology()?;
+        Ok(written?)
+    }
+
+    fn detach_shared_topology(&mut self) {
+        let mut graphs: Vec<&mut ProcessedGraph> = self.profiles.values_mut().collect();
+        graphs.sort_by(|a, b| a.profile_id.cmp(&b.profile_id));
+
+        for i in 1..graphs.len() {
+            let (earlier, rest) = graphs.split_at_mut(i);
+            let graph = &mut *rest[0];
+            for other in earlier.iter() {
+                for_each_topology_field!(
+                    graph,
+                    other,
+                    detach,
+                    &other.profile_id,
+                    &mut graph.shared_fields
+                );
+            }
+        }
+    }
+
+    /// Restores the topology fields `write_cache` left out, pointing them at
+    /// the profile that holds the data.
+    pub fn attach_shared_topology(&mut self) -> Result<()> {
+        let mut graphs: Vec<&mut ProcessedGraph> = self.profiles.values_mut().collect();
+        graphs.sort_by(|a, b| a.profile_id.cmp(&b.profile_id));
+
+        for i in 1..graphs.len() {
+            let (earlier, rest) = graphs.split_at_mut(i);
+            let graph = &mut *rest[0];
+            for (field, owner) in std::mem::take(&mut graph.shared_fields) {
+                let other = earlier
+                    .iter()
+                    .find(|other| other.profile_id == owner)
+                    .ok_or_else(|| GraphError::ProfileNotFound(owner.clone()))?;
+                for_each_topology_field!(graph, other, attach, &field);
+            }
+        }
+        match self
+            .profiles
+            .values()
+            .find(|graph| !graph.shared_fields.is_empty())
+        {
+            Some(graph) => Err(GraphError::ProfileNotFound(
+                graph.shared_fields[0].1.clone(),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn unwalkable_route(index: usize) -> GraphError {
+    GraphError::RoutingError(format!(
+        "route cannot be followed through the graph at node index {}",
+        index
+    ))
+}
+
+fn share<T: PartialEq>(_field: &str, mine: &mut Arc<T>, theirs: &Arc<T>) {
+    if !Arc::ptr_eq(mine, theirs) && **mine == **theirs {
+        *mine = Arc::clone(theirs);
+    }
+}
+
+fn detach<T: Default>(
+    field: &str,
+    mine: &mut Arc<T>,
+    theirs: &Arc<T>,
+    owner: &str,
+    shared_fields: &mut Vec<(String, String)>,
+) {
+    if Arc::ptr_eq(mine, theirs) {
+        *mine = Arc::default();
+        shared_fields.push((field.to_string(), owner.to_string()));
+    }
+}
+
+fn attach<T>(field: &str, mine: &mut Arc<T>, theirs: &Arc<T>, wanted: &str) {
+    if field == wanted {
+        *mine = Arc::clone(theirs);
+    }
+}
+
+pub fn parse_length_m(value: &str) -> Option<f32> {
+    let value = value.trim().replace(',', ".");
+    let value = value.as_str();
+    let (number, scale) = if let Some(feet) = value.strip_suffix("ft") {
+        (feet, 0.3048)
+    } else if let Some(feet) = value.strip_suffix('\'') {
+        (feet, 0.3048)
+    } else {
+        (value.strip_suffix('m').unwrap_or(value), 1.0)
+    };
+    number
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|elevation| elevation.is_finite())
+        .map(|elevation| elevation * scale)
 }
 
 pub fn squared_distance(p1: &[f32; 2], p2: &[f32; 2]) -> f32 {
@@ -240,11 +1261,15 @@ pub fn squared_distance(p1: &[f32; 2], p2: &[f32; 2]) -> f32 {
 }
 
 pub fn point_to_segment_distance(p: &[f32; 2], a: &[f32; 2], b: &[f32; 2]) -> f32 {
+    squared_distance(p, &closest_point_on_segment(p, a, b))
+}
+
+pub fn closest_point_on_segment(p: &[f32; 2], a: &[f32; 2], b: &[f32; 2]) -> [f32; 2] {
     let ab_x = b[0] - a[0];
     let ab_y = b[1] - a[1];
 
     if ab_x.abs() < 1e-9 && ab_y.abs() < 1e-9 {
-        return squared_distance(p, a);
+        return *a;
     }
 
     let ap_x = p[0] - a[0];
@@ -252,10 +1277,542 @@ pub fn point_to_segment_distance(p: &[f32; 2], a: &[f32; 2], b: &[f32; 2]) -> f3
 
     let t = (ap_x * ab_x + ap_y * ab_y) / (ab_x * ab_x + ab_y * ab_y);
 
-    let t_clamped = t.max(0.0).min(1.0);
+    let t_clamped = t.clamp(0.0, 1.0);
+
+    [a[0] + t_clamped * ab_x, a[1] + t_clamped * ab_y]
+}
+
+pub fn simplify_rdp(points: &[[f32; 2]], epsilon: f32) -> Vec<[f32; 2]> {
+    if points.len() <= 2 || epsilon <= 0.0 {
+        return points.to_vec();
+    }
 
-    let closest_x = a[0] + t_clamped * ab_x;
-    let closest_y = a[1] + t_clamped * ab_y;
+    let epsilon_squared = epsilon * epsilon;
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
 
-    (p[0] - closest_x).powi(2) + (p[1] - closest_y).powi(2)
+    let mut stack = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = stack.pop() {
+        let mut max_distance = 0.0;
+        let mut max_index = first;
+        for i in first + 1..last {
+            let distance = point_to_segment_distance(&points[i], &points[first], &points[last]);
+            if distance > max_distance {
+                max_distance = distance;
+                max_index = i;
+            }
+        }
+
+        if max_distance > epsilon_squared {
+            keep[max_index] = true;
+            stack.push((first, max_index));
+            stack.push((max_index, last));
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(point, kept)| kept.then_some(*point))
+        .collect()
+}
+
+pub fn point_in_polygon(p: &[f32; 2], ring: &[[f32; 2]]) -> bool {
+    let mut inside = false;
+    let mut j = ring.len().wrapping_sub(1);
+    for i in 0..ring.len() {
+        let (a, b) = (ring[i], ring[j]);
+        if (a[1] > p[1]) != (b[1] > p[1])
+            && p[0] < (b[0] - a[0]) * (p[1] - a[1]) / (b[1] - a[1]) + a[0]
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+const GEOHASH_ALPHABET: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+pub fn geohash_encode(lat: f32, lon: f32, precision: usize) -> String {
+    let mut lat_range = (-90.0f64, 90.0f64);
+    let mut lon_range = (-180.0f64, 180.0f64);
+    let (lat, lon) = (lat as f64, lon as f64);
+
+    let mut hash = String::with_capacity(precision);
+    let mut is_lon_bit = true;
+    let mut bit = 0;
+    let mut char_idx = 0usize;
+
+    while hash.len() < precision {
+        let range = if is_lon_bit {
+            &mut lon_range
+        } else {
+            &mut lat_range
+        };
+        let value = if is_lon_bit { lon } else { lat };
+        let mid = (range.0 + range.1) / 2.0;
+
+        char_idx <<= 1;
+        if value >= mid {
+            char_idx |= 1;
+            range.0 = mid;
+        } else {
+            range.1 = mid;
+        }
+
+        is_lon_bit = !is_lon_bit;
+        bit += 1;
+        if bit == 5 {
+            hash.push(GEOHASH_ALPHABET[char_idx] as char);
+            bit = 0;
+            char_idx = 0;
+        }
+    }
+
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GraphContainer, ProcessedGraph, RouteNode, SnapDirection};
+    use crate::core::errors::GraphError;
+    use crate::core::types::Profile;
+    use crate::parser::parse_osm_xml;
+    use crate::processing::GraphBuilder;
+    use crate::routing::algorithm::{find_route_through_waypoints, Metric, RouteOptions};
+    use crate::test_support::{build_graph, node, options, osm, profile, relation, way};
+    use rustc_hash::FxHashSet;
+    use std::mem::size_of;
+    use std::sync::Arc;
+
+    #[test]
+    fn reports_restriction_at_its_via_node() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.02, 0.0),
+            node(4, 0.01, 0.01),
+            way(10, &[1, 2], &[("highway", "residential")]),
+            way(11, &[2, 3], &[("highway", "residential")]),
+            way(12, &[2, 4], &[("highway", "residential")]),
+            relation(
+                20,
+                &[("way", 10, "from"), ("node", 2, "via"), ("way", 12, "to")],
+                &[("type", "restriction"), ("restriction", "no_left_turn")],
+            ),
+        ]);
+        let graph = build_graph(&xml, "{}", "{}");
+
+        let restrictions = graph.restrictions_at_node(2);
+        assert_eq!(restrictions.len(), 1);
+        let restriction = restrictions[0];
+        assert_eq!(restriction.relation_id, 20);
+        assert_eq!(
+            graph.string_interner[restriction.kind as usize],
+            "no_left_turn"
+        );
+        assert_eq!(restriction.from_way, 10);
+        assert!(restriction.via_ways.is_empty());
+        assert_eq!(restriction.via_nodes, vec![2]);
+        assert_eq!(restriction.to_way, 12);
+
+        assert!(graph.restrictions_at_node(1).is_empty());
+        assert!(graph.restrictions_at_node(4).is_empty());
+    }
+
+    #[test]
+    fn edge_weights_change_the_optimal_route() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.02, 0.0),
+            node(3, 0.01, 0.005),
+            way(10, &[1, 2], &[("highway", "residential")]),
+            way(11, &[1, 3, 2], &[("highway", "residential")]),
+        ]);
+        let mut graph = build_graph(&xml, "{}", "{}");
+        let options = RouteOptions {
+            metric: Metric::Time,
+            ..Default::default()
+        };
+        let route = |graph: &ProcessedGraph| {
+            find_route_through_waypoints(graph, &[1, 2], &options)
+                .unwrap()
+                .found()
+                .unwrap()
+                .nodes
+        };
+        assert_eq!(route(&graph), vec![1, 2]);
+
+        let applied = graph
+            .apply_edge_weights("wayId,speedKph\n10,5\n99,5\n")
+            .unwrap();
+        assert_eq!(applied, 1);
+        assert_eq!(route(&graph), vec![1, 3, 2]);
+
+        assert!(graph.apply_edge_weights("10,fast").is_err());
+    }
+
+    #[test]
+    fn profiles_differing_in_penalties_share_topology() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.01, 0.01),
+            way(10, &[1, 2, 3], &[("highway", "residential")]),
+            way(11, &[3, 1], &[("highway", "residential")]),
+        ]);
+        let (nodes, ways, relations) = parse_osm_xml(&xml).unwrap();
+        let profiles = [
+            profile(r#"{"id":"a","penalties":{"residential":1}}"#),
+            profile(r#"{"id":"b","penalties":{"residential":3}}"#),
+        ];
+        let options = options(r#"{"share_topology":true}"#, profiles.to_vec());
+        let mut container = GraphContainer::new();
+        for profile in &profiles {
+            let graph = GraphBuilder::new(profile, &options, &nodes, &ways, &relations)
+                .build()
+                .unwrap();
+            container.profiles.insert(profile.id.clone(), graph);
+        }
+        container.share_topology();
+
+        let (a, b) = (&container.profiles["a"], &container.profiles["b"]);
+        assert!(Arc::ptr_eq(&a.nodes, &b.nodes));
+        assert!(Arc::ptr_eq(&a.edges, &b.edges));
+        assert!(Arc::ptr_eq(&a.node_id_map, &b.node_id_map));
+        assert_eq!(b.build_report.shared_topology_with.as_deref(), Some("a"));
+        assert!(a.build_report.shared_topology_with.is_none());
+        assert_ne!(a.edge_costs, b.edge_costs);
+
+        let route = |graph| {
+            find_route_through_waypoints(graph, &[1, 3], &RouteOptions::default())
+                .unwrap()
+                .found()
+                .unwrap()
+        };
+        let (route_a, route_b) = (route(a), route(b));
+        assert_eq!(route_a.nodes, route_b.nodes);
+        assert!(route_b.cost.abs_diff(route_a.cost * 3) <= 3);
+    }
+
+    #[test]
+    fn validates_stored_node_sequences() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.02, 0.0),
+            node(4, 0.01, 0.01),
+            way(10, &[1, 2], &[("highway", "residential")]),
+            way(11, &[2, 3], &[("highway", "residential")]),
+            way(
+                12,
+                &[2, 4],
+                &[("highway", "residential"), ("oneway", "yes")],
+            ),
+            relation(
+                20,
+                &[("way", 10, "from"), ("node", 2, "via"), ("way", 11, "to")],
+                &[("type", "restriction"), ("restriction", "no_straight_on")],
+            ),
+        ]);
+        let mut graph = build_graph(&xml, r#"{"edge_based":true}"#, "{}");
+
+        assert_eq!(graph.validate_route(&[1, 2, 4]), None);
+        assert_eq!(graph.validate_route(&[3, 2, 1]), None);
+        assert_eq!(graph.validate_route(&[99, 2]), Some(0));
+        assert_eq!(graph.validate_route(&[1, 3]), Some(1));
+        assert_eq!(graph.validate_route(&[4, 2]), Some(1));
+        assert_eq!(graph.validate_route(&[1, 2, 3]), Some(2));
+
+        graph.apply_edge_weights("12,0").unwrap();
+        assert_eq!(graph.validate_route(&[1, 2, 4]), Some(2));
+    }
+
+    #[test]
+    fn nearest_matching_way_skips_closer_footway() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0002),
+            node(2, 0.01, 0.0002),
+            node(3, 0.0, 0.001),
+            node(4, 0.01, 0.001),
+            way(10, &[1, 2], &[("highway", "footway")]),
+            way(11, &[3, 4], &[("highway", "residential")]),
+        ]);
+        let graph = build_graph(&xml, "{}", "{}");
+
+        let (road, road_m) = graph
+            .find_nearest_way_matching(0.005, 0.0, "highway", Some("residential"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(road.osm_id, 11);
+        assert!((road_m - 111.2).abs() < 1.0);
+
+        let (nearest, nearest_m) = graph
+            .find_nearest_way_matching(0.005, 0.0, "highway", None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(nearest.osm_id, 10);
+        assert!((nearest_m - 22.2).abs() < 1.0);
+
+        assert!(graph
+            .find_nearest_way_matching(0.005, 0.0, "highway", Some("motorway"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn warns_about_route_crossing_a_ferry() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.05, 0.0),
+            node(4, 0.06, 0.0),
+            way(10, &[1, 2], &[("highway", "residential")]),
+            way(11, &[2, 3], &[("route", "ferry"), ("duration", "00:20")]),
+            way(
+                12,
+                &[3, 4],
+                &[
+                    ("highway", "residential"),
+                    ("toll", "yes"),
+                    ("surface", "gravel"),
+                ],
+            ),
+        ]);
+        let graph = build_graph(&xml, "{}", r#"{"penalties":{"default":1}}"#);
+        let route = find_route_through_waypoints(&graph, &[1, 4], &RouteOptions::default())
+            .unwrap()
+            .found()
+            .unwrap();
+        assert_eq!(route.nodes, vec![1, 2, 3, 4]);
+
+        let crossing = graph.route_advisories(&route.nodes).unwrap();
+        assert!(crossing.ferry && crossing.toll && crossing.unpaved);
+        assert!(!crossing.private_access);
+
+        let road = graph.route_advisories(&[1, 2]).unwrap();
+        assert!(!road.ferry && !road.toll && !road.unpaved && !road.private_access);
+    }
+
+    #[test]
+    fn direction_hint_snaps_onto_usable_end_of_oneway() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.02, 0.0),
+            way(
+                10,
+                &[1, 2, 3],
+                &[("highway", "residential"), ("oneway", "yes")],
+            ),
+        ]);
+        let graph = build_graph(&xml, "{}", "{}");
+        let snap = |lon, direction| graph.find_nearest_node_for(lon, 0.0001, direction).unwrap();
+
+        assert_eq!(snap(0.019, SnapDirection::Any), 3);
+        assert_eq!(snap(0.019, SnapDirection::Depart), 2);
+        assert_eq!(snap(0.019, SnapDirection::Arrive), 3);
+        assert_eq!(snap(0.001, SnapDirection::Any), 1);
+        assert_eq!(snap(0.001, SnapDirection::Arrive), 2);
+        assert_eq!(snap(0.001, SnapDirection::Depart), 1);
+    }
+
+    #[test]
+    fn rejects_out_of_range_and_swapped_coordinates() {
+        let xml = osm(&[
+            node(1, 10.0, 50.0),
+            node(2, 10.01, 50.0),
+            way(10, &[1, 2], &[("highway", "residential")]),
+        ]);
+        let mut graph = build_graph(&xml, "{}", "{}");
+        let message = |graph: &ProcessedGraph, lon, lat| match graph.find_nearest_node(lon, lat) {
+            Err(GraphError::InvalidCoordinates(message)) => message,
+            other => panic!("expected invalid coordinates, got {:?}", other),
+        };
+
+        assert!(message(&graph, 45.0, 120.0).ends_with("look swapped"));
+        assert!(!message(&graph, 200.0, 100.0).contains("swapped"));
+
+        assert!(!matches!(
+            graph.find_nearest_node(50.0, 10.0),
+            Err(GraphError::InvalidCoordinates(_))
+        ));
+        graph.max_outside_bounds_km = Some(100.0);
+        assert!(message(&graph, 50.0, 10.0).ends_with("look swapped"));
+        assert_eq!(graph.find_nearest_node(10.002, 50.001).unwrap(), 1);
+    }
+
+    fn grid_xml() -> String {
+        let mut parts = Vec::new();
+        for row in 0..4 {
+            for col in 0..4 {
+                parts.push(node(
+                    row * 4 + col + 1,
+                    col as f64 * 0.001,
+                    row as f64 * 0.001,
+                ));
+            }
+        }
+        for line in 0..4 {
+            let across: Vec<i64> = (0..4).map(|col| line * 4 + col + 1).collect();
+            let down: Vec<i64> = (0..4).map(|row| row * 4 + line + 1).collect();
+            parts.push(way(100 + line, &across, &[("highway", "residential")]));
+            parts.push(way(200 + line, &down, &[("highway", "primary")]));
+        }
+        osm(&parts)
+    }
+
+    fn containers() -> (GraphContainer, GraphContainer) {
+        let (nodes, ways, relations) = parse_osm_xml(&grid_xml()).unwrap();
+        let profiles: Vec<Profile> = [("a", 1), ("b", 2), ("c", 3), ("d", 4)]
+            .iter()
+            .map(|(id, penalty)| {
+                profile(&format!(
+                    r#"{{"id":"{}","penalties":{{"residential":{},"primary":1}}}}"#,
+                    id, penalty
+                ))
+            })
+            .collect();
+        let options = options("{}", profiles.clone());
+        let build = |profile: &Profile| {
+            GraphBuilder::new(profile, &options, &nodes, &ways, &relations)
+                .build()
+                .unwrap()
+        };
+
+        let mut separate = GraphContainer::new();
+        let mut shared = GraphContainer::new();
+        for profile in &profiles {
+            separate.profiles.insert(profile.id.clone(), build(profile));
+            shared.insert_sharing_topology(build(profile));
+        }
+        (separate, shared)
+    }
+
+    fn topology_bytes(container: &GraphContainer) -> usize {
+        let mut seen = FxHashSet::default();
+        let mut bytes = 0;
+        for graph in container.profiles.values() {
+            let mut count = |ptr: *const (), size: usize| {
+                if seen.insert(ptr) {
+                    bytes += size;
+                }
+            };
+            count(
+                Arc::as_ptr(&graph.nodes).cast(),
+                graph.nodes.len() * size_of::<RouteNode>(),
+            );
+            count(
+                Arc::as_ptr(&graph.offsets).cast(),
+                graph.offsets.len() * size_of::<usize>(),
+            );
+            count(
+                Arc::as_ptr(&graph.edges).cast(),
+                graph.edges.len() * size_of::<u32>(),
+            );
+            count(
+                Arc::as_ptr(&graph.edge_ways).cast(),
+                graph.edge_ways.len() * size_of::<u32>(),
+            );
+            count(
+                Arc::as_ptr(&graph.edge_geometry).cast(),
+                graph.edge_geometry.len() * size_of::<[f32; 2]>(),
+            );
+        }
+        bytes
+    }
+
+    #[test]
+    fn advisories_fail_on_route_that_cannot_be_walked() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.001, 0.0),
+            way(10, &[1, 2], &[("highway", "residential"), ("toll", "yes")]),
+        ]);
+        let graph = build_graph(&xml, "{}", "{}");
+
+        assert!(graph.route_advisories(&[1, 2]).unwrap().toll);
+        assert!(graph.route_advisories(&[1, 99]).is_err());
+    }
+
+    #[test]
+    fn shared_profiles_hold_one_topology() {
+        let (separate, shared) = containers();
+
+        assert!(topology_bytes(&separate) > 0);
+        assert_eq!(topology_bytes(&separate), 4 * topology_bytes(&shared));
+        for id in ["b", "c", "d"] {
+            let graph = &shared.profiles[id];
+            assert_eq!(
+                graph.build_report.shared_topology_with.as_deref(),
+                Some("a")
+            );
+            assert!(Arc::ptr_eq(&graph.ways, &shared.profiles["a"].ways));
+            assert!(Arc::ptr_eq(
+                &graph.string_interner,
+                &shared.profiles["a"].string_interner
+            ));
+            assert_ne!(graph.edge_costs, shared.profiles["a"].edge_costs);
+        }
+    }
+
+    #[test]
+    fn cache_writes_shared_topology_once() {
+        let (mut separate, mut shared) = containers();
+        let mut separate_cache = Vec::new();
+        let mut shared_cache = Vec::new();
+        separate.write_cache(&mut separate_cache).unwrap();
+        shared.write_cache(&mut shared_cache).unwrap();
+
+        assert!(shared_cache.len() < separate_cache.len() * 2 / 3);
+        assert!(Arc::ptr_eq(
+            &shared.profiles["a"].edges,
+            &shared.profiles["d"].edges
+        ));
+
+        let mut loaded: GraphContainer = bincode::deserialize(&shared_cache).unwrap();
+        loaded.attach_shared_topology().unwrap();
+        for id in ["a", "b", "c", "d"] {
+            let (graph, original) = (&loaded.profiles[id], &separate.profiles[id]);
+            assert!(Arc::ptr_eq(&graph.nodes, &loaded.profiles["a"].nodes));
+            assert_eq!(graph.nodes, original.nodes);
+            assert_eq!(graph.edges, original.edges);
+            assert_eq!(graph.edge_geometry, original.edge_geometry);
+            assert_eq!(graph.edge_costs, original.edge_costs);
+            assert!(graph.shared_fields.is_empty());
+        }
+    }
+
+    #[test]
+    fn profiles_with_many_penalties_share_string_table() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            way(10, &[1, 2], &[("highway", "residential")]),
+        ]);
+        let (nodes, ways, relations) = parse_osm_xml(&xml).unwrap();
+        let penalties =
+            r#"{"residential":1,"primary":1,"secondary":1,"tertiary":1,"service":1,"footway":1}"#;
+        let profiles: Vec<Profile> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|id| profile(&format!(r#"{{"id":"{}","penalties":{}}}"#, id, penalties)))
+            .collect();
+        let options = options(r#"{"share_topology":true}"#, profiles.clone());
+        let mut container = GraphContainer::new();
+        for profile in &profiles {
+            let graph = GraphBuilder::new(profile, &options, &nodes, &ways, &relations)
+                .build()
+                .unwrap();
+            container.profiles.insert(profile.id.clone(), graph);
+        }
+        container.share_topology();
+
+        let a = &container.profiles["a"];
+        for graph in container.profiles.values() {
+            assert!(Arc::ptr_eq(&graph.ways, &a.ways));
+            assert!(Arc::ptr_eq(&graph.string_interner, &a.string_interner));
+        }
+    }
 }
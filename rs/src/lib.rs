@@ -4,20 +4,28 @@ mod parser;
 mod processing;
 mod queue;
 mod routing;
+#[cfg(test)]
+mod test_support;
 
 use crate::core::errors::{GraphError, Result};
-use crate::core::types::LoadOptions;
-use crate::graph::GraphContainer;
+use crate::core::types::{LoadOptions, Profile};
+use crate::core::validation::{check_load_options, OptionsIssue};
+use crate::graph::{GraphContainer, GraphSource, ProcessedGraph, RouteNode, SnapDirection};
 use crate::parser::{fetch_from_overpass, fetch_pbf_to_memory, parse_osm_pbf, parse_osm_xml};
-use crate::processing::GraphBuilder;
+use crate::processing::{GraphBuilder, SharedInterner};
 use crate::queue::{RouteQueue, RouteRequest};
+use crate::routing::algorithm::{Metric, Route, RouteOptions, RouteOutcome};
+use crate::routing::osrm::GeometryFormat;
+use crate::routing::{cumulative_distances, path_distance};
 use lazy_static::lazy_static;
 use neon::prelude::*;
+use neon::types::buffer::TypedArray;
 use rayon::prelude::*;
 use rustc_hash::FxHashMap;
 use std::fs::{self, File};
 use std::io::{BufReader, BufWriter};
 use std::path::Path;
+use std::sync::atomic::{AtomicI32, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, SystemTime};
 use tokio::runtime::Runtime;
@@ -28,14 +36,27 @@ lazy_static! {
         .num_threads(num_cpus::get())
         .build()
         .expect("Failed to create routing thread pool");
-    static ref GRAPH_STORAGE: Mutex<FxHashMap<i32, Arc<RwLock<GraphContainer>>>> =
-        Mutex::new(FxHashMap::default());
+    static ref GRAPH_STORAGE: Mutex<FxHashMap<i32, StoredGraph>> = Mutex::new(FxHashMap::default());
     static ref ROUTE_QUEUES: Mutex<FxHashMap<i32, Arc<RouteQueue>>> =
         Mutex::new(FxHashMap::default());
 }
 
-static mut NEXT_GRAPH_ID: i32 = 1;
-static mut NEXT_QUEUE_ID: i32 = 1;
+struct StoredGraph {
+    graph: Arc<RwLock<GraphContainer>>,
+    pinned: bool,
+}
+
+fn get_graph(graph_id: i32) -> Result<Arc<RwLock<GraphContainer>>> {
+    GRAPH_STORAGE
+        .lock()
+        .unwrap()
+        .get(&graph_id)
+        .map(|stored| stored.graph.clone())
+        .ok_or(GraphError::GraphNotFound(graph_id))
+}
+
+static NEXT_GRAPH_ID: AtomicI32 = AtomicI32::new(1);
+static NEXT_QUEUE_ID: AtomicI32 = AtomicI32::new(1);
 
 fn load_or_build_graph_sync(options: LoadOptions) -> Result<GraphContainer> {
     let path = Path::new(&options.file_path);
@@ -50,10 +71,22 @@ fn load_or_build_graph_sync(options: LoadOptions) -> Result<GraphContainer> {
                     < ttl
                 {
                     let reader = BufReader::new(File::open(path)?);
-                    if let Ok(mut container) =
-                        bincode::deserialize_from::<_, GraphContainer>(reader)
-                    {
+                    let cached = bincode::deserialize_from::<_, GraphContainer>(reader)
+                        .map_err(GraphError::from)
+                        .and_then(|mut container| {
+                            container.attach_shared_topology().map(|()| container)
+                        });
+                    if let Ok(mut container) = cached {
+                        if options.share_interner {
+                            container.share_interners();
+                        }
                         container.build_all_indices();
+                        container.index_tags(&options.index_tags);
+                        if options.share_topology {
+                            container.share_topology();
+                        }
+                        container.from_cache = true;
+                        container.apply_load_options(&options);
                         return Ok(container);
                     }
                 }
@@ -80,36 +113,78 @@ fn load_or_build_graph_sync(options: LoadOptions) -> Result<GraphContainer> {
         )));
     };
 
-    let processed_profiles: Vec<_> = options
-        .profiles
-        .par_iter()
-        .map(|profile| {
-            let builder = GraphBuilder::new(profile, &raw_nodes, &raw_ways, &raw_relations);
-            builder.build().map(|graph| (profile.id.clone(), graph))
-        })
-        .collect::<Result<_>>()?;
+    let shared_interner = options
+        .share_interner
+        .then(|| SharedInterner::build(&options.profiles, &raw_nodes, &raw_ways, &raw_relations));
+    let build_profile = |profile: &Profile| {
+        let builder = match &shared_interner {
+            Some(shared) => GraphBuilder::with_shared_interner(
+                profile,
+                &options,
+                &raw_nodes,
+                &raw_ways,
+                &raw_relations,
+                shared,
+            ),
+            None => GraphBuilder::new(profile, &options, &raw_nodes, &raw_ways, &raw_relations),
+        };
+        builder.build()
+    };
 
     let mut container = GraphContainer::new();
-    container.profiles = processed_profiles.into_iter().collect();
+    if options.share_topology {
+        // One profile at a time, so each graph frees the topology it has in
+        // common with the ones before it before the next build starts.
+        for profile in &options.profiles {
+            container.insert_sharing_topology(build_profile(profile)?);
+        }
+    } else {
+        container.profiles = options
+            .profiles
+            .par_iter()
+            .map(|profile| build_profile(profile).map(|graph| (profile.id.clone(), graph)))
+            .collect::<Result<_>>()?;
+    }
+    drop(shared_interner);
+    container.source = if let Some(proto_opts) = &options.protobuf {
+        Some(GraphSource::Protobuf {
+            url: proto_opts.url.clone(),
+        })
+    } else {
+        options
+            .overpass
+            .as_ref()
+            .map(|overpass_opts| GraphSource::Overpass {
+                server: overpass_opts.server.clone(),
+            })
+    };
+    container.apply_load_options(&options);
+    container.index_tags(&options.index_tags);
 
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)?;
     }
     let writer = BufWriter::new(File::create(path)?);
-    bincode::serialize_into(writer, &container)?;
+    container.write_cache(writer)?;
 
     Ok(container)
 }
 
 fn load_graph(mut cx: FunctionContext) -> JsResult<JsNumber> {
     let options_json = cx.argument::<JsString>(0)?.value(&mut cx);
-    let options: LoadOptions = serde_json::from_str(&options_json)
-        .or_else(|e| cx.throw_error(format!("Invalid options JSON: {}", e)))?;
-
-    let graph_id = unsafe {
-        let id = NEXT_GRAPH_ID;
-        NEXT_GRAPH_ID += 1;
-        id
+    let validation = check_load_options(&options_json);
+    for warning in &validation.warnings {
+        log::warn!("{}", warning);
+    }
+    let options = match validation.options {
+        Some(options) => options,
+        None => {
+            let messages: Vec<String> = validation.errors.iter().map(|e| e.to_string()).collect();
+            let js_error = cx.error(format!("Invalid options: {}", messages.join("; ")))?;
+            let js_issues = issues_to_js(&mut cx, &validation.errors)?;
+            js_error.set(&mut cx, "issues", js_issues)?;
+            return cx.throw(js_error);
+        }
     };
 
     match TOKIO_RUNTIME.block_on(async {
@@ -117,70 +192,1156 @@ fn load_graph(mut cx: FunctionContext) -> JsResult<JsNumber> {
             .await
             .unwrap()
     }) {
-        Ok(container) => {
-            GRAPH_STORAGE
-                .lock()
+        Ok(container) => Ok(cx.number(store_graph(container) as f64)),
+        Err(e) => cx.throw_error(format!("Failed to load/build graph: {}", e)),
+    }
+}
+
+fn store_graph(container: GraphContainer) -> i32 {
+    let graph_id = NEXT_GRAPH_ID.fetch_add(1, Ordering::Relaxed);
+    GRAPH_STORAGE.lock().unwrap().insert(
+        graph_id,
+        StoredGraph {
+            graph: Arc::new(RwLock::new(container)),
+            pinned: false,
+        },
+    );
+    graph_id
+}
+
+fn issues_to_js<'a>(cx: &mut impl Context<'a>, issues: &[OptionsIssue]) -> JsResult<'a, JsArray> {
+    let js_issues = JsArray::new(cx, issues.len());
+    for (i, issue) in issues.iter().enumerate() {
+        let js_issue = cx.empty_object();
+        let path = cx.string(&issue.path);
+        js_issue.set(cx, "path", path)?;
+        let message = cx.string(&issue.message);
+        js_issue.set(cx, "message", message)?;
+        js_issues.set(cx, i as u32, js_issue)?;
+    }
+    Ok(js_issues)
+}
+
+fn validate_load_options(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let options_json = cx.argument::<JsString>(0)?.value(&mut cx);
+    let validation = check_load_options(&options_json);
+
+    let js_result = cx.empty_object();
+    let valid = cx.boolean(validation.options.is_some());
+    js_result.set(&mut cx, "valid", valid)?;
+    let js_errors = issues_to_js(&mut cx, &validation.errors)?;
+    js_result.set(&mut cx, "errors", js_errors)?;
+    let js_warnings = issues_to_js(&mut cx, &validation.warnings)?;
+    js_result.set(&mut cx, "warnings", js_warnings)?;
+    Ok(js_result)
+}
+
+fn parse_route_options(cx: &mut FunctionContext, index: usize) -> NeonResult<RouteOptions> {
+    let mut options = RouteOptions::default();
+    let js_options = match cx.argument_opt(index) {
+        Some(v) if v.is_a::<JsObject, _>(cx) => v.downcast_or_throw::<JsObject, _>(cx)?,
+        _ => return Ok(options),
+    };
+
+    if let Some(metric) = js_options.get_opt::<JsString, _, _>(cx, "metric")? {
+        let metric = metric.value(cx);
+        options.metric = match metric.parse::<Metric>() {
+            Ok(m) => m,
+            Err(e) => return cx.throw_error(e.to_string()),
+        };
+    }
+
+    if let Some(bearing) = js_options.get_opt::<JsNumber, _, _>(cx, "initialBearing")? {
+        let bearing = bearing.value(cx);
+        if !bearing.is_finite() {
+            return cx.throw_error("initialBearing must be a finite number");
+        }
+        options.initial_bearing = Some(bearing.rem_euclid(360.0) as f32);
+    }
+
+    if let Some(prefer_lit) = js_options.get_opt::<JsBoolean, _, _>(cx, "preferLit")? {
+        options.prefer_lit = prefer_lit.value(cx);
+    }
+
+    if let Some(required_ways) = js_options.get_opt::<JsArray, _, _>(cx, "requiredWays")? {
+        for way_id in required_ways.to_vec(cx)? {
+            options.required_ways.push(js_to_id(cx, way_id)?);
+        }
+    }
+
+    Ok(options)
+}
+
+fn parse_simplify_epsilon(cx: &mut FunctionContext, index: usize) -> NeonResult<f32> {
+    let js_options = match cx.argument_opt(index) {
+        Some(v) if v.is_a::<JsObject, _>(cx) => v.downcast_or_throw::<JsObject, _>(cx)?,
+        _ => return Ok(0.0),
+    };
+    match js_options.get_opt::<JsNumber, _, _>(cx, "simplifyEpsilon")? {
+        Some(epsilon) => {
+            let epsilon = epsilon.value(cx);
+            if !epsilon.is_finite() || epsilon < 0.0 {
+                return cx.throw_error("simplifyEpsilon must be a non-negative number");
+            }
+            Ok(epsilon as f32)
+        }
+        None => Ok(0.0),
+    }
+}
+
+fn parse_flag_option(cx: &mut FunctionContext, index: usize, key: &str) -> NeonResult<bool> {
+    let js_options = match cx.argument_opt(index) {
+        Some(v) if v.is_a::<JsObject, _>(cx) => v.downcast_or_throw::<JsObject, _>(cx)?,
+        _ => return Ok(false),
+    };
+    Ok(js_options
+        .get_opt::<JsBoolean, _, _>(cx, key)?
+        .is_some_and(|flag| flag.value(cx)))
+}
+
+fn parse_origin_fixes(cx: &mut FunctionContext, index: usize) -> NeonResult<Option<[[f32; 2]; 2]>> {
+    let js_options = match cx.argument_opt(index) {
+        Some(v) if v.is_a::<JsObject, _>(cx) => v.downcast_or_throw::<JsObject, _>(cx)?,
+        _ => return Ok(None),
+    };
+    let js_fixes = match js_options.get_opt::<JsArray, _, _>(cx, "originFixes")? {
+        Some(fixes) => fixes.to_vec(cx)?,
+        None => return Ok(None),
+    };
+    if js_fixes.len() != 2 {
+        return cx.throw_error("originFixes must contain exactly two [lon, lat] fixes");
+    }
+
+    let mut fixes = [[0.0f32; 2]; 2];
+    for (fix, js_fix) in fixes.iter_mut().zip(js_fixes) {
+        let coords = js_fix.downcast_or_throw::<JsArray, _>(cx)?.to_vec(cx)?;
+        if coords.len() != 2 {
+            return cx.throw_error("originFixes must contain exactly two [lon, lat] fixes");
+        }
+        for (value, js_value) in fix.iter_mut().zip(coords) {
+            *value = js_value.downcast_or_throw::<JsNumber, _>(cx)?.value(cx) as f32;
+        }
+    }
+
+    Ok(Some(fixes))
+}
+
+fn get_route(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+    let waypoints_js = cx.argument::<JsArray>(2)?;
+    let waypoints: Vec<i64> = waypoints_js
+        .to_vec(&mut cx)?
+        .into_iter()
+        .map(|v| js_to_id(&mut cx, v))
+        .collect::<NeonResult<_>>()?;
+    let options = parse_route_options(&mut cx, 3)?;
+    let origin_fixes = parse_origin_fixes(&mut cx, 3)?;
+    let simplify_epsilon = parse_simplify_epsilon(&mut cx, 3)?;
+    let include_elevations = parse_flag_option(&mut cx, 3, "includeElevations")?;
+    let include_warnings = parse_flag_option(&mut cx, 3, "includeWarnings")?;
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+
+    ROUTING_THREAD_POOL.spawn(move || {
+        let graph_guard = graph.read().unwrap();
+        let result = match origin_fixes {
+            Some(fixes) => graph_guard.route_from_fixes(&profile_id, fixes, &waypoints, &options),
+            None => graph_guard.route_with_options(&profile_id, &waypoints, &options),
+        };
+        let coordinates = match (&result, graph_guard.profiles.get(&profile_id)) {
+            (Ok(RouteOutcome::Found(route)), Some(profile_graph)) if simplify_epsilon > 0.0 => {
+                Some(profile_graph.simplified_shape(&route.nodes, simplify_epsilon))
+            }
+            _ => None,
+        };
+        let leg_distances: Vec<f32> = match (&result, graph_guard.profiles.get(&profile_id)) {
+            (Ok(RouteOutcome::Found(route)), Some(profile_graph)) if route.legs.len() > 1 => route
+                .legs
+                .iter()
+                .map(|leg| {
+                    path_distance(
+                        profile_graph,
+                        &route.nodes[leg.node_start_index..=leg.node_end_index],
+                    )
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let elevation_profile = match (&result, graph_guard.profiles.get(&profile_id)) {
+            (Ok(RouteOutcome::Found(route)), Some(profile_graph)) if include_elevations => {
+                let elevations: Vec<Option<f32>> = route
+                    .nodes
+                    .iter()
+                    .map(|&node_id| profile_graph.node_elevation(node_id))
+                    .collect();
+                Some((
+                    elevations,
+                    cumulative_distances(profile_graph, &route.nodes),
+                ))
+            }
+            _ => None,
+        };
+        let advisories = match (&result, graph_guard.profiles.get(&profile_id)) {
+            (Ok(RouteOutcome::Found(route)), Some(profile_graph)) if include_warnings => {
+                Some(profile_graph.route_advisories(&route.nodes))
+            }
+            _ => None,
+        };
+        drop(graph_guard);
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(RouteOutcome::Found(route)) => {
+                let nodes = &route.nodes;
+                let js_result = cx.empty_object();
+                let js_nodes = JsArray::new(&mut cx, nodes.len());
+                for (i, node_id) in nodes.iter().enumerate() {
+                    let js_node_id = id_to_js(&mut cx, *node_id);
+                    js_nodes.set(&mut cx, i as u32, js_node_id)?;
+                }
+                js_result.set(&mut cx, "nodes", js_nodes)?;
+
+                if let Some(coordinates) = coordinates {
+                    let js_coordinates = JsArray::new(&mut cx, coordinates.len());
+                    for (i, [lon, lat]) in coordinates.iter().enumerate() {
+                        let point_array = JsArray::new(&mut cx, 2);
+                        let lon = cx.number(*lon as f64);
+                        let lat = cx.number(*lat as f64);
+                        point_array.set(&mut cx, 0, lon)?;
+                        point_array.set(&mut cx, 1, lat)?;
+                        js_coordinates.set(&mut cx, i as u32, point_array)?;
+                    }
+                    js_result.set(&mut cx, "coordinates", js_coordinates)?;
+                }
+
+                if let Some((elevations, distances)) = elevation_profile {
+                    let js_elevations = JsArray::new(&mut cx, elevations.len());
+                    for (i, elevation) in elevations.iter().enumerate() {
+                        let elevation: Handle<JsValue> = match elevation {
+                            Some(elevation) => cx.number(*elevation as f64).upcast(),
+                            None => cx.null().upcast(),
+                        };
+                        js_elevations.set(&mut cx, i as u32, elevation)?;
+                    }
+                    js_result.set(&mut cx, "elevations", js_elevations)?;
+
+                    let js_distances = JsArray::new(&mut cx, distances.len());
+                    for (i, distance) in distances.iter().enumerate() {
+                        let distance = cx.number(*distance as f64);
+                        js_distances.set(&mut cx, i as u32, distance)?;
+                    }
+                    js_result.set(&mut cx, "distances", js_distances)?;
+                }
+
+                if let Some(advisories) = advisories {
+                    let advisories = match advisories {
+                        Ok(advisories) => advisories,
+                        Err(e) => return cx.throw_error(e.to_string()),
+                    };
+                    let js_warnings = cx.empty_object();
+                    let ferry = cx.boolean(advisories.ferry);
+                    js_warnings.set(&mut cx, "ferry", ferry)?;
+                    let toll = cx.boolean(advisories.toll);
+                    js_warnings.set(&mut cx, "toll", toll)?;
+                    let unpaved = cx.boolean(advisories.unpaved);
+                    js_warnings.set(&mut cx, "unpaved", unpaved)?;
+                    let private_access = cx.boolean(advisories.private_access);
+                    js_warnings.set(&mut cx, "private", private_access)?;
+                    js_result.set(&mut cx, "warnings", js_warnings)?;
+                }
+
+                let waypoint_indices = route.waypoint_indices();
+                let js_waypoint_indices = JsArray::new(&mut cx, waypoint_indices.len());
+                for (i, node_index) in waypoint_indices.iter().enumerate() {
+                    let node_index = cx.number(*node_index as f64);
+                    js_waypoint_indices.set(&mut cx, i as u32, node_index)?;
+                }
+                js_result.set(&mut cx, "waypointIndices", js_waypoint_indices)?;
+
+                if !leg_distances.is_empty() {
+                    let js_legs = JsArray::new(&mut cx, route.legs.len());
+                    for (i, (leg, distance_meters)) in
+                        route.legs.iter().zip(&leg_distances).enumerate()
+                    {
+                        let js_leg = cx.empty_object();
+                        let cost = cx.number(leg.cost as f64);
+                        js_leg.set(&mut cx, "cost", cost)?;
+                        let distance = cx.number(*distance_meters as f64);
+                        js_leg.set(&mut cx, "distanceMeters", distance)?;
+                        let start_index = cx.number(leg.node_start_index as f64);
+                        js_leg.set(&mut cx, "nodeStartIndex", start_index)?;
+                        let end_index = cx.number(leg.node_end_index as f64);
+                        js_leg.set(&mut cx, "nodeEndIndex", end_index)?;
+                        js_legs.set(&mut cx, i as u32, js_leg)?;
+                    }
+                    js_result.set(&mut cx, "legs", js_legs)?;
+                }
+                Ok(js_result)
+            }
+
+            Ok(RouteOutcome::NoRouteBetween(from_index, to_index)) => {
+                let js_result = cx.empty_object();
+                let js_nodes = JsArray::new(&mut cx, 0);
+                js_result.set(&mut cx, "nodes", js_nodes)?;
+
+                let js_between = JsArray::new(&mut cx, 2);
+                let from_index = cx.number(from_index as f64);
+                let to_index = cx.number(to_index as f64);
+                js_between.set(&mut cx, 0, from_index)?;
+                js_between.set(&mut cx, 1, to_index)?;
+                js_result.set(&mut cx, "noRouteBetween", js_between)?;
+                Ok(js_result)
+            }
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+fn json_to_js<'a>(cx: &mut impl Context<'a>, value: &serde_json::Value) -> JsResult<'a, JsValue> {
+    Ok(match value {
+        serde_json::Value::Null => cx.null().upcast(),
+        serde_json::Value::Bool(flag) => cx.boolean(*flag).upcast(),
+        serde_json::Value::Number(number) => cx.number(number.as_f64().unwrap_or(0.0)).upcast(),
+        serde_json::Value::String(string) => cx.string(string).upcast(),
+        serde_json::Value::Array(items) => {
+            let js_array = JsArray::new(cx, items.len());
+            for (i, item) in items.iter().enumerate() {
+                let js_item = json_to_js(cx, item)?;
+                js_array.set(cx, i as u32, js_item)?;
+            }
+            js_array.upcast()
+        }
+        serde_json::Value::Object(fields) => {
+            let js_object = cx.empty_object();
+            for (key, field) in fields {
+                let js_field = json_to_js(cx, field)?;
+                js_object.set(cx, key.as_str(), js_field)?;
+            }
+            js_object.upcast()
+        }
+    })
+}
+
+fn get_route_osrm(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+    let coordinates_js = cx.argument::<JsArray>(2)?.to_vec(&mut cx)?;
+
+    let mut coordinates = Vec::with_capacity(coordinates_js.len());
+    for (i, js_coordinate) in coordinates_js.into_iter().enumerate() {
+        let values = js_coordinate
+            .downcast_or_throw::<JsArray, _>(&mut cx)?
+            .to_vec(&mut cx)?;
+        if values.len() != 2 {
+            return cx.throw_error(format!(
+                "Coordinate at index {} is not a [lon, lat] pair",
+                i
+            ));
+        }
+        let mut coordinate = [0.0f32; 2];
+        for (value, js_value) in coordinate.iter_mut().zip(values) {
+            *value = js_value
+                .downcast_or_throw::<JsNumber, _>(&mut cx)?
+                .value(&mut cx) as f32;
+        }
+        coordinates.push(coordinate);
+    }
+
+    let options = parse_route_options(&mut cx, 3)?;
+    let mut geometries = GeometryFormat::Polyline;
+    if let Some(v) = cx.argument_opt(3) {
+        if v.is_a::<JsObject, _>(&mut cx) {
+            let js_options = v.downcast_or_throw::<JsObject, _>(&mut cx)?;
+            if let Some(format) = js_options.get_opt::<JsString, _, _>(&mut cx, "geometries")? {
+                geometries = match format.value(&mut cx).as_str() {
+                    "polyline" => GeometryFormat::Polyline,
+                    "polyline6" => GeometryFormat::Polyline6,
+                    "geojson" => GeometryFormat::GeoJson,
+                    other => {
+                        return cx.throw_error(format!(
+                        "Invalid geometries '{}', expected 'polyline', 'polyline6' or 'geojson'",
+                        other
+                    ))
+                    }
+                };
+            }
+        }
+    }
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+
+    ROUTING_THREAD_POOL.spawn(move || {
+        let result =
+            graph
+                .read()
+                .unwrap()
+                .route_osrm(&profile_id, &coordinates, geometries, &options);
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(response) => json_to_js(&mut cx, &response),
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+fn compared_route_to_js<'a>(
+    cx: &mut TaskContext<'a>,
+    route: &Option<Route>,
+    distance_meters: f32,
+) -> JsResult<'a, JsValue> {
+    let route = match route {
+        Some(r) => r,
+        None => return Ok(cx.null().upcast()),
+    };
+
+    let js_route = cx.empty_object();
+    let js_nodes = JsArray::new(cx, route.nodes.len());
+    for (i, node_id) in route.nodes.iter().enumerate() {
+        let js_node_id = id_to_js(cx, *node_id);
+        js_nodes.set(cx, i as u32, js_node_id)?;
+    }
+    js_route.set(cx, "nodes", js_nodes)?;
+
+    let cost = cx.number(route.cost as f64);
+    js_route.set(cx, "cost", cost)?;
+    let distance = cx.number(distance_meters as f64);
+    js_route.set(cx, "distanceMeters", distance)?;
+
+    Ok(js_route.upcast())
+}
+
+fn compare_routes(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_a = cx.argument::<JsString>(1)?.value(&mut cx);
+    let profile_b = cx.argument::<JsString>(2)?.value(&mut cx);
+    let start = id_argument(&mut cx, 3)?;
+    let end = id_argument(&mut cx, 4)?;
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+
+    ROUTING_THREAD_POOL.spawn(move || {
+        let result = graph
+            .read()
+            .unwrap()
+            .compare_routes(&profile_a, &profile_b, start, end);
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(comparison) => {
+                let js_result = cx.empty_object();
+
+                let route_a =
+                    compared_route_to_js(&mut cx, &comparison.route_a, comparison.distance_a)?;
+                js_result.set(&mut cx, "routeA", route_a)?;
+                let route_b =
+                    compared_route_to_js(&mut cx, &comparison.route_b, comparison.distance_b)?;
+                js_result.set(&mut cx, "routeB", route_b)?;
+
+                let overlap = cx.number(comparison.overlap as f64);
+                js_result.set(&mut cx, "overlap", overlap)?;
+
+                let (cost_delta, distance_delta) = match (&comparison.route_a, &comparison.route_b)
+                {
+                    (Some(a), Some(b)) => (
+                        cx.number(b.cost as f64 - a.cost as f64).upcast::<JsValue>(),
+                        cx.number((comparison.distance_b - comparison.distance_a) as f64)
+                            .upcast::<JsValue>(),
+                    ),
+                    _ => (cx.null().upcast(), cx.null().upcast()),
+                };
+                js_result.set(&mut cx, "costDelta", cost_delta)?;
+                js_result.set(&mut cx, "distanceDelta", distance_delta)?;
+
+                Ok(js_result)
+            }
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+fn get_isochrone_polygon(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+    let start = id_argument(&mut cx, 2)?;
+    let max_cost = cx.argument::<JsNumber>(3)?.value(&mut cx).max(0.0) as u32;
+    let concavity = cx.argument::<JsNumber>(4)?.value(&mut cx);
+    let options = parse_route_options(&mut cx, 5)?;
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+
+    ROUTING_THREAD_POOL.spawn(move || {
+        let result = graph.read().unwrap().isochrone_polygon(
+            &profile_id,
+            start,
+            max_cost,
+            concavity,
+            &options,
+        );
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(ring) => {
+                let js_ring = JsArray::new(&mut cx, ring.len());
+                for (i, [lon, lat]) in ring.iter().enumerate() {
+                    let point_array = JsArray::new(&mut cx, 2);
+                    let lon = cx.number(*lon as f64);
+                    let lat = cx.number(*lat as f64);
+                    point_array.set(&mut cx, 0, lon)?;
+                    point_array.set(&mut cx, 1, lat)?;
+                    js_ring.set(&mut cx, i as u32, point_array)?;
+                }
+                Ok(js_ring)
+            }
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+fn get_isochrone_bands(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+    let start = id_argument(&mut cx, 2)?;
+    let budgets_js = cx.argument::<JsArray>(3)?;
+    let budgets: Vec<u32> = budgets_js
+        .to_vec(&mut cx)?
+        .into_iter()
+        .map(|v| {
+            Ok(v.downcast_or_throw::<JsNumber, _>(&mut cx)?
+                .value(&mut cx)
+                .max(0.0) as u32)
+        })
+        .collect::<NeonResult<_>>()?;
+    let options = parse_route_options(&mut cx, 4)?;
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+
+    ROUTING_THREAD_POOL.spawn(move || {
+        let result = graph
+            .read()
+            .unwrap()
+            .isochrone_bands(&profile_id, start, &budgets, &options);
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(bands) => {
+                let js_bands = JsArray::new(&mut cx, bands.len());
+                for (i, band) in bands.iter().enumerate() {
+                    let js_band = cx.empty_object();
+                    let budget = cx.number(band.budget as f64);
+                    js_band.set(&mut cx, "budget", budget)?;
+
+                    let js_nodes = JsArray::new(&mut cx, band.nodes.len());
+                    for (j, &node_id) in band.nodes.iter().enumerate() {
+                        let node_id = id_to_js(&mut cx, node_id);
+                        js_nodes.set(&mut cx, j as u32, node_id)?;
+                    }
+                    js_band.set(&mut cx, "nodes", js_nodes)?;
+
+                    js_bands.set(&mut cx, i as u32, js_band)?;
+                }
+                Ok(js_bands)
+            }
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+fn find_reachable_pois(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+    let start = id_argument(&mut cx, 2)?;
+    let max_cost = cx.argument::<JsNumber>(3)?.value(&mut cx).max(0.0) as u32;
+    let tag_key = cx.argument::<JsString>(4)?.value(&mut cx);
+    let tag_value = match cx.argument_opt(5) {
+        Some(v) if v.is_a::<JsString, _>(&mut cx) => {
+            Some(v.downcast_or_throw::<JsString, _>(&mut cx)?.value(&mut cx))
+        }
+        _ => None,
+    };
+    let options = parse_route_options(&mut cx, 6)?;
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+
+    ROUTING_THREAD_POOL.spawn(move || {
+        let result = graph.read().unwrap().reachable_pois(
+            &profile_id,
+            start,
+            max_cost,
+            &tag_key,
+            tag_value.as_deref(),
+            &options,
+        );
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(pois) => {
+                let js_pois = JsArray::new(&mut cx, pois.len());
+                for (i, poi) in pois.iter().enumerate() {
+                    let obj = cx.empty_object();
+                    let id = id_to_js(&mut cx, poi.id);
+                    obj.set(&mut cx, "id", id)?;
+
+                    let location = JsArray::new(&mut cx, 2);
+                    let lon = cx.number(poi.location[0] as f64);
+                    let lat = cx.number(poi.location[1] as f64);
+                    location.set(&mut cx, 0, lon)?;
+                    location.set(&mut cx, 1, lat)?;
+                    obj.set(&mut cx, "location", location)?;
+
+                    let cost = cx.number(poi.cost as f64);
+                    obj.set(&mut cx, "cost", cost)?;
+                    js_pois.set(&mut cx, i as u32, obj)?;
+                }
+                Ok(js_pois)
+            }
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+fn id_list_argument(cx: &mut FunctionContext, index: usize, what: &str) -> NeonResult<Vec<i64>> {
+    let js_vec = cx.argument::<JsArray>(index)?.to_vec(cx)?;
+    let mut ids = Vec::with_capacity(js_vec.len());
+    for (i, v) in js_vec.iter().enumerate() {
+        if !v.is_a::<JsNumber, _>(cx) && !v.is_a::<JsString, _>(cx) {
+            return cx.throw_error(format!("{} at index {} is not a valid node ID", what, i));
+        }
+        ids.push(js_to_id(cx, *v)?);
+    }
+    Ok(ids)
+}
+
+fn get_nearest_facility_cost(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+    let origins = id_list_argument(&mut cx, 2, "Origin")?;
+    let facilities = id_list_argument(&mut cx, 3, "Facility")?;
+    let options = parse_route_options(&mut cx, 4)?;
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+
+    ROUTING_THREAD_POOL.spawn(move || {
+        let result =
+            graph
+                .read()
                 .unwrap()
-                .insert(graph_id, Arc::new(RwLock::new(container)));
-            Ok(cx.number(graph_id as f64))
+                .nearest_facilities(&profile_id, &origins, &facilities, &options);
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(nearest) => {
+                let js_nearest = JsArray::new(&mut cx, nearest.len());
+                for (i, entry) in nearest.iter().enumerate() {
+                    let obj = cx.empty_object();
+                    let origin = id_to_js(&mut cx, entry.origin);
+                    obj.set(&mut cx, "originId", origin)?;
+
+                    let facility: Handle<JsValue> = match entry.facility {
+                        Some(facility) => id_to_js(&mut cx, facility),
+                        None => cx.null().upcast(),
+                    };
+                    obj.set(&mut cx, "nearestFacilityId", facility)?;
+
+                    let cost: Handle<JsValue> = match entry.cost {
+                        Some(cost) => cx.number(cost as f64).upcast(),
+                        None => cx.null().upcast(),
+                    };
+                    obj.set(&mut cx, "cost", cost)?;
+                    js_nearest.set(&mut cx, i as u32, obj)?;
+                }
+                Ok(js_nearest)
+            }
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+fn apply_edge_weights(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+    let csv_arg = cx.argument::<JsValue>(2)?;
+    let csv = if let Ok(text) = csv_arg.downcast::<JsString, _>(&mut cx) {
+        text.value(&mut cx)
+    } else if let Ok(buffer) = csv_arg.downcast::<JsBuffer, _>(&mut cx) {
+        match String::from_utf8(buffer.as_slice(&cx).to_vec()) {
+            Ok(text) => text,
+            Err(_) => return cx.throw_error("Edge weight CSV is not valid UTF-8"),
         }
-        Err(e) => cx.throw_error(format!("Failed to load/build graph: {}", e)),
+    } else {
+        return cx.throw_type_error("Edge weights must be a string or a Buffer");
+    };
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+
+    ROUTING_THREAD_POOL.spawn(move || {
+        let result = match graph.write().unwrap().profiles.get_mut(&profile_id) {
+            Some(profile_graph) => profile_graph.apply_edge_weights(&csv),
+            None => Err(GraphError::ProfileNotFound(profile_id)),
+        };
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(applied) => Ok(cx.number(applied as f64)),
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+fn self_test(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+    let sample_count = cx.argument::<JsNumber>(2)?.value(&mut cx).max(0.0) as usize;
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+
+    ROUTING_THREAD_POOL.spawn(move || {
+        let result = graph.read().unwrap().self_test(&profile_id, sample_count);
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(report) => {
+                let obj = cx.empty_object();
+                let samples = cx.number(report.samples as f64);
+                obj.set(&mut cx, "samples", samples)?;
+                let success_rate = if report.samples == 0 {
+                    0.0
+                } else {
+                    report.successes as f64 / report.samples as f64
+                };
+                let success_rate = cx.number(success_rate);
+                obj.set(&mut cx, "successRate", success_rate)?;
+                let median_latency = cx.number(report.median_latency_ms);
+                obj.set(&mut cx, "medianLatencyMs", median_latency)?;
+                let median_expanded = cx.number(report.median_expanded as f64);
+                obj.set(&mut cx, "medianExpanded", median_expanded)?;
+                Ok(obj)
+            }
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+fn warm_graph(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+
+    ROUTING_THREAD_POOL.spawn(move || {
+        let result = graph.read().unwrap().warm(&profile_id);
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(()) => Ok(cx.undefined()),
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+fn get_matrix(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+    let points_js = cx.argument::<JsArray>(2)?;
+
+    let js_vec = points_js.to_vec(&mut cx)?;
+    let mut points = Vec::with_capacity(js_vec.len());
+    for (i, v) in js_vec.iter().enumerate() {
+        if !v.is_a::<JsNumber, _>(&mut cx) && !v.is_a::<JsString, _>(&mut cx) {
+            return cx.throw_error(format!("Point at index {} is not a valid node ID", i));
+        }
+        points.push(js_to_id(&mut cx, *v)?);
+    }
+
+    let mut concurrency = None;
+    if let Some(v) = cx.argument_opt(3) {
+        if v.is_a::<JsObject, _>(&mut cx) {
+            let js_options = v.downcast_or_throw::<JsObject, _>(&mut cx)?;
+            if let Some(limit) = js_options.get_opt::<JsNumber, _, _>(&mut cx, "concurrency")? {
+                let limit = limit.value(&mut cx);
+                if !limit.is_finite() || limit < 1.0 || limit.fract() != 0.0 {
+                    return cx.throw_error("concurrency must be a positive integer");
+                }
+                concurrency = Some(limit as usize);
+            }
+        }
+    }
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+
+    ROUTING_THREAD_POOL.spawn(move || {
+        let result = graph
+            .read()
+            .unwrap()
+            .matrix(&profile_id, &points, concurrency);
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(matrix) => {
+                let js_matrix = JsArray::new(&mut cx, matrix.len());
+                for (i, row) in matrix.iter().enumerate() {
+                    let js_row = JsArray::new(&mut cx, row.len());
+                    for (j, cost) in row.iter().enumerate() {
+                        let cost: Handle<JsValue> = match cost {
+                            Some(cost) => cx.number(*cost as f64).upcast(),
+                            None => cx.null().upcast(),
+                        };
+                        js_row.set(&mut cx, j as u32, cost)?;
+                    }
+                    js_matrix.set(&mut cx, i as u32, js_row)?;
+                }
+                Ok(js_matrix)
+            }
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+fn get_optimized_route(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+    let start = id_argument(&mut cx, 2)?;
+    let waypoints_js = cx.argument::<JsArray>(3)?;
+    let end = id_argument(&mut cx, 4)?;
+
+    let js_vec = waypoints_js.to_vec(&mut cx)?;
+    let mut waypoints = Vec::with_capacity(js_vec.len());
+    for (i, v) in js_vec.iter().enumerate() {
+        if !v.is_a::<JsNumber, _>(&mut cx) && !v.is_a::<JsString, _>(&mut cx) {
+            return cx.throw_error(format!("Waypoint at index {} is not a valid node ID", i));
+        }
+        waypoints.push(js_to_id(&mut cx, *v)?);
+    }
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+
+    ROUTING_THREAD_POOL.spawn(move || {
+        let result = graph
+            .read()
+            .unwrap()
+            .optimized_route(&profile_id, start, &waypoints, end);
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(Some(optimized)) => {
+                let js_result = cx.empty_object();
+
+                let js_order = JsArray::new(&mut cx, optimized.order.len());
+                for (i, idx) in optimized.order.iter().enumerate() {
+                    let js_idx = cx.number(*idx as f64);
+                    js_order.set(&mut cx, i as u32, js_idx)?;
+                }
+                js_result.set(&mut cx, "order", js_order)?;
+
+                let js_nodes = JsArray::new(&mut cx, optimized.route.nodes.len());
+                for (i, node_id) in optimized.route.nodes.iter().enumerate() {
+                    let js_node_id = id_to_js(&mut cx, *node_id);
+                    js_nodes.set(&mut cx, i as u32, js_node_id)?;
+                }
+                js_result.set(&mut cx, "nodes", js_nodes)?;
+
+                let cost = cx.number(optimized.route.cost as f64);
+                js_result.set(&mut cx, "cost", cost)?;
+
+                Ok(js_result.upcast::<JsValue>())
+            }
+            Ok(None) => Ok(cx.null().upcast()),
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+fn get_nearest_node(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+    let lon = cx.argument::<JsNumber>(2)?.value(&mut cx) as f32;
+    let lat = cx.argument::<JsNumber>(3)?.value(&mut cx) as f32;
+    let direction = match cx.argument_opt(4) {
+        Some(v) if v.is_a::<JsString, _>(&mut cx) => {
+            match v
+                .downcast_or_throw::<JsString, _>(&mut cx)?
+                .value(&mut cx)
+                .as_str()
+            {
+                "start" => SnapDirection::Depart,
+                "end" => SnapDirection::Arrive,
+                other => {
+                    return cx.throw_error(format!(
+                        "Unknown snap direction '{}', expected \"start\" or \"end\"",
+                        other
+                    ))
+                }
+            }
+        }
+        _ => SnapDirection::Any,
+    };
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let graph_guard = graph.read().unwrap();
+    let profile_graph = match graph_guard.profiles.get(&profile_id) {
+        Some(pg) => pg,
+        None => return cx.throw_error(GraphError::ProfileNotFound(profile_id).to_string()),
+    };
+
+    match profile_graph.find_nearest_node_for(lon, lat, direction) {
+        Ok(node_id) => Ok(id_to_js(&mut cx, node_id).upcast()),
+        Err(e @ (GraphError::EmptyGraph(_) | GraphError::InvalidCoordinates(_))) => {
+            cx.throw_error(e.to_string())
+        }
+        Err(_) => Ok(cx.null().upcast()),
+    }
+}
+
+fn get_representative_node(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let graph_guard = graph.read().unwrap();
+    let profile_graph = match graph_guard.profiles.get(&profile_id) {
+        Some(pg) => pg,
+        None => return cx.throw_error(GraphError::ProfileNotFound(profile_id).to_string()),
+    };
+
+    match profile_graph.representative_node() {
+        Some(node_id) => Ok(id_to_js(&mut cx, node_id).upcast()),
+        None => Ok(cx.null().upcast()),
+    }
+}
+
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+/// An OSM ID as it crosses into JS: a number while it is a safe integer,
+/// a decimal string beyond that.
+#[derive(Debug, PartialEq)]
+enum JsId {
+    Number(f64),
+    Text(String),
+}
+
+fn encode_id(id: i64) -> JsId {
+    if id.unsigned_abs() <= MAX_SAFE_INTEGER as u64 {
+        JsId::Number(id as f64)
+    } else {
+        JsId::Text(id.to_string())
+    }
+}
+
+fn decode_number_id(number: f64) -> Option<i64> {
+    (number.fract() == 0.0 && number.abs() <= MAX_SAFE_INTEGER as f64).then_some(number as i64)
+}
+
+fn decode_text_id(text: &str) -> Option<i64> {
+    text.trim().parse::<i64>().ok()
+}
+
+fn id_to_js<'a>(cx: &mut impl Context<'a>, id: i64) -> Handle<'a, JsValue> {
+    match encode_id(id) {
+        JsId::Number(number) => cx.number(number).upcast(),
+        JsId::Text(text) => cx.string(text).upcast(),
+    }
+}
+
+fn js_to_id<'a>(cx: &mut impl Context<'a>, value: Handle<'a, JsValue>) -> NeonResult<i64> {
+    if let Ok(number) = value.downcast::<JsNumber, _>(cx) {
+        let number = number.value(cx);
+        return match decode_number_id(number) {
+            Some(id) => Ok(id),
+            None => cx.throw_range_error(format!(
+                "ID {} is not a safe integer; pass IDs above 2^53 as strings",
+                number
+            )),
+        };
+    }
+    if let Ok(text) = value.downcast::<JsString, _>(cx) {
+        let text = text.value(cx);
+        return match decode_text_id(&text) {
+            Some(id) => Ok(id),
+            None => cx.throw_type_error(format!("'{}' is not a valid ID", text)),
+        };
+    }
+    cx.throw_type_error("IDs must be numbers or numeric strings")
+}
+
+fn id_argument(cx: &mut FunctionContext, index: usize) -> NeonResult<i64> {
+    let value = cx.argument::<JsValue>(index)?;
+    js_to_id(cx, value)
+}
+
+fn node_to_js<'a>(
+    cx: &mut impl Context<'a>,
+    graph: &ProcessedGraph,
+    node: &RouteNode,
+) -> JsResult<'a, JsObject> {
+    let js_object = cx.empty_object();
+
+    let id_val = id_to_js(cx, node.external_id);
+    js_object.set(cx, "id", id_val)?;
+
+    let location_array = JsArray::new(cx, 2);
+    let lon = cx.number(node.lon as f64);
+    let lat = cx.number(node.lat as f64);
+    location_array.set(cx, 0, lon)?;
+    location_array.set(cx, 1, lat)?;
+    js_object.set(cx, "location", location_array)?;
+
+    let tags_obj = cx.empty_object();
+    for (key_id, val_id) in &node.tags {
+        let key = &graph.string_interner[*key_id as usize];
+        let value = &graph.string_interner[*val_id as usize];
+        let value_js = cx.string(value);
+        tags_obj.set(cx, key.as_str(), value_js)?;
     }
+    js_object.set(cx, "tags", tags_obj)?;
+
+    Ok(js_object)
 }
-fn get_route(mut cx: FunctionContext) -> JsResult<JsPromise> {
+
+fn get_nearest_way_geometry(mut cx: FunctionContext) -> JsResult<JsValue> {
     let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
     let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
-    let waypoints_js = cx.argument::<JsArray>(2)?;
-    let waypoints: Vec<i64> = waypoints_js
-        .to_vec(&mut cx)?
-        .into_iter()
-        .map(|v| v.downcast::<JsNumber, _>(&mut cx).unwrap().value(&mut cx) as i64)
-        .collect();
+    let lon = cx.argument::<JsNumber>(2)?.value(&mut cx) as f32;
+    let lat = cx.argument::<JsNumber>(3)?.value(&mut cx) as f32;
 
-    let graph = match GRAPH_STORAGE.lock().unwrap().get(&graph_id) {
-        Some(g) => g.clone(),
-        None => return cx.throw_error(GraphError::GraphNotFound(graph_id).to_string()),
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
     };
 
-    let (deferred, promise) = cx.promise();
-    let channel = cx.channel();
+    let graph_guard = graph.read().unwrap();
+    let profile_graph = match graph_guard.profiles.get(&profile_id) {
+        Some(pg) => pg,
+        None => return cx.throw_error(GraphError::ProfileNotFound(profile_id).to_string()),
+    };
 
-    ROUTING_THREAD_POOL.spawn(move || {
-        let result = graph.read().unwrap().route(&profile_id, &waypoints);
-        deferred.settle_with(&channel, move |mut cx| match result {
-            Ok(Some(nodes)) => {
-                let js_result = cx.empty_object();
-                let js_nodes = JsArray::new(&mut cx, nodes.len());
-                for (i, node_id) in nodes.iter().enumerate() {
-                    let js_node_id = cx.number(*node_id as f64);
-                    js_nodes.set(&mut cx, i as u32, js_node_id)?;
-                }
-                js_result.set(&mut cx, "nodes", js_nodes)?;
-                Ok(js_result)
-            }
+    let way_info = match profile_graph.find_nearest_way(lon, lat) {
+        Ok(Some(way_info)) => way_info,
+        Ok(None) => return Ok(cx.null().upcast()),
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
 
-            Ok(None) => {
-                let js_result = cx.empty_object();
-                let js_nodes = JsArray::new(&mut cx, 0);
-                js_result.set(&mut cx, "nodes", js_nodes)?;
-                Ok(js_result)
-            }
-            Err(e) => cx.throw_error(e.to_string()),
-        });
-    });
+    let js_object = cx.empty_object();
+    let id_val = id_to_js(&mut cx, way_info.osm_id);
+    js_object.set(&mut cx, "id", id_val)?;
 
-    Ok(promise)
+    let name: Handle<JsValue> = match profile_graph.tag_value(&way_info.tags, "name") {
+        Some(name) => cx.string(name).upcast(),
+        None => cx.null().upcast(),
+    };
+    js_object.set(&mut cx, "name", name)?;
+
+    let geometry = profile_graph.way_geometry(way_info);
+    let js_geometry = JsArray::new(&mut cx, geometry.len());
+    for (i, [lon, lat]) in geometry.iter().enumerate() {
+        let point_array = JsArray::new(&mut cx, 2);
+        let lon = cx.number(*lon as f64);
+        let lat = cx.number(*lat as f64);
+        point_array.set(&mut cx, 0, lon)?;
+        point_array.set(&mut cx, 1, lat)?;
+        js_geometry.set(&mut cx, i as u32, point_array)?;
+    }
+    js_object.set(&mut cx, "geometry", js_geometry)?;
+
+    Ok(js_object.upcast())
 }
 
-fn get_nearest_node(mut cx: FunctionContext) -> JsResult<JsValue> {
+fn get_nearest_way_matching(mut cx: FunctionContext) -> JsResult<JsValue> {
     let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
     let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
     let lon = cx.argument::<JsNumber>(2)?.value(&mut cx) as f32;
     let lat = cx.argument::<JsNumber>(3)?.value(&mut cx) as f32;
+    let tag_key = cx.argument::<JsString>(4)?.value(&mut cx);
+    let tag_value = match cx.argument_opt(5) {
+        Some(v) if v.is_a::<JsString, _>(&mut cx) => {
+            Some(v.downcast_or_throw::<JsString, _>(&mut cx)?.value(&mut cx))
+        }
+        _ => None,
+    };
 
-    let graph = match GRAPH_STORAGE.lock().unwrap().get(&graph_id) {
-        Some(g) => g.clone(),
-        None => return cx.throw_error(GraphError::GraphNotFound(graph_id).to_string()),
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
     };
 
     let graph_guard = graph.read().unwrap();
@@ -189,10 +1350,27 @@ fn get_nearest_node(mut cx: FunctionContext) -> JsResult<JsValue> {
         None => return cx.throw_error(GraphError::ProfileNotFound(profile_id).to_string()),
     };
 
-    match profile_graph.find_nearest_node(lon, lat) {
-        Ok(node_id) => Ok(cx.number(node_id as f64).upcast()),
-        Err(_) => Ok(cx.null().upcast()),
-    }
+    let (way_info, distance_meters) =
+        match profile_graph.find_nearest_way_matching(lon, lat, &tag_key, tag_value.as_deref()) {
+            Ok(Some(found)) => found,
+            Ok(None) => return Ok(cx.null().upcast()),
+            Err(e) => return cx.throw_error(e.to_string()),
+        };
+
+    let js_object = cx.empty_object();
+    let id_val = id_to_js(&mut cx, way_info.osm_id);
+    js_object.set(&mut cx, "id", id_val)?;
+
+    let name: Handle<JsValue> = match profile_graph.tag_value(&way_info.tags, "name") {
+        Some(name) => cx.string(name).upcast(),
+        None => cx.null().upcast(),
+    };
+    js_object.set(&mut cx, "name", name)?;
+
+    let distance = cx.number(distance_meters as f64);
+    js_object.set(&mut cx, "distanceMeters", distance)?;
+
+    Ok(js_object.upcast())
 }
 
 fn get_nodes_in_radius(mut cx: FunctionContext) -> JsResult<JsArray> {
@@ -202,9 +1380,9 @@ fn get_nodes_in_radius(mut cx: FunctionContext) -> JsResult<JsArray> {
     let lat = cx.argument::<JsNumber>(3)?.value(&mut cx) as f32;
     let radius_meters = cx.argument::<JsNumber>(4)?.value(&mut cx) as f32;
 
-    let graph = match GRAPH_STORAGE.lock().unwrap().get(&graph_id) {
-        Some(g) => g.clone(),
-        None => return cx.throw_error(GraphError::GraphNotFound(graph_id).to_string()),
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
     };
 
     let graph_guard = graph.read().unwrap();
@@ -213,31 +1391,14 @@ fn get_nodes_in_radius(mut cx: FunctionContext) -> JsResult<JsArray> {
         None => return cx.throw_error(GraphError::ProfileNotFound(profile_id).to_string()),
     };
 
-    let found_nodes = profile_graph.find_nodes_within_radius(lon, lat, radius_meters);
+    let found_nodes = match profile_graph.find_nodes_within_radius(lon, lat, radius_meters) {
+        Ok(nodes) => nodes,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
 
-    let js_array = JsArray::new(&mut cx, found_nodes.len() as usize);
+    let js_array = JsArray::new(&mut cx, found_nodes.len());
     for (i, node) in found_nodes.iter().enumerate() {
-        let js_object = cx.empty_object();
-
-        let id_val = cx.number(node.external_id as f64);
-        js_object.set(&mut cx, "id", id_val)?;
-
-        let location_array = JsArray::new(&mut cx, 2);
-        let lon = cx.number(node.lon as f64);
-        let lat = cx.number(node.lat as f64);
-        location_array.set(&mut cx, 0, lon)?;
-        location_array.set(&mut cx, 1, lat)?;
-        js_object.set(&mut cx, "location", location_array)?;
-
-        let tags_obj = cx.empty_object();
-        for (key_id, val_id) in &node.tags {
-            let key = &profile_graph.string_interner[*key_id as usize];
-            let value = &profile_graph.string_interner[*val_id as usize];
-            let value_js = cx.string(value);
-            tags_obj.set(&mut cx, key.as_str(), value_js)?;
-        }
-        js_object.set(&mut cx, "tags", tags_obj)?;
-
+        let js_object = node_to_js(&mut cx, profile_graph, node)?;
         js_array.set(&mut cx, i as u32, js_object)?;
     }
 
@@ -251,9 +1412,9 @@ fn get_ways_in_radius(mut cx: FunctionContext) -> JsResult<JsArray> {
     let lat = cx.argument::<JsNumber>(3)?.value(&mut cx) as f32;
     let radius_meters = cx.argument::<JsNumber>(4)?.value(&mut cx) as f32;
 
-    let graph = match GRAPH_STORAGE.lock().unwrap().get(&graph_id) {
-        Some(g) => g.clone(),
-        None => return cx.throw_error(GraphError::GraphNotFound(graph_id).to_string()),
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
     };
 
     let graph_guard = graph.read().unwrap();
@@ -262,13 +1423,16 @@ fn get_ways_in_radius(mut cx: FunctionContext) -> JsResult<JsArray> {
         None => return cx.throw_error(GraphError::ProfileNotFound(profile_id).to_string()),
     };
 
-    let found_ways = profile_graph.find_ways_within_radius(lon, lat, radius_meters);
+    let found_ways = match profile_graph.find_ways_within_radius(lon, lat, radius_meters) {
+        Ok(ways) => ways,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
 
-    let js_array = JsArray::new(&mut cx, found_ways.len() as usize);
+    let js_array = JsArray::new(&mut cx, found_ways.len());
     for (i, way_info) in found_ways.iter().enumerate() {
         let js_way = cx.empty_object();
 
-        let id_val = cx.number(way_info.osm_id as f64);
+        let id_val = id_to_js(&mut cx, way_info.osm_id);
         js_way.set(&mut cx, "id", id_val)?;
 
         let tags_obj = cx.empty_object();
@@ -280,12 +1444,12 @@ fn get_ways_in_radius(mut cx: FunctionContext) -> JsResult<JsArray> {
         }
         js_way.set(&mut cx, "tags", tags_obj)?;
 
-        let js_nodes = JsArray::new(&mut cx, way_info.node_ids.len() as usize);
+        let js_nodes = JsArray::new(&mut cx, way_info.node_ids.len());
         for (j, &node_id) in way_info.node_ids.iter().enumerate() {
             let node = &profile_graph.nodes[node_id as usize];
             let js_node = cx.empty_object();
 
-            let node_id_val = cx.number(node.external_id as f64);
+            let node_id_val = id_to_js(&mut cx, node.external_id);
             js_node.set(&mut cx, "id", node_id_val)?;
 
             let location_array = JsArray::new(&mut cx, 2);
@@ -316,11 +1480,11 @@ fn get_ways_in_radius(mut cx: FunctionContext) -> JsResult<JsArray> {
 fn get_node(mut cx: FunctionContext) -> JsResult<JsValue> {
     let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
     let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
-    let node_id = cx.argument::<JsNumber>(2)?.value(&mut cx) as i64;
+    let node_id = id_argument(&mut cx, 2)?;
 
-    let graph = match GRAPH_STORAGE.lock().unwrap().get(&graph_id) {
-        Some(g) => g.clone(),
-        None => return cx.throw_error(GraphError::GraphNotFound(graph_id).to_string()),
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
     };
 
     let graph_guard = graph.read().unwrap();
@@ -331,42 +1495,78 @@ fn get_node(mut cx: FunctionContext) -> JsResult<JsValue> {
 
     if let Some(internal_id) = profile_graph.node_id_map.get(&node_id) {
         if let Some(node) = profile_graph.nodes.get(*internal_id as usize) {
-            let js_object = cx.empty_object();
+            return Ok(node_to_js(&mut cx, profile_graph, node)?.upcast());
+        }
+    }
 
-            let id_val = cx.number(node.external_id as f64);
-            js_object.set(&mut cx, "id", id_val)?;
+    Ok(cx.null().upcast())
+}
 
-            let location_array = JsArray::new(&mut cx, 2);
-            let lon = cx.number(node.lon as f64);
-            let lat = cx.number(node.lat as f64);
-            location_array.set(&mut cx, 0, lon)?;
-            location_array.set(&mut cx, 1, lat)?;
-            js_object.set(&mut cx, "location", location_array)?;
+fn get_restrictions_at_node(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+    let node_id = id_argument(&mut cx, 2)?;
 
-            let tags_obj = cx.empty_object();
-            for (key_id, val_id) in &node.tags {
-                let key = &profile_graph.string_interner[*key_id as usize];
-                let value = &profile_graph.string_interner[*val_id as usize];
-                let value_js = cx.string(value);
-                tags_obj.set(&mut cx, key.as_str(), value_js)?;
-            }
-            js_object.set(&mut cx, "tags", tags_obj)?;
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let graph_guard = graph.read().unwrap();
+    let profile_graph = match graph_guard.profiles.get(&profile_id) {
+        Some(pg) => pg,
+        None => return cx.throw_error(GraphError::ProfileNotFound(profile_id).to_string()),
+    };
+
+    let restrictions = profile_graph.restrictions_at_node(node_id);
+    let js_restrictions = JsArray::new(&mut cx, restrictions.len());
+    for (i, restriction) in restrictions.iter().enumerate() {
+        let obj = cx.empty_object();
+
+        let id = id_to_js(&mut cx, restriction.relation_id);
+        obj.set(&mut cx, "id", id)?;
+        let kind = cx.string(&profile_graph.string_interner[restriction.kind as usize]);
+        obj.set(&mut cx, "type", kind)?;
+        let from_way = id_to_js(&mut cx, restriction.from_way);
+        obj.set(&mut cx, "fromWay", from_way)?;
+
+        let js_via_ways = JsArray::new(&mut cx, restriction.via_ways.len());
+        for (j, &way_id) in restriction.via_ways.iter().enumerate() {
+            let way_id = id_to_js(&mut cx, way_id);
+            js_via_ways.set(&mut cx, j as u32, way_id)?;
+        }
+        obj.set(&mut cx, "viaWays", js_via_ways)?;
 
-            return Ok(js_object.upcast());
+        let js_via_nodes = JsArray::new(&mut cx, restriction.via_nodes.len());
+        for (j, &via_node) in restriction.via_nodes.iter().enumerate() {
+            let via_node = id_to_js(&mut cx, via_node);
+            js_via_nodes.set(&mut cx, j as u32, via_node)?;
         }
+        obj.set(&mut cx, "viaNodes", js_via_nodes)?;
+
+        let to_way = id_to_js(&mut cx, restriction.to_way);
+        obj.set(&mut cx, "toWay", to_way)?;
+
+        js_restrictions.set(&mut cx, i as u32, obj)?;
     }
 
-    Ok(cx.null().upcast())
+    Ok(js_restrictions)
 }
 
-fn get_shape(mut cx: FunctionContext) -> JsResult<JsArray> {
+fn validate_route(mut cx: FunctionContext) -> JsResult<JsValue> {
     let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
     let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
     let nodes_js = cx.argument::<JsArray>(2)?;
 
-    let graph = match GRAPH_STORAGE.lock().unwrap().get(&graph_id) {
-        Some(g) => g.clone(),
-        None => return cx.throw_error(GraphError::GraphNotFound(graph_id).to_string()),
+    let node_ids: Vec<i64> = nodes_js
+        .to_vec(&mut cx)?
+        .into_iter()
+        .map(|v| js_to_id(&mut cx, v))
+        .collect::<NeonResult<_>>()?;
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
     };
 
     let graph_guard = graph.read().unwrap();
@@ -375,49 +1575,332 @@ fn get_shape(mut cx: FunctionContext) -> JsResult<JsArray> {
         None => return cx.throw_error(GraphError::ProfileNotFound(profile_id).to_string()),
     };
 
-    let len = nodes_js.len(&mut cx);
-    let result = JsArray::new(&mut cx, len as usize);
-    for i in 0..len {
-        let node_id = nodes_js.get::<JsNumber, _, _>(&mut cx, i)?.value(&mut cx) as i64;
+    match profile_graph.validate_route(&node_ids) {
+        Some(index) => Ok(cx.number(index as f64).upcast()),
+        None => Ok(cx.null().upcast()),
+    }
+}
+
+fn get_nodes(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+    let nodes_js = cx.argument::<JsArray>(2)?;
+
+    let node_ids: Vec<i64> = nodes_js
+        .to_vec(&mut cx)?
+        .into_iter()
+        .map(|v| js_to_id(&mut cx, v))
+        .collect::<NeonResult<_>>()?;
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let graph_guard = graph.read().unwrap();
+    let profile_graph = match graph_guard.profiles.get(&profile_id) {
+        Some(pg) => pg,
+        None => return cx.throw_error(GraphError::ProfileNotFound(profile_id).to_string()),
+    };
 
-        if let Some(internal_id) = profile_graph.node_id_map.get(&node_id) {
-            if let Some(node) = profile_graph.nodes.get(*internal_id as usize) {
-                let point_array = JsArray::new(&mut cx, 2);
-                let lon = cx.number(node.lon as f64);
-                let lat = cx.number(node.lat as f64);
-                point_array.set(&mut cx, 0, lon)?;
-                point_array.set(&mut cx, 1, lat)?;
-                result.set(&mut cx, i, point_array)?;
+    let result = JsArray::new(&mut cx, node_ids.len());
+    for (i, node_id) in node_ids.iter().enumerate() {
+        let node = profile_graph
+            .node_id_map
+            .get(node_id)
+            .and_then(|internal_id| profile_graph.nodes.get(*internal_id as usize));
+        match node {
+            Some(node) => {
+                let js_object = node_to_js(&mut cx, profile_graph, node)?;
+                result.set(&mut cx, i as u32, js_object)?;
+            }
+            None => {
+                let null = cx.null();
+                result.set(&mut cx, i as u32, null)?;
             }
         }
     }
+
+    Ok(result)
+}
+
+fn get_shape(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+    let nodes_js = cx.argument::<JsArray>(2)?;
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let graph_guard = graph.read().unwrap();
+    let profile_graph = match graph_guard.profiles.get(&profile_id) {
+        Some(pg) => pg,
+        None => return cx.throw_error(GraphError::ProfileNotFound(profile_id).to_string()),
+    };
+
+    let node_ids: Vec<i64> = nodes_js
+        .to_vec(&mut cx)?
+        .into_iter()
+        .map(|v| js_to_id(&mut cx, v))
+        .collect::<NeonResult<_>>()?;
+
+    let shape = profile_graph.shape(&node_ids);
+    let result = JsArray::new(&mut cx, shape.len());
+    for (i, point) in shape.iter().enumerate() {
+        if let Some([lon, lat]) = point {
+            let point_array = JsArray::new(&mut cx, 2);
+            let lon = cx.number(*lon as f64);
+            let lat = cx.number(*lat as f64);
+            point_array.set(&mut cx, 0, lon)?;
+            point_array.set(&mut cx, 1, lat)?;
+            result.set(&mut cx, i as u32, point_array)?;
+        }
+    }
     Ok(result)
 }
 
+fn get_node_density(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+    let precision = cx.argument::<JsNumber>(2)?.value(&mut cx) as usize;
+
+    if !(1..=12).contains(&precision) {
+        return cx.throw_error("Geohash precision must be between 1 and 12");
+    }
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let graph_guard = graph.read().unwrap();
+    let profile_graph = match graph_guard.profiles.get(&profile_id) {
+        Some(pg) => pg,
+        None => return cx.throw_error(GraphError::ProfileNotFound(profile_id).to_string()),
+    };
+
+    let density = profile_graph.node_density(precision);
+
+    let js_object = cx.empty_object();
+    for (geohash, count) in &density {
+        let count_val = cx.number(*count as f64);
+        js_object.set(&mut cx, geohash.as_str(), count_val)?;
+    }
+
+    Ok(js_object)
+}
+
+fn find_nodes_by_tag(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+    let tag_key = cx.argument::<JsString>(2)?.value(&mut cx);
+    let tag_value = match cx.argument_opt(3) {
+        Some(v) if v.is_a::<JsString, _>(&mut cx) => {
+            Some(v.downcast_or_throw::<JsString, _>(&mut cx)?.value(&mut cx))
+        }
+        _ => None,
+    };
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let graph_guard = graph.read().unwrap();
+    let profile_graph = match graph_guard.profiles.get(&profile_id) {
+        Some(pg) => pg,
+        None => return cx.throw_error(GraphError::ProfileNotFound(profile_id).to_string()),
+    };
+
+    let ids = match profile_graph.nodes_by_tag(&tag_key, tag_value.as_deref()) {
+        Ok(ids) => ids,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+    drop(graph_guard);
+
+    let js_ids = JsArray::new(&mut cx, ids.len());
+    for (i, id) in ids.iter().enumerate() {
+        let js_id = id_to_js(&mut cx, *id);
+        js_ids.set(&mut cx, i as u32, js_id)?;
+    }
+    Ok(js_ids)
+}
+
+fn get_graph_stats(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    let profile_id = cx.argument::<JsString>(1)?.value(&mut cx);
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+
+    let graph_guard = graph.read().unwrap();
+    let profile_graph = match graph_guard.profiles.get(&profile_id) {
+        Some(pg) => pg,
+        None => return cx.throw_error(GraphError::ProfileNotFound(profile_id).to_string()),
+    };
+
+    let js_object = cx.empty_object();
+
+    let nodes = cx.number(profile_graph.nodes.len() as f64);
+    let edges = cx.number(profile_graph.edges.len() as f64);
+    let ways = cx.number(profile_graph.ways.len() as f64);
+    let connectors = cx.number(profile_graph.connector_count as f64);
+
+    js_object.set(&mut cx, "nodes", nodes)?;
+    js_object.set(&mut cx, "edges", edges)?;
+    js_object.set(&mut cx, "ways", ways)?;
+    js_object.set(&mut cx, "connectors", connectors)?;
+
+    Ok(js_object)
+}
+
+fn get_graph_info(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+    let graph_guard = graph.read().unwrap();
+
+    let js_info = cx.empty_object();
+    let built_at = graph_guard
+        .built_at
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let built_at = cx.number(built_at.as_millis() as f64);
+    js_info.set(&mut cx, "builtAt", built_at)?;
+
+    let js_source: Handle<JsValue> = match &graph_guard.source {
+        Some(source) => {
+            let (kind, location) = match source {
+                GraphSource::Overpass { server } => ("overpass", server),
+                GraphSource::Protobuf { url } => ("protobuf", url),
+            };
+            let js_source = cx.empty_object();
+            let kind = cx.string(kind);
+            js_source.set(&mut cx, "type", kind)?;
+            let location = cx.string(location);
+            js_source.set(&mut cx, "location", location)?;
+            js_source.upcast()
+        }
+        None => cx.null().upcast(),
+    };
+    js_info.set(&mut cx, "source", js_source)?;
+
+    let cache_path = cx.string(&graph_guard.cache_path);
+    js_info.set(&mut cx, "cachePath", cache_path)?;
+    let ttl_days = cx.number(graph_guard.ttl_days as f64);
+    js_info.set(&mut cx, "ttlDays", ttl_days)?;
+    let from_cache = cx.boolean(graph_guard.from_cache);
+    js_info.set(&mut cx, "fromCache", from_cache)?;
+
+    Ok(js_info)
+}
+
+fn get_last_build_report(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+
+    let graph = match get_graph(graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
+    };
+    let graph_guard = graph.read().unwrap();
+
+    let js_report = cx.empty_object();
+    let from_cache = cx.boolean(graph_guard.from_cache);
+    js_report.set(&mut cx, "fromCache", from_cache)?;
+
+    let js_profiles = cx.empty_object();
+    for (profile_id, profile_graph) in &graph_guard.profiles {
+        let report = &profile_graph.build_report;
+        let js_profile = cx.empty_object();
+
+        let warnings = JsArray::new(&mut cx, report.warnings.len());
+        for (i, warning) in report.warnings.iter().enumerate() {
+            let warning = cx.string(warning);
+            warnings.set(&mut cx, i as u32, warning)?;
+        }
+        js_profile.set(&mut cx, "warnings", warnings)?;
+
+        let skipped_ways = cx.number(report.skipped_ways as f64);
+        js_profile.set(&mut cx, "skippedWays", skipped_ways)?;
+        let skipped_restrictions = cx.number(report.skipped_restrictions as f64);
+        js_profile.set(&mut cx, "skippedRestrictions", skipped_restrictions)?;
+        let over_clone_cap = cx.number(report.restrictions_over_clone_cap as f64);
+        js_profile.set(&mut cx, "restrictionsOverCloneCap", over_clone_cap)?;
+        let merged_nodes = cx.number(report.merged_nodes as f64);
+        js_profile.set(&mut cx, "mergedNodes", merged_nodes)?;
+        let shared_topology_with: Handle<JsValue> = match &report.shared_topology_with {
+            Some(profile_id) => cx.string(profile_id).upcast(),
+            None => cx.null().upcast(),
+        };
+        js_profile.set(&mut cx, "sharedTopologyWith", shared_topology_with)?;
+
+        let unknown_values = JsArray::new(&mut cx, report.unknown_highway_values.len());
+        for (i, value) in report.unknown_highway_values.iter().enumerate() {
+            let value = cx.string(value);
+            unknown_values.set(&mut cx, i as u32, value)?;
+        }
+        js_profile.set(&mut cx, "unknownHighwayValues", unknown_values)?;
+
+        js_profiles.set(&mut cx, profile_id.as_str(), js_profile)?;
+    }
+    js_report.set(&mut cx, "profiles", js_profiles)?;
+
+    Ok(js_report)
+}
+
 fn unload_graph(mut cx: FunctionContext) -> JsResult<JsBoolean> {
-    let graph_id_to_remove = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
-    let removed_graph = GRAPH_STORAGE
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    Ok(cx.boolean(remove_graph(graph_id)))
+}
+
+/// Removes a graph and its route queues, holding the storage lock throughout
+/// so no queue can be created for it in between.
+fn remove_graph(graph_id: i32) -> bool {
+    let mut storage = GRAPH_STORAGE.lock().unwrap();
+    if storage.remove(&graph_id).is_none() {
+        return false;
+    }
+    ROUTE_QUEUES
         .lock()
         .unwrap()
-        .remove(&graph_id_to_remove)
-        .is_some();
+        .retain(|_, queue| queue.graph_id != graph_id);
+    drop(storage);
+    true
+}
 
-    if !removed_graph {
-        return Ok(cx.boolean(false));
+fn set_graph_pinned(mut cx: FunctionContext, pinned: bool) -> JsResult<JsBoolean> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    match GRAPH_STORAGE.lock().unwrap().get_mut(&graph_id) {
+        Some(stored) => {
+            let changed = stored.pinned != pinned;
+            stored.pinned = pinned;
+            Ok(cx.boolean(changed))
+        }
+        None => cx.throw_error(GraphError::GraphNotFound(graph_id).to_string()),
     }
+}
+
+fn pin_graph(cx: FunctionContext) -> JsResult<JsBoolean> {
+    set_graph_pinned(cx, true)
+}
 
-    let mut queues = ROUTE_QUEUES.lock().unwrap();
-    let queue_ids_to_remove: Vec<i32> = queues
-        .iter()
-        .filter(|(_, queue)| queue.graph_id == graph_id_to_remove)
-        .map(|(id, _)| *id)
-        .collect();
+fn unpin_graph(cx: FunctionContext) -> JsResult<JsBoolean> {
+    set_graph_pinned(cx, false)
+}
 
-    for queue_id in queue_ids_to_remove {
-        queues.remove(&queue_id);
+fn is_graph_pinned(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+    let graph_id = cx.argument::<JsNumber>(0)?.value(&mut cx) as i32;
+    match GRAPH_STORAGE.lock().unwrap().get(&graph_id) {
+        Some(stored) => Ok(cx.boolean(stored.pinned)),
+        None => cx.throw_error(GraphError::GraphNotFound(graph_id).to_string()),
     }
-
-    Ok(cx.boolean(true))
 }
 
 fn create_route_queue(mut cx: FunctionContext) -> JsResult<JsNumber> {
@@ -429,33 +1912,39 @@ fn create_route_queue(mut cx: FunctionContext) -> JsResult<JsNumber> {
         None
     };
 
-    if !GRAPH_STORAGE.lock().unwrap().contains_key(&graph_id) {
-        return cx.throw_error(GraphError::GraphNotFound(graph_id).to_string());
-    }
-
-    let graph_arc = GRAPH_STORAGE
-        .lock()
-        .unwrap()
-        .get(&graph_id)
-        .unwrap()
-        .clone();
-    let graph_container = graph_arc.read().unwrap();
-    if !graph_container.profiles.contains_key(&profile_id) {
-        return cx.throw_error(GraphError::ProfileNotFound(profile_id).to_string());
+    match register_route_queue(graph_id, profile_id, max_concurrency) {
+        Ok(queue_id) => Ok(cx.number(queue_id as f64)),
+        Err(e) => cx.throw_error(e.to_string()),
     }
+}
 
-    let queue_id = unsafe {
-        let id = NEXT_QUEUE_ID;
-        NEXT_QUEUE_ID += 1;
-        id
+fn register_route_queue(
+    graph_id: i32,
+    profile_id: String,
+    max_concurrency: Option<usize>,
+) -> Result<i32> {
+    let storage = GRAPH_STORAGE.lock().unwrap();
+    let has_profile = match storage.get(&graph_id) {
+        Some(stored) => stored
+            .graph
+            .read()
+            .unwrap()
+            .profiles
+            .contains_key(&profile_id),
+        None => return Err(GraphError::GraphNotFound(graph_id)),
     };
+    if !has_profile {
+        return Err(GraphError::ProfileNotFound(profile_id));
+    }
 
+    let queue_id = NEXT_QUEUE_ID.fetch_add(1, Ordering::Relaxed);
     let route_queue = RouteQueue::new(graph_id, profile_id, max_concurrency);
     ROUTE_QUEUES
         .lock()
         .unwrap()
         .insert(queue_id, Arc::new(route_queue));
-    Ok(cx.number(queue_id as f64))
+    drop(storage);
+    Ok(queue_id)
 }
 
 fn enqueue_route(mut cx: FunctionContext) -> JsResult<JsString> {
@@ -467,10 +1956,10 @@ fn enqueue_route(mut cx: FunctionContext) -> JsResult<JsString> {
     let mut waypoints = Vec::with_capacity(js_vec.len());
 
     for (i, v) in js_vec.iter().enumerate() {
-        let num = v.downcast::<JsNumber, _>(&mut cx).or_else(|_| {
-            cx.throw_error(format!("Waypoint at index {} is not a valid number", i))
-        })?;
-        waypoints.push(num.value(&mut cx) as i64);
+        if !v.is_a::<JsNumber, _>(&mut cx) && !v.is_a::<JsString, _>(&mut cx) {
+            return cx.throw_error(format!("Waypoint at index {} is not a valid node ID", i));
+        }
+        waypoints.push(js_to_id(&mut cx, *v)?);
     }
 
     let queue = match ROUTE_QUEUES.lock().unwrap().get(&queue_id) {
@@ -494,9 +1983,9 @@ fn process_queue(mut cx: FunctionContext) -> JsResult<JsUndefined> {
         None => return cx.throw_error(format!("RouteQueue with ID {} not found", queue_id)),
     };
 
-    let graph_container = match GRAPH_STORAGE.lock().unwrap().get(&queue.graph_id) {
-        Some(g) => g.clone(),
-        None => return cx.throw_error(GraphError::GraphNotFound(queue.graph_id).to_string()),
+    let graph_container = match get_graph(queue.graph_id) {
+        Ok(g) => g,
+        Err(e) => return cx.throw_error(e.to_string()),
     };
 
     let channel = cx.channel();
@@ -534,13 +2023,39 @@ fn clear_route_queue(mut cx: FunctionContext) -> JsResult<JsBoolean> {
 #[neon::main]
 fn main(mut cx: ModuleContext) -> NeonResult<()> {
     cx.export_function("loadGraph", load_graph)?;
+    cx.export_function("validateLoadOptions", validate_load_options)?;
     cx.export_function("unloadGraph", unload_graph)?;
+    cx.export_function("pinGraph", pin_graph)?;
+    cx.export_function("unpinGraph", unpin_graph)?;
+    cx.export_function("isGraphPinned", is_graph_pinned)?;
+    cx.export_function("getLastBuildReport", get_last_build_report)?;
+    cx.export_function("getGraphInfo", get_graph_info)?;
     cx.export_function("getRoute", get_route)?;
+    cx.export_function("getRouteOsrm", get_route_osrm)?;
+    cx.export_function("compareRoutes", compare_routes)?;
+    cx.export_function("getOptimizedRoute", get_optimized_route)?;
+    cx.export_function("getMatrix", get_matrix)?;
+    cx.export_function("getIsochronePolygon", get_isochrone_polygon)?;
+    cx.export_function("getIsochroneBands", get_isochrone_bands)?;
+    cx.export_function("findReachablePois", find_reachable_pois)?;
+    cx.export_function("getNearestFacilityCost", get_nearest_facility_cost)?;
     cx.export_function("getNearestNode", get_nearest_node)?;
+    cx.export_function("getRepresentativeNode", get_representative_node)?;
+    cx.export_function("getNearestWayGeometry", get_nearest_way_geometry)?;
+    cx.export_function("getNearestWayMatching", get_nearest_way_matching)?;
     cx.export_function("getNodesInRadius", get_nodes_in_radius)?;
     cx.export_function("getWaysInRadius", get_ways_in_radius)?;
     cx.export_function("getNode", get_node)?;
+    cx.export_function("getNodes", get_nodes)?;
+    cx.export_function("getRestrictionsAtNode", get_restrictions_at_node)?;
+    cx.export_function("validateRoute", validate_route)?;
     cx.export_function("getShape", get_shape)?;
+    cx.export_function("getNodeDensity", get_node_density)?;
+    cx.export_function("findNodesByTag", find_nodes_by_tag)?;
+    cx.export_function("getGraphStats", get_graph_stats)?;
+    cx.export_function("selfTest", self_test)?;
+    cx.export_function("warmGraph", warm_graph)?;
+    cx.export_function("applyEdgeWeights", apply_edge_weights)?;
 
     cx.export_function("createRouteQueue", create_route_queue)?;
     cx.export_function("enqueueRoute", enqueue_route)?;
@@ -550,3 +2065,160 @@ fn main(mut cx: ModuleContext) -> NeonResult<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_number_id, decode_text_id, encode_id, load_or_build_graph_sync,
+        register_route_queue, remove_graph, store_graph, JsId, GRAPH_STORAGE, MAX_SAFE_INTEGER,
+        ROUTE_QUEUES,
+    };
+    use crate::graph::{GraphContainer, ProcessedGraph};
+    use crate::test_support::{build_graph, options, osm, profile, tagged_node, way};
+    use rustc_hash::FxHashSet;
+    use std::fs::{self, File};
+    use std::thread;
+
+    fn round_trip(id: i64) -> Option<i64> {
+        match encode_id(id) {
+            JsId::Number(number) => decode_number_id(number),
+            JsId::Text(text) => decode_text_id(&text),
+        }
+    }
+
+    #[test]
+    fn ids_round_trip_through_js() {
+        for id in [
+            0,
+            1,
+            -1,
+            4_294_967_296,
+            -4_294_967_296,
+            MAX_SAFE_INTEGER,
+            -MAX_SAFE_INTEGER,
+        ] {
+            assert_eq!(encode_id(id), JsId::Number(id as f64));
+            assert_eq!(round_trip(id), Some(id));
+        }
+        for id in [
+            MAX_SAFE_INTEGER + 1,
+            -MAX_SAFE_INTEGER - 1,
+            9_007_199_254_740_993,
+            i64::MAX,
+            i64::MIN,
+        ] {
+            assert_eq!(encode_id(id), JsId::Text(id.to_string()));
+            assert_eq!(round_trip(id), Some(id));
+        }
+    }
+
+    #[test]
+    fn rejects_ids_that_lose_precision() {
+        assert_eq!(decode_number_id(9_007_199_254_740_992.0), None);
+        assert_eq!(decode_number_id(-9_007_199_254_740_992.0), None);
+        assert_eq!(decode_number_id(1.5), None);
+        assert_eq!(decode_text_id(" -42 "), Some(-42));
+        assert_eq!(decode_text_id("9223372036854775808"), None);
+        assert_eq!(decode_text_id("node"), None);
+    }
+
+    #[test]
+    fn queues_survive_concurrent_load_and_unload() {
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                thread::spawn(|| {
+                    let mut queue_ids = Vec::new();
+                    for _ in 0..200 {
+                        let mut container = GraphContainer::new();
+                        container
+                            .profiles
+                            .insert("p".to_string(), ProcessedGraph::new());
+                        let graph_id = store_graph(container);
+                        let neighbour = graph_id - 1;
+                        for id in [graph_id, neighbour] {
+                            if let Ok(queue_id) = register_route_queue(id, "p".to_string(), Some(1))
+                            {
+                                queue_ids.push(queue_id);
+                            }
+                        }
+                        remove_graph(neighbour);
+                    }
+                    queue_ids
+                })
+            })
+            .collect();
+
+        let mut queue_ids = FxHashSet::default();
+        for handle in handles {
+            for queue_id in handle.join().unwrap() {
+                assert!(queue_ids.insert(queue_id), "queue id {} reused", queue_id);
+            }
+        }
+
+        let storage = GRAPH_STORAGE.lock().unwrap();
+        for queue in ROUTE_QUEUES.lock().unwrap().values() {
+            assert!(
+                storage.contains_key(&queue.graph_id),
+                "queue left behind for unloaded graph {}",
+                queue.graph_id
+            );
+        }
+    }
+
+    #[test]
+    fn tag_index_loads_from_cache_without_rebuild() {
+        let xml = osm(&[
+            tagged_node(1, 0.0, 0.0, &[]),
+            tagged_node(2, 0.001, 0.0, &[("amenity", "cafe")]),
+            tagged_node(3, 0.002, 0.0, &[("amenity", "bench")]),
+            way(10, &[1, 2, 3], &[("highway", "residential")]),
+        ]);
+        let mut container = GraphContainer::new();
+        container
+            .profiles
+            .insert("p".to_string(), build_graph(&xml, "{}", "{}"));
+        assert_eq!(container.index_tags(&["amenity".to_string()]), 1);
+        // Only a cached index can hold this entry, so it shows whether a
+        // load rebuilt the index.
+        container
+            .profiles
+            .get_mut("p")
+            .unwrap()
+            .tag_index
+            .nodes
+            .get_mut("amenity")
+            .unwrap()
+            .insert("cached".to_string(), vec![42]);
+
+        let path = std::env::temp_dir().join(format!("tag-index-{}.bin", std::process::id()));
+        container.write_cache(File::create(&path).unwrap()).unwrap();
+        let load = |index_tags: serde_json::Value| {
+            let json = serde_json::json!({
+                "file_path": path,
+                "ttl_days": 1,
+                "index_tags": index_tags,
+            });
+            load_or_build_graph_sync(options(&json.to_string(), vec![profile("{}")])).unwrap()
+        };
+
+        let cached = load(serde_json::json!(["amenity"]));
+        let graph = &cached.profiles["p"];
+        assert!(cached.from_cache);
+        assert_eq!(
+            graph.nodes_by_tag("amenity", Some("cafe")).unwrap(),
+            vec![2]
+        );
+        assert_eq!(graph.nodes_by_tag("amenity", None).unwrap(), vec![2, 3, 42]);
+        assert_eq!(
+            graph.nodes_by_tag("amenity", Some("cached")).unwrap(),
+            vec![42]
+        );
+
+        let rebuilt = load(serde_json::json!(["amenity", "shop"]));
+        let graph = &rebuilt.profiles["p"];
+        assert_eq!(graph.nodes_by_tag("amenity", None).unwrap(), vec![2, 3]);
+        assert!(graph.nodes_by_tag("shop", None).unwrap().is_empty());
+        assert!(graph.nodes_by_tag("highway", None).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -7,6 +7,12 @@ use std::io::Cursor; // <-- NOWY IMPORT
 use xml::attribute::OwnedAttribute;
 use xml::reader::{EventReader, XmlEvent};
 
+pub type OsmData = (
+    HashMap<i64, Node>,
+    HashMap<i64, Way>,
+    HashMap<i64, Relation>,
+);
+
 pub fn fetch_from_overpass(
     query: &str,
     server: &str,
@@ -65,13 +71,7 @@ pub fn fetch_pbf_to_memory(options: &ProtobufOptions) -> Result<Bytes> {
     )))
 }
 
-pub fn parse_osm_pbf(
-    pbf_data: &[u8],
-) -> Result<(
-    HashMap<i64, Node>,
-    HashMap<i64, Way>,
-    HashMap<i64, Relation>,
-)> {
+pub fn parse_osm_pbf(pbf_data: &[u8]) -> Result<OsmData> {
     let cursor = Cursor::new(pbf_data);
     let reader = ElementReader::new(cursor);
 
@@ -134,9 +134,7 @@ pub fn parse_osm_pbf(
                                     member_type: match m.member_type {
                                         osmpbf::RelMemberType::Node => "node".to_string(),
                                         osmpbf::RelMemberType::Way => "way".to_string(),
-                                        osmpbf::RelMemberType::Relation => {
-                                            "relation".to_string()
-                                        }
+                                        osmpbf::RelMemberType::Relation => "relation".to_string(),
                                     },
                                     ref_id: m.member_id,
                                     role: m.role().unwrap_or("").to_string(),
@@ -164,13 +162,7 @@ pub fn parse_osm_pbf(
     Ok((nodes, ways, relations))
 }
 
-pub fn parse_osm_xml(
-    xml_data: &str,
-) -> Result<(
-    HashMap<i64, Node>,
-    HashMap<i64, Way>,
-    HashMap<i64, Relation>,
-)> {
+pub fn parse_osm_xml(xml_data: &str) -> Result<OsmData> {
     let parser = EventReader::new(xml_data.as_bytes());
     let mut nodes = HashMap::new();
     let mut ways = HashMap::new();
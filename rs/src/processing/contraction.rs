@@ -0,0 +1,194 @@
+use super::{EdgeCost, GraphBuilder};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+impl<'a> GraphBuilder<'a> {
+    pub(super) fn contract_shape_nodes(&mut self) {
+        let mut incoming: FxHashMap<u32, FxHashSet<u32>> = FxHashMap::default();
+        for (&from, targets) in &self.temp_edges {
+            for &to in targets.keys() {
+                incoming.entry(to).or_default().insert(from);
+            }
+        }
+
+        let mut external_counts: FxHashMap<i64, u32> = FxHashMap::default();
+        for node in &self.nodes {
+            *external_counts.entry(node.external_id).or_insert(0) += 1;
+        }
+
+        let mut restricted: FxHashSet<i64> = FxHashSet::default();
+        for (&(from, via), rule) in &self.turn_rules {
+            restricted.insert(from);
+            restricted.insert(via);
+            restricted.extend(rule.only);
+            restricted.extend(&rule.forbidden);
+        }
+
+        let mut removed: FxHashSet<u32> = FxHashSet::default();
+        for v in 0..self.nodes.len() as u32 {
+            let node = &self.nodes[v as usize];
+            if !node.tags.is_empty()
+                || external_counts[&node.external_id] > 1
+                || restricted.contains(&node.external_id)
+            {
+                continue;
+            }
+
+            let mut out: Vec<u32> = self
+                .temp_edges
+                .get(&v)
+                .map(|e| e.keys().copied().collect())
+                .unwrap_or_default();
+            let mut inc: Vec<u32> = incoming
+                .get(&v)
+                .map(|e| e.iter().copied().collect())
+                .unwrap_or_default();
+            out.sort_unstable();
+            inc.sort_unstable();
+
+            let bypasses = if out.len() == 2 && out == inc {
+                vec![(out[0], out[1]), (out[1], out[0])]
+            } else if out.len() == 1 && inc.len() == 1 && out[0] != inc[0] {
+                vec![(inc[0], out[0])]
+            } else {
+                continue;
+            };
+
+            let contractible = bypasses.iter().all(|&(from, to)| {
+                let from_edges = &self.temp_edges[&from];
+                let (first, second) = (from_edges[&v], self.temp_edges[&v][&to]);
+                !from_edges.contains_key(&to)
+                    && first.way == second.way
+                    && (first.cost as u32 + second.cost as u32) <= u16::MAX as u32
+            });
+            if !contractible {
+                continue;
+            }
+
+            let via_point = [node.lon, node.lat];
+            for &(from, to) in &bypasses {
+                let first_cost = self.temp_edges.get_mut(&from).unwrap().remove(&v).unwrap();
+                let second_cost = self.temp_edges[&v][&to];
+
+                let mut shape = self.edge_shapes.remove(&(from, v)).unwrap_or_default();
+                shape.push(via_point);
+                shape.extend(self.edge_shapes.remove(&(v, to)).unwrap_or_default());
+
+                self.temp_edges.get_mut(&from).unwrap().insert(
+                    to,
+                    EdgeCost {
+                        cost: first_cost.cost + second_cost.cost,
+                        time: first_cost.time.saturating_add(second_cost.time),
+                        way: first_cost.way,
+                    },
+                );
+                self.edge_shapes.insert((from, to), shape);
+
+                let to_incoming = incoming.get_mut(&to).unwrap();
+                to_incoming.remove(&v);
+                to_incoming.insert(from);
+            }
+
+            self.temp_edges.remove(&v);
+            incoming.remove(&v);
+            removed.insert(v);
+        }
+
+        if removed.is_empty() {
+            return;
+        }
+
+        let mut new_ids = vec![u32::MAX; self.nodes.len()];
+        let mut next_id = 0;
+        for (old_id, new_id) in new_ids.iter_mut().enumerate() {
+            if !removed.contains(&(old_id as u32)) {
+                *new_id = next_id;
+                next_id += 1;
+            }
+        }
+
+        self.nodes = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .filter(|n| !removed.contains(&n.id))
+            .map(|mut n| {
+                n.id = new_ids[n.id as usize];
+                n
+            })
+            .collect();
+        self.next_internal_id = next_id;
+
+        self.node_map.retain(|_, id| !removed.contains(id));
+        for id in self.node_map.values_mut() {
+            *id = new_ids[*id as usize];
+        }
+
+        self.temp_edges = std::mem::take(&mut self.temp_edges)
+            .into_iter()
+            .map(|(from, targets)| {
+                let targets = targets
+                    .into_iter()
+                    .map(|(to, cost)| (new_ids[to as usize], cost))
+                    .collect();
+                (new_ids[from as usize], targets)
+            })
+            .collect();
+
+        self.edge_shapes = std::mem::take(&mut self.edge_shapes)
+            .into_iter()
+            .map(|((from, to), shape)| ((new_ids[from as usize], new_ids[to as usize]), shape))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{build_graph, node, osm, way};
+
+    #[test]
+    fn folds_shape_nodes_into_edge_geometry() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.001),
+            node(3, 0.02, 0.002),
+            node(4, 0.03, 0.0),
+            way(10, &[1, 2, 3, 4], &[("highway", "residential")]),
+        ]);
+        let graph = build_graph(&xml, r#"{"contract_shape_nodes":true}"#, "{}");
+
+        assert!(!graph.node_id_map.contains_key(&2));
+        assert!(!graph.node_id_map.contains_key(&3));
+        let (first, last) = (graph.node_id_map[&1], graph.node_id_map[&4]);
+        let forward = graph.find_edge(first, 4).unwrap();
+        assert_eq!(
+            graph.edge_geometry(forward),
+            &[[0.01, 0.001], [0.02, 0.002]]
+        );
+        let backward = graph.find_edge(last, 1).unwrap();
+        assert_eq!(
+            graph.edge_geometry(backward),
+            &[[0.02, 0.002], [0.01, 0.001]]
+        );
+    }
+
+    #[test]
+    fn keeps_ways_apart_at_shared_shape_node() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.02, 0.0),
+            way(10, &[1, 2], &[("highway", "residential")]),
+            way(11, &[2, 3], &[("highway", "residential"), ("toll", "yes")]),
+        ]);
+        let graph = build_graph(&xml, r#"{"contract_shape_nodes":true}"#, "{}");
+
+        assert!(graph.node_id_map.contains_key(&2));
+        let mut edge_ways: Vec<i64> = graph
+            .edge_ways
+            .iter()
+            .map(|&way_idx| graph.ways[way_idx as usize].osm_id)
+            .collect();
+        edge_ways.sort_unstable();
+        edge_ways.dedup();
+        assert_eq!(edge_ways, vec![10, 11]);
+        assert!(graph.route_advisories(&[1, 2, 3]).unwrap().toll);
+    }
+}
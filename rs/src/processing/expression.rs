@@ -0,0 +1,274 @@
+use super::StringInterner;
+use rustc_hash::FxHashMap;
+
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum Expression {
+    Number(f64),
+    Base,
+    Has(u32, Option<u32>),
+    Neg(Box<Expression>),
+    Binary(Op, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    pub(super) fn compile(
+        source: &str,
+        interner: &mut StringInterner,
+    ) -> std::result::Result<Self, String> {
+        let mut parser = Parser {
+            chars: source.chars().collect(),
+            pos: 0,
+            interner,
+        };
+        let expression = parser.parse_sum()?;
+        parser.skip_whitespace();
+        if parser.pos < parser.chars.len() {
+            return Err(format!(
+                "unexpected '{}' at position {}",
+                parser.chars[parser.pos], parser.pos
+            ));
+        }
+        Ok(expression)
+    }
+
+    pub(super) fn evaluate(&self, base: f64, tags: &FxHashMap<u32, u32>) -> f64 {
+        match self {
+            Expression::Number(value) => *value,
+            Expression::Base => base,
+            Expression::Has(key, value) => {
+                let present = match (tags.get(key), value) {
+                    (Some(actual), Some(expected)) => actual == expected,
+                    (Some(_), None) => true,
+                    (None, _) => false,
+                };
+                if present {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Expression::Neg(inner) => -inner.evaluate(base, tags),
+            Expression::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.evaluate(base, tags), rhs.evaluate(base, tags));
+                match op {
+                    Op::Add => lhs + rhs,
+                    Op::Sub => lhs - rhs,
+                    Op::Mul => lhs * rhs,
+                    Op::Div => lhs / rhs,
+                }
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    interner: &'a mut StringInterner,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, expected: char) -> std::result::Result<(), String> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => Err(format!(
+                "expected '{}' but found '{}' at position {}",
+                expected, c, self.pos
+            )),
+            None => Err(format!("expected '{}' but reached the end", expected)),
+        }
+    }
+
+    fn parse_sum(&mut self) -> std::result::Result<Expression, String> {
+        let mut lhs = self.parse_product()?;
+        loop {
+            let op = match self.peek() {
+                Some('+') => Op::Add,
+                Some('-') => Op::Sub,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            let rhs = self.parse_product()?;
+            lhs = Expression::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn parse_product(&mut self) -> std::result::Result<Expression, String> {
+        let mut lhs = self.parse_factor()?;
+        loop {
+            let op = match self.peek() {
+                Some('*') => Op::Mul,
+                Some('/') => Op::Div,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            let rhs = self.parse_factor()?;
+            lhs = Expression::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn parse_factor(&mut self) -> std::result::Result<Expression, String> {
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(Expression::Neg(Box::new(self.parse_factor()?)))
+            }
+            Some('(') => {
+                self.pos += 1;
+                let inner = self.parse_sum()?;
+                self.expect(')')?;
+                Ok(inner)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.parse_number(),
+            Some(c) if c.is_ascii_alphabetic() => {
+                let start = self.pos;
+                while self.pos < self.chars.len()
+                    && (self.chars[self.pos].is_ascii_alphanumeric() || self.chars[self.pos] == '_')
+                {
+                    self.pos += 1;
+                }
+                let ident: String = self.chars[start..self.pos].iter().collect();
+                match ident.as_str() {
+                    "base" => Ok(Expression::Base),
+                    "has" => self.parse_has(),
+                    _ => Err(format!(
+                        "unknown identifier '{}' at position {}",
+                        ident, start
+                    )),
+                }
+            }
+            Some(c) => Err(format!("unexpected '{}' at position {}", c, self.pos)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    fn parse_number(&mut self) -> std::result::Result<Expression, String> {
+        let start = self.pos;
+        while self.pos < self.chars.len()
+            && (self.chars[self.pos].is_ascii_digit() || self.chars[self.pos] == '.')
+        {
+            self.pos += 1;
+        }
+        let literal: String = self.chars[start..self.pos].iter().collect();
+        literal
+            .parse::<f64>()
+            .map(Expression::Number)
+            .map_err(|_| format!("invalid number '{}' at position {}", literal, start))
+    }
+
+    fn parse_has(&mut self) -> std::result::Result<Expression, String> {
+        self.expect('(')?;
+        let start = self.pos;
+        while self.pos < self.chars.len() && self.chars[self.pos] != ')' {
+            self.pos += 1;
+        }
+        if self.pos == self.chars.len() {
+            return Err("unterminated has(...)".to_string());
+        }
+        let argument: String = self.chars[start..self.pos].iter().collect();
+        self.pos += 1;
+
+        let (key, value) = match argument.split_once('=') {
+            Some((key, value)) => (key.trim(), Some(value.trim())),
+            None => (argument.trim(), None),
+        };
+        if key.is_empty() {
+            return Err(format!("has(...) needs a tag key at position {}", start));
+        }
+
+        Ok(Expression::Has(
+            self.interner.intern(key),
+            value.map(|value| self.interner.intern(value)),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(source: &str, base: f64, tags: &[(&str, &str)]) -> f64 {
+        let mut interner = StringInterner::new();
+        let expression = Expression::compile(source, &mut interner).unwrap();
+        let tags = tags
+            .iter()
+            .map(|(k, v)| (interner.intern(k), interner.intern(v)))
+            .collect();
+        expression.evaluate(base, &tags)
+    }
+
+    fn compile_error(source: &str) -> String {
+        Expression::compile(source, &mut StringInterner::new()).unwrap_err()
+    }
+
+    #[test]
+    fn follows_operator_precedence() {
+        assert_eq!(evaluate("1 + 2 * 3", 0.0, &[]), 7.0);
+        assert_eq!(evaluate("(1 + 2) * 3", 0.0, &[]), 9.0);
+        assert_eq!(evaluate("10 - 4 - 3", 0.0, &[]), 3.0);
+        assert_eq!(evaluate("12 / 3 / 2", 0.0, &[]), 2.0);
+        assert_eq!(evaluate("-2 * 3 + 1", 0.0, &[]), -5.0);
+    }
+
+    #[test]
+    fn has_matches_key_and_value() {
+        let tags = [("highway", "residential"), ("lit", "yes")];
+        assert_eq!(evaluate("has(highway=residential)", 0.0, &tags), 1.0);
+        assert_eq!(evaluate("has(highway = primary)", 0.0, &tags), 0.0);
+        assert_eq!(evaluate("has(lit)", 0.0, &tags), 1.0);
+        assert_eq!(evaluate("has(surface)", 0.0, &tags), 0.0);
+        assert_eq!(evaluate("1 + 2 * has(lit=yes)", 0.0, &tags), 3.0);
+    }
+
+    #[test]
+    fn base_is_the_table_penalty() {
+        assert_eq!(evaluate("base", 1.5, &[]), 1.5);
+        assert_eq!(evaluate("base * 2 + 1", 1.5, &[]), 4.0);
+    }
+
+    #[test]
+    fn division_by_zero_is_not_finite() {
+        assert_eq!(evaluate("base / 0", 2.0, &[]), f64::INFINITY);
+        assert!(evaluate("0 / 0", 2.0, &[]).is_nan());
+        assert_eq!(evaluate("base / has(lit)", 2.0, &[]), f64::INFINITY);
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        assert_eq!(compile_error("1 +"), "unexpected end of expression");
+        assert_eq!(compile_error("1 2"), "unexpected '2' at position 2");
+        assert_eq!(
+            compile_error("speed"),
+            "unknown identifier 'speed' at position 0"
+        );
+        assert_eq!(compile_error("(1 + 2"), "expected ')' but reached the end");
+        assert_eq!(compile_error("has(highway"), "unterminated has(...)");
+        assert_eq!(
+            compile_error("has(=yes)"),
+            "has(...) needs a tag key at position 4"
+        );
+        assert_eq!(compile_error("1..2"), "invalid number '1..2' at position 0");
+    }
+}
@@ -1,9 +1,19 @@
 use crate::core::errors::{GraphError, Result};
-use crate::core::types::{Node, Profile, Relation, RelationMember, Way};
-use crate::graph::{ProcessedGraph, RouteNode, WayInfo};
-use crate::routing::distance;
+use crate::core::types::{
+    LoadOptions, Node, PenaltyMatch, Profile, Relation, RelationMember, UnknownHighwayPolicy, Way,
+};
+use crate::graph::{
+    parse_length_m, point_in_polygon, BuildReport, ProcessedGraph, RestrictionInfo, RouteNode,
+    TurnRule, WayInfo,
+};
+use crate::routing::{distance, meters_per_degree, EARTH_RADIUS_KM};
+use expression::Expression;
 use rustc_hash::FxHashMap;
 use std::collections::HashMap;
+use std::sync::Arc;
+
+mod contraction;
+mod expression;
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 enum TurnRestriction {
@@ -12,17 +22,51 @@ enum TurnRestriction {
     Mandatory,
 }
 
+const DEFAULT_ACCESS_HIERARCHY: &[(&str, &str)] = &[
+    ("motorcar", "motor_vehicle"),
+    ("motorcycle", "motor_vehicle"),
+    ("moped", "motor_vehicle"),
+    ("mofa", "motor_vehicle"),
+    ("goods", "motor_vehicle"),
+    ("hgv", "motor_vehicle"),
+    ("bus", "psv"),
+    ("taxi", "psv"),
+    ("psv", "motor_vehicle"),
+    ("motor_vehicle", "vehicle"),
+    ("bicycle", "vehicle"),
+    ("vehicle", "access"),
+    ("foot", "access"),
+    ("horse", "access"),
+];
+
+const DEFAULT_SPEED_KMH: f64 = 50.0;
+
+#[derive(Clone, Copy, Debug)]
+struct EdgeCost {
+    cost: u16,
+    time: u32,
+    way: u32,
+}
+
+impl EdgeCost {
+    fn new(distance_km: f32, penalty: f64, speed_kmh: f64, way: u32) -> Self {
+        EdgeCost {
+            cost: (distance_km * penalty as f32 * 1000.0) as u16,
+            time: (distance_km as f64 / speed_kmh * 3_600_000.0) as u32,
+            way,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
 struct StringInterner {
-    map: FxHashMap<String, u32>,
-    vec: Vec<String>,
+    map: Arc<FxHashMap<String, u32>>,
+    vec: Arc<Vec<String>>,
 }
 
 impl StringInterner {
     fn new() -> Self {
-        Self {
-            map: FxHashMap::default(),
-            vec: Vec::new(),
-        }
+        Self::default()
     }
 
     fn intern(&mut self, s: &str) -> u32 {
@@ -31,19 +75,70 @@ impl StringInterner {
         }
         let id = self.vec.len() as u32;
         let s_owned = s.to_owned();
-        self.map.insert(s_owned.clone(), id);
-        self.vec.push(s_owned);
+        Arc::make_mut(&mut self.map).insert(s_owned.clone(), id);
+        Arc::make_mut(&mut self.vec).push(s_owned);
         id
     }
 }
 
+pub struct SharedInterner {
+    interner: StringInterner,
+}
+
+impl SharedInterner {
+    pub fn build(
+        profiles: &[Profile],
+        raw_nodes: &HashMap<i64, Node>,
+        raw_ways: &HashMap<i64, Way>,
+        raw_relations: &HashMap<i64, Relation>,
+    ) -> Self {
+        let mut interner = StringInterner::new();
+        for profile in profiles {
+            GraphBuilder::intern_profile(profile, &mut interner);
+            if let Some(source) = &profile.penalty_expression {
+                let _ = Expression::compile(source, &mut interner);
+            }
+        }
+
+        let tags = raw_nodes
+            .values()
+            .map(|node| &node.tags)
+            .chain(raw_ways.values().map(|way| &way.tags))
+            .chain(raw_relations.values().map(|relation| &relation.tags));
+        for tags in tags {
+            for (k, v) in tags {
+                interner.intern(k);
+                interner.intern(v);
+            }
+        }
+
+        SharedInterner { interner }
+    }
+}
+
 struct InternedProfile {
+    id: String,
     key: u32,
     penalties: FxHashMap<u32, f64>,
+    penalty_match: PenaltyMatch,
+    penalty_patterns: Vec<(String, f64)>,
     default_penalty: Option<u32>,
+    speeds: FxHashMap<u32, f64>,
+    default_speed: f64,
     access_tags: Vec<u32>,
+    access_hierarchy: Vec<u32>,
     oneway_tags: Vec<u32>,
     except_tags: Vec<u32>,
+    pedestrian_areas: bool,
+    exclude_motorroads: bool,
+    penalty_expression: Option<String>,
+    ignore_access_restrictions: bool,
+    ignore_turn_restrictions: bool,
+    turn_angle_weight: f64,
+    unlit_multiplier: f64,
+    min_width: Option<f64>,
+    narrow_way_multiplier: Option<f64>,
+    unknown_highway_policy: UnknownHighwayPolicy,
 }
 
 pub struct GraphBuilder<'a> {
@@ -52,16 +147,35 @@ pub struct GraphBuilder<'a> {
     raw_relations: &'a HashMap<i64, Relation>,
 
     interner: StringInterner,
+    shared_strings: Option<Arc<Vec<String>>>,
     profile: InternedProfile,
+    snap_tolerance_m: f64,
+    contract_shape_nodes: bool,
+    edge_based: bool,
+    max_clones_per_node: Option<u32>,
+    merge_coincident_nodes: bool,
+    earth_radius_km: f32,
 
     node_map: FxHashMap<i64, u32>,
+    node_aliases: FxHashMap<i64, i64>,
+    coordinate_nodes: FxHashMap<(i64, i64), u32>,
     next_internal_id: u32,
     nodes: Vec<RouteNode>,
-    temp_edges: FxHashMap<u32, FxHashMap<u32, u16>>,
+    temp_edges: FxHashMap<u32, FxHashMap<u32, EdgeCost>>,
     processed_ways: Vec<(i64, Vec<i64>, FxHashMap<u32, u32>)>,
+    way_indices: FxHashMap<i64, u32>,
+    way_speeds: Vec<f32>,
 
     way_node_map: FxHashMap<i64, Vec<i64>>,
     via_node_clones: FxHashMap<(u32, i64), u32>,
+    clone_counts: FxHashMap<i64, u32>,
+    connector_count: u32,
+    max_speed_kmh: f64,
+    edge_shapes: FxHashMap<(u32, u32), Vec<[f32; 2]>>,
+    turn_rules: FxHashMap<(i64, i64), TurnRule>,
+    restrictions: Vec<RestrictionInfo>,
+    report: BuildReport,
+    penalty_expression: Option<Expression>,
 }
 
 struct GraphChange<'a, 'b> {
@@ -111,10 +225,41 @@ impl<'a, 'b> GraphChange<'a, 'b> {
         self.builder
             .via_node_clones
             .insert((from_node_id, target_osm_id), cloned_internal_id);
+        *self.builder.clone_counts.entry(target_osm_id).or_insert(0) += 1;
 
         Ok(cloned_internal_id)
     }
 
+    fn node_over_clone_cap(&self, restriction_path: &[i64]) -> Option<i64> {
+        let cap = self.builder.max_clones_per_node?;
+        if restriction_path.len() < 3 {
+            return None;
+        }
+
+        let mut current_node_id = self.builder.node_map.get(&restriction_path[0]).copied();
+        for &via_osm_id in &restriction_path[1..restriction_path.len() - 1] {
+            let existing = current_node_id.and_then(|from| {
+                self.builder
+                    .via_node_clones
+                    .get(&(from, via_osm_id))
+                    .copied()
+            });
+            if existing.is_none()
+                && self
+                    .builder
+                    .clone_counts
+                    .get(&via_osm_id)
+                    .copied()
+                    .unwrap_or(0)
+                    >= cap
+            {
+                return Some(via_osm_id);
+            }
+            current_node_id = existing;
+        }
+        None
+    }
+
     fn apply_restriction(
         &mut self,
         restriction_path: &[i64],
@@ -171,26 +316,110 @@ impl<'a, 'b> GraphChange<'a, 'b> {
 impl<'a> GraphBuilder<'a> {
     pub fn new(
         profile: &'a Profile,
+        options: &LoadOptions,
         raw_nodes: &'a HashMap<i64, Node>,
         raw_ways: &'a HashMap<i64, Way>,
         raw_relations: &'a HashMap<i64, Relation>,
     ) -> Self {
-        let mut interner = StringInterner::new();
+        Self::with_interner(
+            profile,
+            options,
+            raw_nodes,
+            raw_ways,
+            raw_relations,
+            StringInterner::new(),
+            None,
+        )
+    }
 
-        let interned_profile = InternedProfile {
+    pub fn with_shared_interner(
+        profile: &'a Profile,
+        options: &LoadOptions,
+        raw_nodes: &'a HashMap<i64, Node>,
+        raw_ways: &'a HashMap<i64, Way>,
+        raw_relations: &'a HashMap<i64, Relation>,
+        shared: &SharedInterner,
+    ) -> Self {
+        Self::with_interner(
+            profile,
+            options,
+            raw_nodes,
+            raw_ways,
+            raw_relations,
+            shared.interner.clone(),
+            Some(Arc::clone(&shared.interner.vec)),
+        )
+    }
+
+    fn with_interner(
+        profile: &'a Profile,
+        options: &LoadOptions,
+        raw_nodes: &'a HashMap<i64, Node>,
+        raw_ways: &'a HashMap<i64, Way>,
+        raw_relations: &'a HashMap<i64, Relation>,
+        mut interner: StringInterner,
+        shared_strings: Option<Arc<Vec<String>>>,
+    ) -> Self {
+        let interned_profile = Self::intern_profile(profile, &mut interner);
+
+        GraphBuilder {
+            raw_nodes,
+            raw_ways,
+            raw_relations,
+            interner,
+            shared_strings,
+            profile: interned_profile,
+            snap_tolerance_m: options.snap_tolerance_m,
+            contract_shape_nodes: options.contract_shape_nodes,
+            edge_based: options.edge_based,
+            max_clones_per_node: options.max_clones_per_node,
+            merge_coincident_nodes: options.merge_coincident_nodes,
+            earth_radius_km: options.earth_radius_km.unwrap_or(EARTH_RADIUS_KM as f64) as f32,
+            node_map: FxHashMap::default(),
+            node_aliases: FxHashMap::default(),
+            coordinate_nodes: FxHashMap::default(),
+            next_internal_id: 0,
+            nodes: Vec::new(),
+            temp_edges: FxHashMap::default(),
+            processed_ways: Vec::new(),
+            way_indices: FxHashMap::default(),
+            way_speeds: Vec::new(),
+            way_node_map: FxHashMap::default(),
+            via_node_clones: FxHashMap::default(),
+            clone_counts: FxHashMap::default(),
+            connector_count: 0,
+            max_speed_kmh: 0.0,
+            edge_shapes: FxHashMap::default(),
+            turn_rules: FxHashMap::default(),
+            restrictions: Vec::new(),
+            report: BuildReport::default(),
+            penalty_expression: None,
+        }
+    }
+
+    fn intern_profile(profile: &Profile, interner: &mut StringInterner) -> InternedProfile {
+        InternedProfile {
+            id: profile.id.clone(),
             key: interner.intern(&profile.key),
-            penalties: profile
-                .penalties
-                .penalties
-                .iter()
+            penalties: sorted(&profile.penalties.penalties)
                 .map(|(k, v)| (interner.intern(k), *v))
                 .collect(),
+            penalty_match: profile.penalty_match,
+            penalty_patterns: Self::penalty_patterns(profile),
             default_penalty: profile.penalties.default,
+            speeds: sorted(&profile.speeds)
+                .map(|(k, v)| (interner.intern(k), *v))
+                .collect(),
+            default_speed: profile.default_speed.unwrap_or(DEFAULT_SPEED_KMH),
             access_tags: profile
                 .access_tags
                 .iter()
                 .map(|tag| interner.intern(tag))
                 .collect(),
+            access_hierarchy: Self::expand_access_hierarchy(profile)
+                .iter()
+                .map(|tag| interner.intern(tag))
+                .collect(),
             oneway_tags: profile
                 .oneway_tags
                 .iter()
@@ -201,25 +430,71 @@ impl<'a> GraphBuilder<'a> {
                 .iter()
                 .map(|tag| interner.intern(tag))
                 .collect(),
-        };
+            pedestrian_areas: profile.pedestrian_areas,
+            exclude_motorroads: profile.exclude_motorroads,
+            penalty_expression: profile.penalty_expression.clone(),
+            ignore_access_restrictions: profile.ignore_access_restrictions,
+            ignore_turn_restrictions: profile.ignore_turn_restrictions,
+            turn_angle_weight: profile.turn_angle_weight,
+            unlit_multiplier: profile.unlit_multiplier,
+            min_width: profile.min_width,
+            narrow_way_multiplier: profile.narrow_way_multiplier,
+            unknown_highway_policy: profile.unknown_highway_policy,
+        }
+    }
 
-        GraphBuilder {
-            raw_nodes,
-            raw_ways,
-            raw_relations,
-            interner,
-            profile: interned_profile,
-            node_map: FxHashMap::default(),
-            next_internal_id: 0,
-            nodes: Vec::new(),
-            temp_edges: FxHashMap::default(),
-            processed_ways: Vec::new(),
-            way_node_map: FxHashMap::default(),
-            via_node_clones: FxHashMap::default(),
+    fn penalty_patterns(profile: &Profile) -> Vec<(String, f64)> {
+        let mut patterns: Vec<(String, f64)> = profile
+            .penalties
+            .penalties
+            .iter()
+            .map(|(k, v)| (k.clone(), *v))
+            .collect();
+        patterns.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+        patterns
+    }
+
+    fn expand_access_hierarchy(profile: &Profile) -> Vec<String> {
+        let mut parents: HashMap<&str, &str> = DEFAULT_ACCESS_HIERARCHY.iter().copied().collect();
+        for (child, parent) in &profile.access_hierarchy {
+            parents.insert(child.as_str(), parent.as_str());
+        }
+
+        let mut expanded: Vec<String> = Vec::new();
+        for tag in &profile.access_tags {
+            let mut current = Some(tag.as_str());
+            while let Some(tag) = current {
+                if expanded.iter().any(|t| t == tag) {
+                    break;
+                }
+                expanded.push(tag.to_string());
+                current = parents.get(tag).copied();
+            }
         }
+
+        let depth = |tag: &str| {
+            let mut depth = 0;
+            let mut current = parents.get(tag).copied();
+            while let Some(parent) = current {
+                if depth > parents.len() {
+                    break;
+                }
+                depth += 1;
+                current = parents.get(parent).copied();
+            }
+            depth
+        };
+        expanded.sort_by_key(|tag| std::cmp::Reverse(depth(tag)));
+        expanded
     }
 
     pub fn build(mut self) -> Result<ProcessedGraph> {
+        if let Some(source) = &self.profile.penalty_expression {
+            let expression = Expression::compile(source, &mut self.interner)
+                .map_err(|e| GraphError::InvalidProfile(self.profile.id.clone(), e))?;
+            self.penalty_expression = Some(expression);
+        }
+
         for way in self.raw_ways.values() {
             if self.is_way_usable(way) {
                 let valid_nodes: Vec<i64> = way
@@ -229,6 +504,7 @@ impl<'a> GraphBuilder<'a> {
                     .cloned()
                     .collect();
                 if valid_nodes.len() < 2 {
+                    self.report.skipped_ways += 1;
                     continue;
                 }
 
@@ -238,8 +514,11 @@ impl<'a> GraphBuilder<'a> {
                     .map(|(k, v)| (self.interner.intern(k), self.interner.intern(v)))
                     .collect();
 
+                self.way_indices
+                    .insert(way.id, self.processed_ways.len() as u32);
                 self.processed_ways
                     .push((way.id, valid_nodes.clone(), interned_tags));
+                self.way_speeds.push(0.0);
                 self.way_node_map.insert(way.id, valid_nodes.clone());
                 for &osm_node_id in &valid_nodes {
                     self.get_or_create_internal_node(osm_node_id);
@@ -247,61 +526,139 @@ impl<'a> GraphBuilder<'a> {
             }
         }
 
+        if !self.report.unknown_highway_values.is_empty() {
+            self.report.unknown_highway_values.sort_unstable();
+            self.warn(format!(
+                "Profile '{}' excluded ways with {} values missing from its penalties: {}",
+                self.profile.id,
+                self.interner.vec[self.profile.key as usize],
+                self.report.unknown_highway_values.join(", ")
+            ));
+        }
+
+        if self.report.skipped_ways > 0 {
+            self.warn(format!(
+                "Skipped {} usable ways with fewer than two nodes present in the data",
+                self.report.skipped_ways
+            ));
+        }
+
+        if self.processed_ways.is_empty() {
+            self.warn(format!(
+                "Profile '{}' produced no usable ways; routing queries will fail",
+                self.profile.id
+            ));
+        }
+
         for way in self.raw_ways.values() {
             self.add_way(way);
         }
 
+        if self.snap_tolerance_m > 0.0 {
+            self.add_snap_connectors();
+        }
+
         for relation in self.raw_relations.values() {
             if let Err(e) = self.add_relation(relation) {
-                log::warn!(
-                    "Skipping turn restriction {}: {}",
-                    relation.id,
-                    e.to_string()
-                );
+                self.report.skipped_restrictions += 1;
+                self.warn(format!("Skipping turn restriction {}: {}", relation.id, e));
             }
         }
 
+        if self.contract_shape_nodes {
+            self.contract_shape_nodes();
+        }
+
         self.finalize_graph()
     }
 
-    fn finalize_graph(self) -> Result<ProcessedGraph> {
+    fn warn(&mut self, message: String) {
+        log::warn!("{}", message);
+        self.report.warnings.push(message);
+    }
+
+    fn finalize_graph(mut self) -> Result<ProcessedGraph> {
+        if let Some(shared) = &self.shared_strings {
+            if !Arc::ptr_eq(shared, &self.interner.vec) {
+                self.warn(format!(
+                    "Profile '{}' needed strings missing from the shared interner and keeps its own copy",
+                    self.profile.id
+                ));
+            }
+        }
+
         let mut graph = ProcessedGraph::new();
-        graph.ways = self
-            .processed_ways
-            .into_iter()
-            .map(|(osm_id, node_refs, tags)| WayInfo {
-                osm_id,
-                node_ids: node_refs
-                    .iter()
-                    .map(|osm_node_id| *self.node_map.get(osm_node_id).unwrap())
-                    .collect(),
-                tags,
-            })
-            .collect();
-        graph.nodes = self.nodes;
-        graph.node_id_map = self.node_map;
+        graph.ways = Arc::new(
+            self.processed_ways
+                .into_iter()
+                .map(|(osm_id, node_refs, tags)| WayInfo {
+                    osm_id,
+                    node_ids: node_refs
+                        .iter()
+                        .filter_map(|osm_node_id| self.node_map.get(osm_node_id).copied())
+                        .collect(),
+                    tags,
+                })
+                .collect(),
+        );
+        graph.way_speeds = self.way_speeds;
+        graph.nodes = Arc::new(self.nodes);
         graph.string_interner = self.interner.vec;
+        graph.profile_id = self.profile.id;
+        graph.connector_count = self.connector_count;
+        graph.max_speed_kmh = self.max_speed_kmh as f32;
+        graph.turn_angle_weight = self.profile.turn_angle_weight as f32;
+        graph.unlit_multiplier = self.profile.unlit_multiplier.max(1.0) as f32;
+        graph.earth_radius_km = self.earth_radius_km;
+        graph.turn_rules = Arc::new(self.turn_rules);
+        graph.restrictions = Arc::new(self.restrictions);
+        graph.node_aliases = Arc::new(self.node_aliases);
+        graph.build_report = self.report;
 
         let node_count = graph.nodes.len();
-        graph.offsets.resize(node_count + 1, 0);
-        graph.edges.clear();
+        let mut offsets = vec![0; node_count + 1];
+        let mut edges = Vec::new();
+        let mut edge_ways = Vec::new();
+        let mut edge_geometry_offsets = Vec::new();
+        let mut edge_geometry = Vec::new();
+
+        let has_geometry = !self.edge_shapes.is_empty();
+        if has_geometry {
+            edge_geometry_offsets.push(0);
+        }
 
         let mut edge_count: usize = 0;
         for node_id in 0..node_count as u32 {
-            graph.offsets[node_id as usize] = edge_count;
+            offsets[node_id as usize] = edge_count;
 
             if let Some(neighbors) = self.temp_edges.get(&node_id) {
                 let mut sorted_neighbors: Vec<_> = neighbors.iter().collect();
                 sorted_neighbors.sort_unstable_by_key(|(k, _v)| **k);
 
-                for (&target, &cost) in sorted_neighbors {
-                    graph.edges.push((target, cost));
+                for (&target, &edge) in sorted_neighbors {
+                    edges.push(target);
+                    graph.edge_costs.push(edge.cost);
+                    graph.edge_times.push(edge.time);
+                    edge_ways.push(edge.way);
+
+                    if has_geometry {
+                        if let Some(shape) = self.edge_shapes.get(&(node_id, target)) {
+                            edge_geometry.extend_from_slice(shape);
+                        }
+                        edge_geometry_offsets.push(edge_geometry.len() as u32);
+                    }
                 }
 
                 edge_count += neighbors.len();
             }
         }
-        graph.offsets[node_count] = edge_count;
+        offsets[node_count] = edge_count;
+
+        graph.offsets = Arc::new(offsets);
+        graph.edges = Arc::new(edges);
+        graph.edge_ways = Arc::new(edge_ways);
+        graph.edge_geometry_offsets = Arc::new(edge_geometry_offsets);
+        graph.edge_geometry = Arc::new(edge_geometry);
 
         graph.build_indices();
         Ok(graph)
@@ -312,17 +669,36 @@ impl<'a> GraphBuilder<'a> {
             return *id;
         }
 
-        let internal_id = self.next_internal_id;
-        self.node_map.insert(osm_node_id, internal_id);
-        self.next_internal_id += 1;
-
         let raw_node = self.raw_nodes.get(&osm_node_id).unwrap();
-        let interned_tags = raw_node
+        let interned_tags: FxHashMap<u32, u32> = raw_node
             .tags
             .iter()
             .map(|(k, v)| (self.interner.intern(k), self.interner.intern(v)))
             .collect();
 
+        if self.merge_coincident_nodes {
+            let coordinate_key = (
+                (raw_node.lon * 1e7).round() as i64,
+                (raw_node.lat * 1e7).round() as i64,
+            );
+            if let Some(&existing_id) = self.coordinate_nodes.get(&coordinate_key) {
+                let existing = &mut self.nodes[existing_id as usize];
+                for (key, value) in interned_tags {
+                    existing.tags.entry(key).or_insert(value);
+                }
+                self.node_aliases.insert(osm_node_id, existing.external_id);
+                self.node_map.insert(osm_node_id, existing_id);
+                self.report.merged_nodes += 1;
+                return existing_id;
+            }
+            self.coordinate_nodes
+                .insert(coordinate_key, self.next_internal_id);
+        }
+
+        let internal_id = self.next_internal_id;
+        self.node_map.insert(osm_node_id, internal_id);
+        self.next_internal_id += 1;
+
         self.nodes.push(RouteNode {
             id: internal_id,
             external_id: osm_node_id,
@@ -341,10 +717,22 @@ impl<'a> GraphBuilder<'a> {
             .map(|(k, v)| (self.interner.intern(k), self.interner.intern(v)))
             .collect();
 
+        if self.profile.unknown_highway_policy == UnknownHighwayPolicy::ErrorOnBuild {
+            if let Some(value_id) = self.unknown_key_value(&interned_tags) {
+                let value = &self.interner.vec[value_id as usize];
+                if !self.report.unknown_highway_values.contains(value) {
+                    self.report.unknown_highway_values.push(value.clone());
+                }
+            }
+        }
+
         let penalty = self.get_way_penalty(&interned_tags);
         if penalty.is_none() || !penalty.unwrap().is_finite() || penalty.unwrap() < 1.0 {
             return false;
         }
+        if self.profile.exclude_motorroads && self.is_motorroad(&interned_tags) {
+            return false;
+        }
         let (forward, backward) = self.get_way_direction(&interned_tags);
         !(!forward && !backward)
     }
@@ -365,6 +753,16 @@ impl<'a> GraphBuilder<'a> {
             return;
         }
 
+        let speed = self
+            .get_ferry_speed(way.id, &interned_tags)
+            .unwrap_or_else(|| self.get_way_speed(&interned_tags));
+        self.max_speed_kmh = self.max_speed_kmh.max(speed);
+
+        let way_idx = self.way_indices.get(&way.id).copied().unwrap_or(u32::MAX);
+        if let Some(way_speed) = self.way_speeds.get_mut(way_idx as usize) {
+            *way_speed = speed as f32;
+        }
+
         if let Some(valid_nodes) = self.way_node_map.get(&way.id) {
             for window in valid_nodes.windows(2) {
                 let (from_osm, to_osm) = (window[0], window[1]);
@@ -375,11 +773,15 @@ impl<'a> GraphBuilder<'a> {
                     from_node.lon as f32,
                     to_node.lat as f32,
                     to_node.lon as f32,
+                    self.earth_radius_km,
                 );
-                let cost = (distance * penalty as f32 * 1000.0) as u16;
+                let cost = EdgeCost::new(distance, penalty, speed, way_idx);
 
                 let from_id = *self.node_map.get(&from_osm).unwrap();
                 let to_id = *self.node_map.get(&to_osm).unwrap();
+                if from_id == to_id {
+                    continue;
+                }
 
                 if forward {
                     self.temp_edges
@@ -395,6 +797,148 @@ impl<'a> GraphBuilder<'a> {
                 }
             }
         }
+
+        if self.profile.pedestrian_areas && self.is_pedestrian_area(&interned_tags) {
+            self.add_area_edges(way.id, way_idx, penalty, speed);
+        }
+    }
+
+    fn is_motorroad(&self, tags: &FxHashMap<u32, u32>) -> bool {
+        let motorroad_id = self.interner.map.get("motorroad");
+        let yes_id = self.interner.map.get("yes");
+        match (motorroad_id, yes_id) {
+            (Some(motorroad_id), Some(yes_id)) => tags.get(motorroad_id) == Some(yes_id),
+            _ => false,
+        }
+    }
+
+    fn is_pedestrian_area(&self, tags: &FxHashMap<u32, u32>) -> bool {
+        let tag_is = |key: &str, value: &str| match (
+            self.interner.map.get(key),
+            self.interner.map.get(value),
+        ) {
+            (Some(k), Some(v)) => tags.get(k) == Some(v),
+            _ => false,
+        };
+        tag_is("area", "yes") && tag_is("highway", "pedestrian")
+    }
+
+    fn add_area_edges(&mut self, way_id: i64, way_idx: u32, penalty: f64, speed: f64) {
+        let ring_nodes = match self.way_node_map.get(&way_id) {
+            Some(nodes) if nodes.len() >= 4 && nodes.first() == nodes.last() => {
+                nodes[..nodes.len() - 1].to_vec()
+            }
+            _ => return,
+        };
+
+        let ring: Vec<[f32; 2]> = ring_nodes
+            .iter()
+            .map(|id| {
+                let node = &self.raw_nodes[id];
+                [node.lon as f32, node.lat as f32]
+            })
+            .collect();
+
+        for i in 0..ring_nodes.len() {
+            for j in (i + 2)..ring_nodes.len() {
+                if i == 0 && j == ring_nodes.len() - 1 {
+                    continue;
+                }
+
+                let midpoint = [
+                    (ring[i][0] + ring[j][0]) / 2.0,
+                    (ring[i][1] + ring[j][1]) / 2.0,
+                ];
+                if !point_in_polygon(&midpoint, &ring) {
+                    continue;
+                }
+
+                let distance = distance(
+                    ring[i][1],
+                    ring[i][0],
+                    ring[j][1],
+                    ring[j][0],
+                    self.earth_radius_km,
+                );
+                let cost = EdgeCost::new(distance, penalty, speed, way_idx);
+
+                let from_id = *self.node_map.get(&ring_nodes[i]).unwrap();
+                let to_id = *self.node_map.get(&ring_nodes[j]).unwrap();
+
+                self.temp_edges
+                    .entry(from_id)
+                    .or_default()
+                    .entry(to_id)
+                    .or_insert(cost);
+                self.temp_edges
+                    .entry(to_id)
+                    .or_default()
+                    .entry(from_id)
+                    .or_insert(cost);
+            }
+        }
+    }
+
+    fn add_snap_connectors(&mut self) {
+        let meters_per_degree = meters_per_degree(self.earth_radius_km) as f64;
+        let tolerance = self.snap_tolerance_m;
+
+        let project = |node: &Node| {
+            let x = node.lon * node.lat.to_radians().cos() * meters_per_degree;
+            let y = node.lat * meters_per_degree;
+            [x, y]
+        };
+
+        let mut cells = FxHashMap::<(i64, i64), Vec<_>>::default();
+        for node in &self.nodes {
+            let p = project(&self.raw_nodes[&node.external_id]);
+            let cell = (
+                (p[0] / tolerance).floor() as i64,
+                (p[1] / tolerance).floor() as i64,
+            );
+            cells.entry(cell).or_default().push((node.id, p));
+        }
+
+        let mut connectors: Vec<(u32, u32)> = Vec::new();
+        for (&(cx, cy), members) in &cells {
+            for &(a, pa) in members {
+                for dx in -1..=1 {
+                    for dy in -1..=1 {
+                        let Some(neighbors) = cells.get(&(cx + dx, cy + dy)) else {
+                            continue;
+                        };
+                        for &(b, pb) in neighbors {
+                            if b <= a {
+                                continue;
+                            }
+                            if (pa[0] - pb[0]).powi(2) + (pa[1] - pb[1]).powi(2)
+                                > tolerance * tolerance
+                            {
+                                continue;
+                            }
+
+                            let connected =
+                                self.temp_edges.get(&a).is_some_and(|e| e.contains_key(&b))
+                                    || self.temp_edges.get(&b).is_some_and(|e| e.contains_key(&a));
+                            if !connected {
+                                connectors.push((a, b));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for (a, b) in connectors {
+            let cost = EdgeCost {
+                cost: 0,
+                time: 0,
+                way: u32::MAX,
+            };
+            self.temp_edges.entry(a).or_default().insert(b, cost);
+            self.temp_edges.entry(b).or_default().insert(a, cost);
+            self.connector_count += 1;
+        }
     }
 
     fn add_relation(&mut self, rel: &Relation) -> Result<()> {
@@ -409,14 +953,87 @@ impl<'a> GraphBuilder<'a> {
             return Ok(());
         }
 
+        let members = self.get_ordered_restriction_members(rel)?;
         let mut member_nodes: Vec<Vec<i64>> = Vec::new();
-        for m in self.get_ordered_restriction_members(rel)? {
-            member_nodes.push(self.restriction_member_to_nodes(rel, m)?);
+        let mut via_ways: Vec<i64> = Vec::new();
+        for &m in &members {
+            let Some(nodes) = self.restriction_member_to_nodes(rel, m)? else {
+                self.report.skipped_restrictions += 1;
+                return Ok(());
+            };
+            if m.role == "via" && nodes.len() > 1 {
+                via_ways.push(m.ref_id);
+            }
+            member_nodes.push(nodes);
         }
         let nodes_path = self.flatten_restriction_nodes(member_nodes)?;
-        let mut change = GraphChange::new(self);
+        if self.edge_based && nodes_path.len() == 3 {
+            self.add_turn_rule(&nodes_path, restriction_type)?;
+        } else {
+            let mut change = GraphChange::new(self);
+
+            if let Some(via_osm_id) = change.node_over_clone_cap(&nodes_path) {
+                self.report.restrictions_over_clone_cap += 1;
+                self.warn(format!(
+                    "Skipping turn restriction {}: via node {} already has {} clones",
+                    rel.id,
+                    via_osm_id,
+                    self.max_clones_per_node.unwrap_or_default()
+                ));
+                return Ok(());
+            }
+
+            change.apply_restriction(&nodes_path, restriction_type)?;
+        }
+
+        if let Some(kind) = self.get_restriction_value(&interned_tags) {
+            self.restrictions.push(RestrictionInfo {
+                relation_id: rel.id,
+                kind,
+                from_way: members[0].ref_id,
+                via_ways,
+                via_nodes: nodes_path[1..nodes_path.len() - 1].to_vec(),
+                to_way: members[members.len() - 1].ref_id,
+            });
+        }
+        Ok(())
+    }
+
+    fn add_turn_rule(
+        &mut self,
+        restriction_path: &[i64],
+        restriction_type: TurnRestriction,
+    ) -> Result<()> {
+        let (from, via, to) = (
+            restriction_path[0],
+            restriction_path[1],
+            restriction_path[2],
+        );
+
+        let has_edge = match (self.node_map.get(&from), self.node_map.get(&via)) {
+            (Some(from_id), Some(via_id)) => self
+                .temp_edges
+                .get(from_id)
+                .is_some_and(|edges| edges.contains_key(via_id)),
+            _ => false,
+        };
+        if !has_edge {
+            return Err(GraphError::InvalidOsmData(format!(
+                "Edge from {} to {} does not exist for restriction",
+                from, via
+            )));
+        }
+
+        let canonical = |osm_id: i64| self.node_aliases.get(&osm_id).copied().unwrap_or(osm_id);
+        let (from, via, to) = (canonical(from), canonical(via), canonical(to));
 
-        change.apply_restriction(&nodes_path, restriction_type)
+        let rule = self.turn_rules.entry((from, via)).or_default();
+        match restriction_type {
+            TurnRestriction::Prohibitory => rule.forbidden.push(to),
+            TurnRestriction::Mandatory => rule.only = Some(to),
+            TurnRestriction::Inapplicable => {}
+        }
+        Ok(())
     }
 
     fn get_way_penalty(&self, tags: &FxHashMap<u32, u32>) -> Option<f64> {
@@ -424,12 +1041,80 @@ impl<'a> GraphBuilder<'a> {
             return None;
         }
         tags.get(&self.profile.key)
-            .and_then(|val_id| self.profile.penalties.get(val_id))
+            .and_then(|val_id| self.get_key_penalty(*val_id))
+            .or_else(|| {
+                if self.profile.unknown_highway_policy != UnknownHighwayPolicy::Default
+                    && tags.contains_key(&self.profile.key)
+                {
+                    return None;
+                }
+                self.profile.default_penalty.map(|p| p as f64)
+            })
+            .map(|base| match &self.penalty_expression {
+                Some(expression) => expression.evaluate(base, tags),
+                None => base,
+            })
+            .and_then(|penalty| Some(penalty * self.get_width_multiplier(tags)?))
+    }
+
+    fn get_key_penalty(&self, val_id: u32) -> Option<f64> {
+        self.profile
+            .penalties
+            .get(&val_id)
             .copied()
-            .or_else(|| self.profile.default_penalty.map(|p| p as f64))
+            .or_else(|| self.get_inherited_penalty(val_id))
+    }
+
+    fn unknown_key_value(&self, tags: &FxHashMap<u32, u32>) -> Option<u32> {
+        tags.get(&self.profile.key)
+            .copied()
+            .filter(|&val_id| self.get_key_penalty(val_id).is_none())
+    }
+
+    fn get_width_multiplier(&self, tags: &FxHashMap<u32, u32>) -> Option<f64> {
+        let Some(min_width) = self.profile.min_width else {
+            return Some(1.0);
+        };
+        let width = self
+            .interner
+            .map
+            .get("width")
+            .and_then(|width_id| tags.get(width_id))
+            .and_then(|value_id| parse_length_m(&self.interner.vec[*value_id as usize]));
+        match width {
+            Some(width) if (width as f64) < min_width => {
+                self.profile.narrow_way_multiplier.map(|m| m.max(1.0))
+            }
+            _ => Some(1.0),
+        }
+    }
+
+    fn get_inherited_penalty(&self, val_id: u32) -> Option<f64> {
+        let value = &self.interner.vec[val_id as usize];
+        let is_separator = |c: Option<char>| matches!(c, Some('_') | Some(':'));
+
+        self.profile
+            .penalty_patterns
+            .iter()
+            .find(|(pattern, _)| match self.profile.penalty_match {
+                PenaltyMatch::Exact => false,
+                PenaltyMatch::Prefix => {
+                    value.starts_with(pattern.as_str())
+                        && is_separator(value[pattern.len()..].chars().next())
+                }
+                PenaltyMatch::Suffix => {
+                    value.ends_with(pattern.as_str())
+                        && is_separator(value[..value.len() - pattern.len()].chars().last())
+                }
+            })
+            .map(|(_, penalty)| *penalty)
     }
 
     fn is_way_accessible(&self, tags: &FxHashMap<u32, u32>) -> bool {
+        if self.profile.ignore_access_restrictions {
+            return true;
+        }
+
         let yes_id = self.interner.map.get("yes").copied();
         let designated_id = self.interner.map.get("designated").copied();
         let permissive_id = self.interner.map.get("permissive").copied();
@@ -438,35 +1123,81 @@ impl<'a> GraphBuilder<'a> {
         let private_id = self.interner.map.get("private").copied();
         let false_id = self.interner.map.get("false").copied();
 
-        let mut has_specific_permission = false;
-        let mut has_specific_prohibition = false;
-
-        for tag_id in self.profile.access_tags.iter() {
+        for tag_id in self.profile.access_hierarchy.iter() {
             if let Some(val_id) = tags.get(tag_id).copied() {
                 let val_id = Some(val_id);
                 if val_id == yes_id || val_id == designated_id || val_id == permissive_id {
-                    has_specific_permission = true;
-                    break;
+                    return true;
                 }
 
                 if val_id == no_id || val_id == private_id || val_id == false_id {
-                    has_specific_prohibition = true;
+                    return false;
                 }
             }
         }
 
-        if has_specific_permission {
-            return true;
+        true
+    }
+
+    fn get_way_speed(&self, tags: &FxHashMap<u32, u32>) -> f64 {
+        let class_speed = tags
+            .get(&self.profile.key)
+            .and_then(|val_id| self.profile.speeds.get(val_id))
+            .copied();
+        let max_speed = self
+            .interner
+            .map
+            .get("maxspeed")
+            .and_then(|key_id| tags.get(key_id))
+            .and_then(|val_id| parse_maxspeed(&self.interner.vec[*val_id as usize]));
+
+        let speed = match (class_speed, max_speed) {
+            (Some(class), Some(max)) => class.min(max),
+            (Some(class), None) => class,
+            (None, Some(max)) => max,
+            (None, None) => self.profile.default_speed,
+        };
+
+        if speed.is_finite() && speed > 0.0 {
+            speed
+        } else {
+            self.profile.default_speed
         }
+    }
 
-        if has_specific_prohibition {
-            return false;
+    fn get_ferry_speed(&self, way_id: i64, tags: &FxHashMap<u32, u32>) -> Option<f64> {
+        let route_id = self.interner.map.get("route")?;
+        let ferry_id = self.interner.map.get("ferry")?;
+        if tags.get(route_id) != Some(ferry_id) {
+            return None;
         }
 
-        true
+        let duration_id = self.interner.map.get("duration")?;
+        let hours = parse_duration_hours(&self.interner.vec[*tags.get(duration_id)? as usize])?;
+
+        let length_km: f64 = self
+            .way_node_map
+            .get(&way_id)?
+            .windows(2)
+            .map(|pair| {
+                let (a, b) = (&self.raw_nodes[&pair[0]], &self.raw_nodes[&pair[1]]);
+                distance(
+                    a.lat as f32,
+                    a.lon as f32,
+                    b.lat as f32,
+                    b.lon as f32,
+                    self.earth_radius_km,
+                ) as f64
+            })
+            .sum();
+
+        Some(length_km / hours).filter(|speed| speed.is_finite() && *speed > 0.0)
     }
 
     fn get_way_direction(&self, tags: &FxHashMap<u32, u32>) -> (bool, bool) {
+        if let Some(direction) = self.get_explicit_oneway(tags) {
+            return direction;
+        }
         if let Some(j_id) = self.interner.map.get("junction") {
             if let Some(val_id) = tags.get(j_id) {
                 if Some(*val_id) == self.interner.map.get("roundabout").copied()
@@ -476,6 +1207,10 @@ impl<'a> GraphBuilder<'a> {
                 }
             }
         }
+        (true, true)
+    }
+
+    fn get_explicit_oneway(&self, tags: &FxHashMap<u32, u32>) -> Option<(bool, bool)> {
         let yes_id = self.interner.map.get("yes").copied();
         let true_id = self.interner.map.get("true").copied();
         let one_id = self.interner.map.get("1").copied();
@@ -486,31 +1221,47 @@ impl<'a> GraphBuilder<'a> {
         for tag_id in self.profile.oneway_tags.iter() {
             if let Some(val_id) = tags.get(tag_id).copied() {
                 if Some(val_id) == yes_id || Some(val_id) == true_id || Some(val_id) == one_id {
-                    return (true, false);
+                    return Some((true, false));
                 }
                 if Some(val_id) == reverse_id || Some(val_id) == minus_one_id {
-                    return (false, true);
+                    return Some((false, true));
                 }
                 if Some(val_id) == no_id {
-                    return (true, true);
+                    return Some((true, true));
                 }
             }
         }
-        (true, true)
+        None
     }
 
     fn get_restriction_type(&self, tags: &FxHashMap<u32, u32>) -> TurnRestriction {
+        if self.profile.ignore_turn_restrictions {
+            return TurnRestriction::Inapplicable;
+        }
+        if let Some(val_id) = self.get_restriction_value(tags) {
+            let value_str = &self.interner.vec[val_id as usize];
+            if value_str.starts_with("no_") {
+                return TurnRestriction::Prohibitory;
+            }
+            if value_str.starts_with("only_") {
+                return TurnRestriction::Mandatory;
+            }
+        }
+        TurnRestriction::Inapplicable
+    }
+
+    fn get_restriction_value(&self, tags: &FxHashMap<u32, u32>) -> Option<u32> {
         let type_id = self.interner.map.get("type").copied();
         let restriction_id = self.interner.map.get("restriction").copied();
 
         if type_id.is_none() || restriction_id.is_none() {
-            return TurnRestriction::Inapplicable;
+            return None;
         }
         if tags.get(&type_id.unwrap()) != Some(&restriction_id.unwrap()) {
-            return TurnRestriction::Inapplicable;
+            return None;
         }
         if self.is_exempted(tags) {
-            return TurnRestriction::Inapplicable;
+            return None;
         }
 
         let mut restriction_value_id = None;
@@ -529,17 +1280,7 @@ impl<'a> GraphBuilder<'a> {
                 restriction_value_id = Some(*val_id);
             }
         }
-
-        if let Some(val_id) = restriction_value_id {
-            let value_str = &self.interner.vec[val_id as usize];
-            if value_str.starts_with("no_") {
-                return TurnRestriction::Prohibitory;
-            }
-            if value_str.starts_with("only_") {
-                return TurnRestriction::Mandatory;
-            }
-        }
-        TurnRestriction::Inapplicable
+        restriction_value_id
     }
 
     fn is_exempted(&self, tags: &FxHashMap<u32, u32>) -> bool {
@@ -588,8 +1329,43 @@ impl<'a> GraphBuilder<'a> {
         Ok(ordered)
     }
 
-    fn restriction_member_to_nodes(&self, _r: &Relation, m: &RelationMember) -> Result<Vec<i64>> {
-        match m.member_type.as_str() {
+    fn infer_member_type(&self, m: &RelationMember) -> Option<&'static str> {
+        if m.role != "via" {
+            return Some("way");
+        }
+        match (
+            self.way_node_map.contains_key(&m.ref_id),
+            self.raw_nodes.contains_key(&m.ref_id),
+        ) {
+            // Node and way IDs are separate OSM namespaces, so an ID found in both says
+            // nothing about which one the mapper meant.
+            (true, true) => None,
+            (true, false) => Some("way"),
+            (false, _) => Some("node"),
+        }
+    }
+
+    fn restriction_member_to_nodes(
+        &mut self,
+        r: &Relation,
+        m: &RelationMember,
+    ) -> Result<Option<Vec<i64>>> {
+        let member_type = m.member_type.trim().to_ascii_lowercase();
+        let member_type = match member_type.as_str() {
+            "" => match self.infer_member_type(m) {
+                Some(inferred) => inferred,
+                None => {
+                    self.warn(format!(
+                        "Skipping turn restriction {}: untyped via member {} is both a node and a way",
+                        r.id, m.ref_id
+                    ));
+                    return Ok(None);
+                }
+            },
+            other => other,
+        };
+
+        match member_type {
             "node" if m.role == "via" => {
                 if !self.raw_nodes.contains_key(&m.ref_id) {
                     return Err(GraphError::InvalidOsmData(format!(
@@ -597,14 +1373,19 @@ impl<'a> GraphBuilder<'a> {
                         m.ref_id
                     )));
                 }
-                Ok(vec![m.ref_id])
-            }
-            "way" => self.way_node_map.get(&m.ref_id).cloned().ok_or_else(|| {
-                GraphError::InvalidOsmData(format!(
-                    "Unknown or unusable way in restriction: {}",
-                    m.ref_id
-                ))
-            }),
+                Ok(Some(vec![m.ref_id]))
+            }
+            "way" => self
+                .way_node_map
+                .get(&m.ref_id)
+                .cloned()
+                .map(Some)
+                .ok_or_else(|| {
+                    GraphError::InvalidOsmData(format!(
+                        "Unknown or unusable way in restriction: {}",
+                        m.ref_id
+                    ))
+                }),
             _ => Err(GraphError::InvalidOsmData(format!(
                 "Invalid member type/role combo: {}/{}",
                 m.member_type, m.role
@@ -706,10 +1487,8 @@ impl<'a> GraphBuilder<'a> {
                 if start_idx_in_b < end_idx_in_b {
                     final_path.extend_from_slice(&way_b[start_idx_in_b + 1..=end_idx_in_b]);
                 } else {
-                    let mut reversed_segment: Vec<i64> = way_b[end_idx_in_b..start_idx_in_b]
-                        .iter()
-                        .cloned()
-                        .collect();
+                    let mut reversed_segment: Vec<i64> =
+                        way_b[end_idx_in_b..start_idx_in_b].to_vec();
 
                     reversed_segment.reverse();
                     final_path.extend(reversed_segment);
@@ -734,3 +1513,717 @@ impl<'a> GraphBuilder<'a> {
         Ok(final_path)
     }
 }
+
+/// Iterates a profile map by key, so every profile interns its strings in the
+/// same order whatever the map's hash seed.
+fn sorted<V>(map: &HashMap<String, V>) -> impl Iterator<Item = (&String, &V)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries.into_iter()
+}
+
+fn parse_maxspeed(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let (number, factor) = match value.strip_suffix("mph") {
+        Some(number) => (number, 1.609_344),
+        None => (value.strip_suffix("km/h").unwrap_or(value), 1.0),
+    };
+    number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|v| *v > 0.0)
+        .map(|v| v * factor)
+}
+
+fn parse_duration_hours(value: &str) -> Option<f64> {
+    let parts: Vec<&str> = value.trim().split(':').collect();
+    if parts.len() != 2 && parts.len() != 3 {
+        return None;
+    }
+
+    let mut seconds = 0.0;
+    for (part, unit) in parts.iter().zip([3600.0, 60.0, 1.0]) {
+        let part = part.trim();
+        if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        seconds += part.parse::<f64>().ok()? * unit;
+    }
+
+    Some(seconds / 3600.0).filter(|hours| *hours > 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_duration_hours, GraphBuilder, SharedInterner, DEFAULT_SPEED_KMH};
+    use crate::core::types::Profile;
+    use crate::graph::{GraphContainer, ProcessedGraph};
+    use crate::parser::parse_osm_xml;
+    use crate::routing::algorithm::{find_route_through_waypoints, RouteOptions};
+    use crate::test_support::{build_graph, node, options, osm, profile, relation, way};
+    use std::sync::Arc;
+
+    fn square_area() -> String {
+        osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.001, 0.0),
+            node(3, 0.001, 0.001),
+            node(4, 0.0, 0.001),
+            way(
+                10,
+                &[1, 2, 3, 4, 1],
+                &[("highway", "pedestrian"), ("area", "yes")],
+            ),
+        ])
+    }
+
+    #[test]
+    fn routes_across_pedestrian_area() {
+        let foot = r#"{"penalties":{"pedestrian":1},"pedestrian_areas":true}"#;
+        let graph = build_graph(&square_area(), "{}", foot);
+
+        let route = find_route_through_waypoints(&graph, &[1, 3], &RouteOptions::default())
+            .unwrap()
+            .found()
+            .unwrap();
+        assert_eq!(route.nodes, vec![1, 3]);
+    }
+
+    #[test]
+    fn keeps_to_area_outline_without_flag() {
+        let graph = build_graph(&square_area(), "{}", r#"{"penalties":{"pedestrian":1}}"#);
+
+        let route = find_route_through_waypoints(&graph, &[1, 3], &RouteOptions::default())
+            .unwrap()
+            .found()
+            .unwrap();
+        assert_eq!(route.nodes.len(), 3);
+    }
+
+    fn near_miss() -> String {
+        osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.001, 0.0),
+            node(3, 0.00101, 0.0),
+            node(4, 0.002, 0.0),
+            way(10, &[1, 2], &[("highway", "residential")]),
+            way(11, &[3, 4], &[("highway", "residential")]),
+        ])
+    }
+
+    #[test]
+    fn snaps_near_coincident_nodes() {
+        let graph = build_graph(&near_miss(), r#"{"snap_tolerance_m":5}"#, "{}");
+
+        assert_eq!(graph.connector_count, 1);
+        let route = find_route_through_waypoints(&graph, &[1, 4], &RouteOptions::default())
+            .unwrap()
+            .found()
+            .unwrap();
+        assert_eq!(route.nodes, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn leaves_nodes_apart_beyond_tolerance() {
+        let graph = build_graph(&near_miss(), r#"{"snap_tolerance_m":0.5}"#, "{}");
+
+        assert_eq!(graph.connector_count, 0);
+        assert!(
+            find_route_through_waypoints(&graph, &[1, 4], &RouteOptions::default())
+                .unwrap()
+                .found()
+                .is_none()
+        );
+    }
+
+    fn included_ways(profile_json: &str, ways: &[(i64, &[(&str, &str)])]) -> Vec<i64> {
+        let mut parts = Vec::new();
+        for (i, (way_id, tags)) in ways.iter().enumerate() {
+            let first = i as i64 * 2 + 1;
+            parts.push(node(first, 0.0, i as f64 * 0.01));
+            parts.push(node(first + 1, 0.01, i as f64 * 0.01));
+            parts.push(way(*way_id, &[first, first + 1], tags));
+        }
+        let graph = build_graph(&osm(&parts), "{}", profile_json);
+        let mut included: Vec<i64> = graph.ways.iter().map(|way| way.osm_id).collect();
+        included.sort_unstable();
+        included
+    }
+
+    #[test]
+    fn parent_access_tag_applies_without_specific_one() {
+        let included = included_ways(
+            r#"{"access_tags":["motorcar","access"]}"#,
+            &[
+                (
+                    10,
+                    &[
+                        ("highway", "residential"),
+                        ("access", "yes"),
+                        ("motor_vehicle", "no"),
+                    ],
+                ),
+                (
+                    11,
+                    &[
+                        ("highway", "residential"),
+                        ("access", "no"),
+                        ("motorcar", "yes"),
+                    ],
+                ),
+                (12, &[("highway", "residential"), ("vehicle", "no")]),
+                (13, &[("highway", "residential")]),
+            ],
+        );
+        assert_eq!(included, vec![11, 13]);
+    }
+
+    #[test]
+    fn link_roads_inherit_parent_penalty() {
+        let ways: &[(i64, &[(&str, &str)])] = &[
+            (10, &[("highway", "primary_link")]),
+            (11, &[("highway", "primary")]),
+            (12, &[("highway", "secondary_link")]),
+        ];
+        let prefix = included_ways(
+            r#"{"penalties":{"primary":1},"penalty_match":"prefix"}"#,
+            ways,
+        );
+        assert_eq!(prefix, vec![10, 11]);
+
+        let exact = included_ways(r#"{"penalties":{"primary":1}}"#, ways);
+        assert_eq!(exact, vec![11]);
+    }
+
+    #[test]
+    fn turn_rules_route_like_node_clones() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.02, 0.0),
+            node(4, 0.01, 0.01),
+            node(5, 0.01, -0.01),
+            node(6, 0.02, 0.01),
+            way(10, &[1, 2], &[("highway", "residential")]),
+            way(11, &[2, 3], &[("highway", "residential")]),
+            way(12, &[2, 4], &[("highway", "residential")]),
+            way(13, &[5, 2], &[("highway", "residential")]),
+            way(14, &[3, 6, 4], &[("highway", "residential")]),
+            relation(
+                20,
+                &[("way", 10, "from"), ("node", 2, "via"), ("way", 12, "to")],
+                &[("type", "restriction"), ("restriction", "no_left_turn")],
+            ),
+            relation(
+                21,
+                &[("way", 13, "from"), ("node", 2, "via"), ("way", 12, "to")],
+                &[("type", "restriction"), ("restriction", "only_straight_on")],
+            ),
+        ]);
+        let cloned = build_graph(&xml, "{}", "{}");
+        let edge_based = build_graph(&xml, r#"{"edge_based":true}"#, "{}");
+        assert!(cloned.turn_rules.is_empty());
+        assert_eq!(edge_based.turn_rules.len(), 2);
+
+        for (from, to) in [(1, 4), (5, 3), (5, 1), (1, 5), (4, 1), (3, 5)] {
+            let route = |graph| {
+                find_route_through_waypoints(graph, &[from, to], &RouteOptions::default())
+                    .unwrap()
+                    .found()
+                    .unwrap()
+            };
+            let (cloned_route, edge_based_route) = (route(&cloned), route(&edge_based));
+            assert_eq!(
+                cloned_route.nodes, edge_based_route.nodes,
+                "{} -> {}",
+                from, to
+            );
+            assert_eq!(
+                cloned_route.cost, edge_based_route.cost,
+                "{} -> {}",
+                from, to
+            );
+        }
+        for (waypoints, detour) in [([1, 4], vec![1, 2, 3, 6, 4]), ([5, 3], vec![5, 2, 4, 6, 3])] {
+            let route =
+                find_route_through_waypoints(&edge_based, &waypoints, &RouteOptions::default());
+            assert_eq!(route.unwrap().found().unwrap().nodes, detour);
+        }
+    }
+
+    #[test]
+    fn bike_profile_skips_motorroad_trunk() {
+        let ways: &[(i64, &[(&str, &str)])] = &[
+            (10, &[("highway", "trunk"), ("motorroad", "yes")]),
+            (11, &[("highway", "trunk")]),
+        ];
+        let bike = included_ways(
+            r#"{"penalties":{"trunk":1},"exclude_motorroads":true}"#,
+            ways,
+        );
+        assert_eq!(bike, vec![11]);
+
+        let car = included_ways(r#"{"penalties":{"trunk":1}}"#, ways);
+        assert_eq!(car, vec![10, 11]);
+    }
+
+    fn untyped_via_route(via: &[(&str, i64, &str)], extra: &[String]) -> Option<Vec<i64>> {
+        let mut parts = vec![
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.02, 0.0),
+            node(4, 0.03, 0.0),
+            way(10, &[1, 2], &[("highway", "residential")]),
+            way(11, &[3, 4], &[("highway", "residential")]),
+            relation(
+                20,
+                via,
+                &[("type", "restriction"), ("restriction", "no_straight_on")],
+            ),
+        ];
+        parts.extend_from_slice(extra);
+        let graph = build_graph(&osm(&parts), "{}", "{}");
+        find_route_through_waypoints(&graph, &[1, 4], &RouteOptions::default())
+            .unwrap()
+            .found()
+            .map(|route| route.nodes)
+    }
+
+    #[test]
+    fn untyped_via_member_resolves_to_node() {
+        let route = untyped_via_route(
+            &[("way", 10, "from"), ("", 2, "via"), ("way", 12, "to")],
+            &[
+                way(12, &[2, 3], &[("highway", "residential")]),
+                node(5, 0.01, 0.01),
+                node(6, 0.02, 0.01),
+                way(13, &[2, 5, 6, 3], &[("highway", "residential")]),
+            ],
+        );
+        assert_eq!(route, Some(vec![1, 2, 5, 6, 3, 4]));
+    }
+
+    #[test]
+    fn untyped_via_member_resolves_to_known_way() {
+        let route = untyped_via_route(
+            &[("way", 10, "from"), ("", 12, "via"), ("way", 11, "to")],
+            &[way(12, &[2, 3], &[("highway", "residential")])],
+        );
+        assert_eq!(route, None);
+    }
+
+    fn roundabout_route(tags: &[(&str, &str)]) -> Vec<i64> {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.001, 0.0),
+            node(3, 0.0005, 0.001),
+            way(10, &[1, 2, 3, 1], tags),
+        ]);
+        let graph = build_graph(&xml, "{}", "{}");
+        find_route_through_waypoints(&graph, &[1, 2], &RouteOptions::default())
+            .unwrap()
+            .found()
+            .unwrap()
+            .nodes
+    }
+
+    #[test]
+    fn reverse_digitised_roundabout_follows_oneway_tag() {
+        let roundabout = [("highway", "residential"), ("junction", "roundabout")];
+        assert_eq!(roundabout_route(&roundabout), vec![1, 2]);
+
+        let reversed = [
+            ("highway", "residential"),
+            ("junction", "roundabout"),
+            ("oneway", "-1"),
+        ];
+        assert_eq!(roundabout_route(&reversed), vec![1, 3, 2]);
+    }
+
+    #[test]
+    fn private_road_included_only_when_ignoring_access() {
+        let ways: &[(i64, &[(&str, &str)])] = &[
+            (10, &[("highway", "service"), ("access", "private")]),
+            (11, &[("highway", "service")]),
+        ];
+        assert_eq!(included_ways("{}", ways), vec![11]);
+        assert_eq!(
+            included_ways(r#"{"ignore_access_restrictions":true}"#, ways),
+            vec![10, 11]
+        );
+    }
+
+    #[test]
+    fn clone_cap_skips_restrictions_at_busy_junction() {
+        let restriction = |id, from, kind| {
+            relation(
+                id,
+                &[("way", from, "from"), ("node", 2, "via"), ("way", 13, "to")],
+                &[("type", "restriction"), ("restriction", kind)],
+            )
+        };
+        let xml = osm(&[
+            node(2, 0.0, 0.0),
+            node(3, -0.01, 0.0),
+            node(4, 0.0, -0.01),
+            node(5, 0.01, 0.0),
+            node(6, 0.0, 0.01),
+            way(10, &[3, 2], &[("highway", "residential")]),
+            way(11, &[4, 2], &[("highway", "residential")]),
+            way(12, &[5, 2], &[("highway", "residential")]),
+            way(13, &[2, 6], &[("highway", "residential")]),
+            restriction(20, 10, "no_left_turn"),
+            restriction(21, 11, "no_straight_on"),
+            restriction(22, 12, "no_right_turn"),
+        ]);
+        let reachable_arms = |graph: &ProcessedGraph| {
+            [3, 4, 5]
+                .into_iter()
+                .filter(|&from| {
+                    find_route_through_waypoints(graph, &[from, 6], &RouteOptions::default())
+                        .unwrap()
+                        .found()
+                        .is_some()
+                })
+                .count()
+        };
+
+        let uncapped = build_graph(&xml, "{}", "{}");
+        assert_eq!(uncapped.build_report.restrictions_over_clone_cap, 0);
+        assert_eq!(reachable_arms(&uncapped), 0);
+
+        let capped = build_graph(&xml, r#"{"max_clones_per_node":2}"#, "{}");
+        assert_eq!(capped.build_report.restrictions_over_clone_cap, 1);
+        assert!(capped
+            .build_report
+            .warnings
+            .iter()
+            .any(|w| w.contains("via node 2 already has 2 clones")));
+        assert_eq!(reachable_arms(&capped), 1);
+    }
+
+    #[test]
+    fn parses_ferry_durations() {
+        assert_eq!(parse_duration_hours("01:30"), Some(1.5));
+        assert_eq!(parse_duration_hours(" 0:45:36 "), Some(0.76));
+        assert_eq!(parse_duration_hours("90"), None);
+        assert_eq!(parse_duration_hours("1:x"), None);
+        assert_eq!(parse_duration_hours("00:00"), None);
+    }
+
+    #[test]
+    fn ferry_duration_sets_crossing_time() {
+        let ferry_time = |duration| {
+            let xml = osm(&[
+                node(1, 0.0, 0.0),
+                node(2, 0.03, 0.0),
+                node(3, 0.1, 0.0),
+                way(
+                    10,
+                    &[1, 2, 3],
+                    &[("route", "ferry"), ("duration", duration)],
+                ),
+            ]);
+            let graph = build_graph(&xml, "{}", r#"{"penalties":{"default":1}}"#);
+            let (first, second) = (graph.node_id_map[&1], graph.node_id_map[&2]);
+            graph.edge_times[graph.find_edge(first, 2).unwrap()]
+                + graph.edge_times[graph.find_edge(second, 3).unwrap()]
+        };
+
+        assert!(ferry_time("01:30").abs_diff(5_400_000) < 1_000);
+        let at_default_speed = 11.12 / DEFAULT_SPEED_KMH * 3_600_000.0;
+        assert!((ferry_time("soon") as f64 - at_default_speed).abs() < 5_000.0);
+    }
+
+    #[test]
+    fn merged_coincident_nodes_join_ways() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.01, 0.0),
+            node(4, 0.02, 0.0),
+            way(10, &[1, 2], &[("highway", "residential")]),
+            way(11, &[3, 4], &[("highway", "residential")]),
+        ]);
+        let route = |graph: &ProcessedGraph, waypoints: &[i64]| {
+            find_route_through_waypoints(graph, waypoints, &RouteOptions::default())
+                .unwrap()
+                .found()
+                .map(|route| route.nodes)
+        };
+
+        let separate = build_graph(&xml, "{}", "{}");
+        assert_eq!(route(&separate, &[1, 4]), None);
+
+        let merged = build_graph(&xml, r#"{"merge_coincident_nodes":true}"#, "{}");
+        assert_eq!(merged.build_report.merged_nodes, 1);
+        let nodes = route(&merged, &[1, 4]).unwrap();
+        assert_eq!(nodes.len(), 3);
+        assert!(nodes[1] == 2 || nodes[1] == 3);
+        assert!(route(&merged, &[3, 1]).is_some());
+        assert!(route(&merged, &[2, 4]).is_some());
+    }
+
+    #[test]
+    fn bike_profile_excludes_narrow_way() {
+        let ways: &[(i64, &[(&str, &str)])] = &[
+            (10, &[("highway", "cycleway"), ("width", "1")]),
+            (11, &[("highway", "cycleway"), ("width", "3")]),
+        ];
+        let bike = r#"{"penalties":{"cycleway":1},"min_width":2}"#;
+        assert_eq!(included_ways(bike, ways), vec![11]);
+
+        let tolerant = r#"{"penalties":{"cycleway":1},"min_width":2,"narrow_way_multiplier":4}"#;
+        assert_eq!(included_ways(tolerant, ways), vec![10, 11]);
+    }
+
+    fn shared_interner_graphs(
+        interned: &[&str],
+        built: &[&str],
+    ) -> (Vec<ProcessedGraph>, Vec<ProcessedGraph>) {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.02, 0.0),
+            way(10, &[1, 2], &[("highway", "residential"), ("name", "A")]),
+            way(
+                11,
+                &[2, 3],
+                &[("highway", "footway"), ("surface", "gravel")],
+            ),
+        ]);
+        let (nodes, ways, relations) = parse_osm_xml(&xml).unwrap();
+        let profiles = |jsons: &[&str]| -> Vec<Profile> {
+            jsons
+                .iter()
+                .enumerate()
+                .map(|(i, json)| {
+                    let mut profile = profile(json);
+                    profile.id = format!("p{}", i);
+                    profile
+                })
+                .collect()
+        };
+        let (interned, built) = (profiles(interned), profiles(built));
+        let options = options("{}", built.clone());
+
+        let shared = SharedInterner::build(&interned, &nodes, &ways, &relations);
+        let joined = built
+            .iter()
+            .map(|p| {
+                GraphBuilder::with_shared_interner(p, &options, &nodes, &ways, &relations, &shared)
+                    .build()
+                    .unwrap()
+            })
+            .collect();
+        let separate = built
+            .iter()
+            .map(|p| {
+                GraphBuilder::new(p, &options, &nodes, &ways, &relations)
+                    .build()
+                    .unwrap()
+            })
+            .collect();
+        (joined, separate)
+    }
+
+    #[test]
+    fn profiles_share_one_string_table() {
+        let jsons = [
+            "{}",
+            r#"{"penalties":{"footway":1}}"#,
+            r#"{"penalty_expression":"base * (1 + has(surface=gravel))"}"#,
+        ];
+        let (joined, separate) = shared_interner_graphs(&jsons, &jsons);
+
+        for graph in &joined {
+            assert!(Arc::ptr_eq(
+                &graph.string_interner,
+                &joined[0].string_interner
+            ));
+            assert!(graph.build_report.warnings.is_empty());
+        }
+        for (shared, own) in joined.iter().zip(&separate) {
+            assert_eq!(shared.edge_costs, own.edge_costs);
+            for (a, b) in shared.ways.iter().zip(own.ways.iter()) {
+                for key in ["highway", "name", "surface"] {
+                    assert_eq!(shared.tag_value(&a.tags, key), own.tag_value(&b.tags, key));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cached_graphs_share_interner_again() {
+        let jsons = ["{}", r#"{"penalties":{"footway":1}}"#];
+        let (joined, _) = shared_interner_graphs(&jsons, &jsons);
+        let mut container = GraphContainer::new();
+        for graph in joined {
+            container.profiles.insert(graph.profile_id.clone(), graph);
+        }
+
+        let mut loaded: GraphContainer =
+            bincode::deserialize(&bincode::serialize(&container).unwrap()).unwrap();
+        loaded.share_interners();
+        let graphs: Vec<&ProcessedGraph> = loaded.profiles.values().collect();
+        assert!(Arc::ptr_eq(
+            &graphs[0].string_interner,
+            &graphs[1].string_interner
+        ));
+    }
+
+    const UNKNOWN_HIGHWAY_WAYS: &[(i64, &[(&str, &str)])] = &[
+        (10, &[("highway", "residential")]),
+        (11, &[("highway", "bogus")]),
+        (12, &[("route", "ferry")]),
+    ];
+
+    fn unknown_highway_profile(policy: &str) -> String {
+        format!(
+            r#"{{"penalties":{{"residential":1,"default":2}},"unknown_highway_policy":"{}"}}"#,
+            policy
+        )
+    }
+
+    #[test]
+    fn default_policy_gives_unknown_highway_default_penalty() {
+        let included = included_ways(&unknown_highway_profile("default"), UNKNOWN_HIGHWAY_WAYS);
+        assert_eq!(included, vec![10, 11, 12]);
+    }
+
+    #[test]
+    fn exclude_policy_drops_unknown_highway_only() {
+        let included = included_ways(&unknown_highway_profile("exclude"), UNKNOWN_HIGHWAY_WAYS);
+        assert_eq!(included, vec![10, 12]);
+    }
+
+    #[test]
+    fn error_on_build_policy_reports_unknown_highway_values() {
+        let included = included_ways(
+            &unknown_highway_profile("error-on-build"),
+            UNKNOWN_HIGHWAY_WAYS,
+        );
+        assert_eq!(included, vec![10, 12]);
+
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.02, 0.0),
+            way(10, &[1, 2], &[("highway", "residential")]),
+            way(11, &[2, 3], &[("highway", "bogus")]),
+            way(12, &[1, 3], &[("highway", "bogus")]),
+        ]);
+        let graph = build_graph(&xml, "{}", &unknown_highway_profile("error-on-build"));
+        assert_eq!(graph.build_report.unknown_highway_values, vec!["bogus"]);
+        assert!(graph
+            .build_report
+            .warnings
+            .iter()
+            .any(|warning| warning.contains("highway values missing from its penalties: bogus")));
+    }
+
+    #[test]
+    fn orders_access_hierarchy_by_specificity() {
+        let profile = profile(r#"{"access_tags":["foot","bicycle","access"]}"#);
+        assert_eq!(
+            GraphBuilder::expand_access_hierarchy(&profile),
+            vec!["bicycle", "foot", "vehicle", "access"]
+        );
+    }
+
+    #[test]
+    fn specific_access_tag_overrides_general_one() {
+        let included = included_ways(
+            r#"{"access_tags":["foot","bicycle","access"]}"#,
+            &[
+                (
+                    10,
+                    &[
+                        ("highway", "residential"),
+                        ("access", "no"),
+                        ("bicycle", "yes"),
+                    ],
+                ),
+                (11, &[("highway", "residential"), ("access", "no")]),
+                (
+                    12,
+                    &[
+                        ("highway", "residential"),
+                        ("vehicle", "no"),
+                        ("bicycle", "yes"),
+                    ],
+                ),
+            ],
+        );
+        assert_eq!(included, vec![10, 12]);
+    }
+
+    #[test]
+    fn skips_restriction_with_ambiguous_via_member() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.02, 0.0),
+            node(4, 0.01, 0.01),
+            node(12, 0.01, 0.02),
+            way(10, &[1, 2], &[("highway", "residential")]),
+            way(11, &[2, 3], &[("highway", "residential")]),
+            way(12, &[2, 4, 12], &[("highway", "residential")]),
+            relation(
+                20,
+                &[("way", 10, "from"), ("", 12, "via"), ("way", 11, "to")],
+                &[("type", "restriction"), ("restriction", "no_straight_on")],
+            ),
+        ]);
+        let graph = build_graph(&xml, "{}", "{}");
+
+        assert_eq!(graph.build_report.skipped_restrictions, 1);
+        assert!(graph.restrictions.is_empty());
+        assert_eq!(graph.validate_route(&[1, 2, 3]), None);
+    }
+
+    #[test]
+    fn snaps_merged_nodes_once() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.01, 0.0),
+            node(4, 0.02, 0.0),
+            node(5, 0.01, 0.00002),
+            node(6, 0.01, 0.01),
+            way(10, &[1, 2], &[("highway", "residential")]),
+            way(11, &[3, 4], &[("highway", "residential")]),
+            way(12, &[5, 6], &[("highway", "residential")]),
+        ]);
+        let graph = build_graph(
+            &xml,
+            r#"{"merge_coincident_nodes":true,"snap_tolerance_m":5}"#,
+            "{}",
+        );
+
+        assert_eq!(graph.build_report.merged_nodes, 1);
+        assert_eq!(graph.connector_count, 1);
+        for node_id in 0..graph.nodes.len() as u32 {
+            assert!(graph
+                .edge_range(node_id)
+                .all(|edge_idx| graph.edges[edge_idx] != node_id));
+        }
+    }
+
+    #[test]
+    fn reports_profile_missing_from_shared_interner() {
+        let (joined, _) = shared_interner_graphs(&["{}"], &["{}", r#"{"key":"railway"}"#]);
+
+        assert!(joined[0].build_report.warnings.is_empty());
+        assert!(joined[1]
+            .build_report
+            .warnings
+            .iter()
+            .any(|warning| warning.contains("shared interner")));
+        assert!(!Arc::ptr_eq(
+            &joined[0].string_interner,
+            &joined[1].string_interner
+        ));
+    }
+}
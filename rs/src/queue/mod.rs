@@ -1,4 +1,5 @@
 use crate::graph::GraphContainer;
+use crate::id_to_js;
 use neon::prelude::*;
 use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
@@ -103,11 +104,12 @@ impl RouteQueue {
                         let id_js = cx.string(request.id);
 
                         let result_value: Handle<JsValue> = match result {
-                            Ok(Some(nodes)) => {
+                            Ok(Some(route)) => {
+                                let nodes = route.nodes;
                                 let js_result = cx.empty_object();
-                                let js_nodes = JsArray::new(&mut cx, nodes.len() as usize);
+                                let js_nodes = JsArray::new(&mut cx, nodes.len());
                                 for (i, node_id) in nodes.iter().enumerate() {
-                                    let js_node = cx.number(*node_id as f64);
+                                    let js_node = id_to_js(&mut cx, *node_id);
                                     js_nodes.set(&mut cx, i as u32, js_node).unwrap();
                                 }
                                 js_result.set(&mut cx, "nodes", js_nodes).unwrap();
@@ -1,8 +1,10 @@
 use crate::core::errors::{GraphError, Result};
 use crate::graph::{ProcessedGraph, RouteNode};
-use crate::routing::distance;
-use rustc_hash::FxHashMap;
-use std::cmp::Ordering;
+use crate::routing::{calculate_bearing, distance};
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::cell::RefCell;
+use std::cmp::{Ordering, Reverse};
 use std::collections::BinaryHeap;
 
 #[derive(Copy, Clone, Eq, PartialEq)]
@@ -28,50 +30,180 @@ impl PartialOrd for State {
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Metric {
+    #[default]
+    Distance,
+    Time,
+    Minimax,
+}
+
+impl std::str::FromStr for Metric {
+    type Err = GraphError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "distance" => Ok(Metric::Distance),
+            "time" => Ok(Metric::Time),
+            "minimax" => Ok(Metric::Minimax),
+            _ => Err(GraphError::RoutingError(format!(
+                "Unknown routing metric '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RouteOptions {
+    pub metric: Metric,
+    pub initial_bearing: Option<f32>,
+    pub prefer_lit: bool,
+    pub required_ways: Vec<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    pub nodes: Vec<i64>,
+    pub cost: u32,
+    pub legs: Vec<RouteLeg>,
+}
+
+impl Route {
+    pub fn waypoint_indices(&self) -> Vec<usize> {
+        match self.legs.first() {
+            Some(first_leg) => std::iter::once(first_leg.node_start_index)
+                .chain(self.legs.iter().map(|leg| leg.node_end_index))
+                .collect(),
+            None => vec![0, self.nodes.len().saturating_sub(1)],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RouteLeg {
+    pub cost: u32,
+    pub node_start_index: usize,
+    pub node_end_index: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchStats {
+    pub expanded: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouteOutcome {
+    Found(Route),
+    NoRouteBetween(usize, usize),
+}
+
+impl RouteOutcome {
+    pub fn found(self) -> Option<Route> {
+        match self {
+            RouteOutcome::Found(route) => Some(route),
+            RouteOutcome::NoRouteBetween(..) => None,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Hash)]
 struct VisitedKey {
     node_id: u32,
     prev_external_id: Option<i64>,
 }
 
+#[derive(Default)]
+struct SearchScratch {
+    open_set: BinaryHeap<State>,
+    g_score: FxHashMap<VisitedKey, u32>,
+    came_from: FxHashMap<VisitedKey, VisitedKey>,
+}
+
+impl SearchScratch {
+    fn clear(&mut self) {
+        self.open_set.clear();
+        self.g_score.clear();
+        self.came_from.clear();
+    }
+}
+
+thread_local! {
+    static SEARCH_SCRATCH: RefCell<SearchScratch> = RefCell::default();
+}
+
 pub fn find_route_through_waypoints(
     graph: &ProcessedGraph,
     waypoints: &[i64],
-) -> Result<Option<Vec<i64>>> {
+    options: &RouteOptions,
+) -> Result<RouteOutcome> {
     if waypoints.len() < 2 {
         return Err(GraphError::RoutingError(
             "At least 2 points are required".to_string(),
         ));
     }
+    if graph.nodes.is_empty() {
+        return Err(GraphError::EmptyGraph(graph.profile_id.clone()));
+    }
 
     let mut full_path: Vec<i64> = Vec::new();
+    let mut total_cost: u32 = 0;
+    let mut legs: Vec<RouteLeg> = Vec::with_capacity(waypoints.len() - 1);
+    let mut stats = SearchStats::default();
+    let later_options = RouteOptions {
+        initial_bearing: None,
+        ..options.clone()
+    };
 
     for i in 0..waypoints.len() - 1 {
         let start_osm_id = waypoints[i];
         let end_osm_id = waypoints[i + 1];
+        let segment_options = if i == 0 { options } else { &later_options };
 
-        match find_route_segment_astar(graph, start_osm_id, end_osm_id)? {
-            Some(segment_path) => {
+        match find_route_segment_astar(
+            graph,
+            start_osm_id,
+            end_osm_id,
+            segment_options,
+            &mut stats,
+        )? {
+            Some(segment) => {
+                let node_start_index = full_path.len().saturating_sub(1);
                 if full_path.is_empty() {
-                    full_path.extend(segment_path);
+                    full_path.extend(segment.nodes);
                 } else {
-                    full_path.extend_from_slice(&segment_path[1..]);
+                    full_path.extend_from_slice(&segment.nodes[1..]);
                 }
+                total_cost = match options.metric {
+                    Metric::Minimax => total_cost.max(segment.cost),
+                    _ => total_cost.saturating_add(segment.cost),
+                };
+                legs.push(RouteLeg {
+                    cost: segment.cost,
+                    node_start_index,
+                    node_end_index: full_path.len() - 1,
+                });
             }
             None => {
-                return Ok(None);
+                return Ok(RouteOutcome::NoRouteBetween(i, i + 1));
             }
         }
     }
 
-    Ok(Some(full_path))
+    Ok(RouteOutcome::Found(Route {
+        nodes: full_path,
+        cost: total_cost,
+        legs,
+    }))
 }
 
-fn find_route_segment_astar(
+pub fn find_route_segment_astar(
     graph: &ProcessedGraph,
     start_osm_id: i64,
     end_osm_id: i64,
-) -> Result<Option<Vec<i64>>> {
+    options: &RouteOptions,
+    stats: &mut SearchStats,
+) -> Result<Option<Route>> {
     let start_node_id = *graph.node_id_map.get(&start_osm_id).ok_or_else(|| {
         GraphError::RoutingError(format!("Start node {} not in graph", start_osm_id))
     })?;
@@ -80,11 +212,55 @@ fn find_route_segment_astar(
         .get(&end_osm_id)
         .ok_or_else(|| GraphError::RoutingError(format!("End node {} not in graph", end_osm_id)))?;
 
-    let end_node = &graph.nodes[end_node_id as usize];
+    if options.metric == Metric::Minimax {
+        return Ok(search_segment_bottleneck(
+            graph,
+            start_node_id,
+            end_node_id,
+            options,
+            stats,
+        ));
+    }
 
-    let mut open_set = BinaryHeap::new();
-    let mut g_score: FxHashMap<VisitedKey, u32> = FxHashMap::default();
-    let mut came_from: FxHashMap<VisitedKey, VisitedKey> = FxHashMap::default();
+    SEARCH_SCRATCH.with(|scratch| match scratch.try_borrow_mut() {
+        Ok(mut scratch) => {
+            scratch.clear();
+            let route = search_segment(
+                graph,
+                start_node_id,
+                end_node_id,
+                options,
+                stats,
+                &mut scratch,
+            );
+            scratch.clear();
+            Ok(route)
+        }
+        Err(_) => Ok(search_segment(
+            graph,
+            start_node_id,
+            end_node_id,
+            options,
+            stats,
+            &mut SearchScratch::default(),
+        )),
+    })
+}
+
+fn search_segment(
+    graph: &ProcessedGraph,
+    start_node_id: u32,
+    end_node_id: u32,
+    options: &RouteOptions,
+    stats: &mut SearchStats,
+    scratch: &mut SearchScratch,
+) -> Option<Route> {
+    let end_node = &graph.nodes[end_node_id as usize];
+    let SearchScratch {
+        open_set,
+        g_score,
+        came_from,
+    } = scratch;
 
     let start_key = VisitedKey {
         node_id: start_node_id,
@@ -92,7 +268,12 @@ fn find_route_segment_astar(
     };
     g_score.insert(start_key, 0);
 
-    let initial_h_cost = heuristic_cost(&graph.nodes[start_node_id as usize], end_node);
+    let initial_h_cost = heuristic_cost(
+        graph,
+        &graph.nodes[start_node_id as usize],
+        end_node,
+        options.metric,
+    );
     open_set.push(State {
         cost: 0,
         estimated_total_cost: initial_h_cost,
@@ -107,14 +288,18 @@ fn find_route_segment_astar(
                     node_id: current.node_id,
                     prev_external_id: current.prev_external_id,
                 },
-                &came_from,
+                came_from,
             );
 
             let path_external = path_internal
                 .iter()
                 .map(|&id| graph.nodes[id as usize].external_id)
                 .collect();
-            return Ok(Some(path_external));
+            return Some(Route {
+                nodes: path_external,
+                cost: current.cost,
+                legs: Vec::new(),
+            });
         }
 
         let current_key = VisitedKey {
@@ -124,16 +309,26 @@ fn find_route_segment_astar(
         if current.cost > *g_score.get(&current_key).unwrap_or(&u32::MAX) {
             continue;
         }
+        stats.expanded += 1;
 
         let current_node_external_id = graph.nodes[current.node_id as usize].external_id;
 
-        for &(neighbor_id, cost) in graph.neighbors(current.node_id) {
+        for edge_idx in graph.edge_range(current.node_id) {
+            let neighbor_id = graph.edges[edge_idx];
             let neighbor_node = &graph.nodes[neighbor_id as usize];
-            if Some(neighbor_node.external_id) == current.prev_external_id {
+            if Some(neighbor_node.external_id) == current.prev_external_id
+                || !graph.is_turn_allowed(current.prev_external_id, current.node_id, neighbor_id)
+            {
                 continue;
             }
 
-            let new_cost = current.cost.saturating_add(cost as u32);
+            let new_cost = current.cost.saturating_add(step_cost(
+                graph,
+                current.prev_external_id,
+                current.node_id,
+                edge_idx,
+                options,
+            ));
             let neighbor_key = VisitedKey {
                 node_id: neighbor_id,
                 prev_external_id: Some(current_node_external_id),
@@ -143,7 +338,7 @@ fn find_route_segment_astar(
                 g_score.insert(neighbor_key, new_cost);
                 came_from.insert(neighbor_key, current_key);
 
-                let h_cost = heuristic_cost(neighbor_node, end_node);
+                let h_cost = heuristic_cost(graph, neighbor_node, end_node, options.metric);
 
                 open_set.push(State {
                     cost: new_cost,
@@ -154,7 +349,423 @@ fn find_route_segment_astar(
             }
         }
     }
-    Ok(None)
+    None
+}
+
+fn search_segment_bottleneck(
+    graph: &ProcessedGraph,
+    start_node_id: u32,
+    end_node_id: u32,
+    options: &RouteOptions,
+    stats: &mut SearchStats,
+) -> Option<Route> {
+    let mut open_set = BinaryHeap::new();
+    let mut best: FxHashMap<VisitedKey, (u32, u32)> = FxHashMap::default();
+    let mut came_from: FxHashMap<VisitedKey, VisitedKey> = FxHashMap::default();
+
+    let start_key = VisitedKey {
+        node_id: start_node_id,
+        prev_external_id: None,
+    };
+    best.insert(start_key, (0, 0));
+    open_set.push(Reverse((0u32, 0u32, start_node_id, None::<i64>)));
+
+    while let Some(Reverse((bottleneck, total, node_id, prev_external_id))) = open_set.pop() {
+        let current_key = VisitedKey {
+            node_id,
+            prev_external_id,
+        };
+        if node_id == end_node_id {
+            let nodes = reconstruct_path(current_key, &came_from)
+                .iter()
+                .map(|&id| graph.nodes[id as usize].external_id)
+                .collect();
+            return Some(Route {
+                nodes,
+                cost: bottleneck,
+                legs: Vec::new(),
+            });
+        }
+        if (bottleneck, total) > *best.get(&current_key).unwrap_or(&(u32::MAX, u32::MAX)) {
+            continue;
+        }
+        stats.expanded += 1;
+
+        let current_node_external_id = graph.nodes[node_id as usize].external_id;
+
+        for edge_idx in graph.edge_range(node_id) {
+            let neighbor_id = graph.edges[edge_idx];
+            let neighbor_node = &graph.nodes[neighbor_id as usize];
+            if Some(neighbor_node.external_id) == prev_external_id
+                || !graph.is_turn_allowed(prev_external_id, node_id, neighbor_id)
+            {
+                continue;
+            }
+
+            let step = step_cost(graph, prev_external_id, node_id, edge_idx, options);
+            if step == u32::MAX {
+                continue;
+            }
+            let candidate = (bottleneck.max(step), total.saturating_add(step));
+            let neighbor_key = VisitedKey {
+                node_id: neighbor_id,
+                prev_external_id: Some(current_node_external_id),
+            };
+
+            if candidate < *best.get(&neighbor_key).unwrap_or(&(u32::MAX, u32::MAX)) {
+                best.insert(neighbor_key, candidate);
+                came_from.insert(neighbor_key, current_key);
+                open_set.push(Reverse((
+                    candidate.0,
+                    candidate.1,
+                    neighbor_id,
+                    Some(current_node_external_id),
+                )));
+            }
+        }
+    }
+    None
+}
+
+pub fn cost_matrix(
+    graph: &ProcessedGraph,
+    points: &[i64],
+    concurrency: Option<usize>,
+) -> Result<Vec<Vec<Option<u32>>>> {
+    let internal_ids =
+        points
+            .iter()
+            .map(|osm_id| {
+                graph.node_id_map.get(osm_id).copied().ok_or_else(|| {
+                    GraphError::RoutingError(format!("Node {} not in graph", osm_id))
+                })
+            })
+            .collect::<Result<Vec<u32>>>()?;
+
+    let sources_per_task = match concurrency {
+        Some(limit) if limit > 0 => internal_ids.len().div_ceil(limit).max(1),
+        _ => 1,
+    };
+    Ok(internal_ids
+        .par_chunks(sources_per_task)
+        .flat_map_iter(|sources| {
+            sources
+                .iter()
+                .map(|&source| costs_from_source(graph, source, &internal_ids))
+        })
+        .collect())
+}
+
+pub fn reachable_costs(
+    graph: &ProcessedGraph,
+    start_osm_id: i64,
+    max_cost: u32,
+    options: &RouteOptions,
+) -> Result<FxHashMap<u32, u32>> {
+    if graph.nodes.is_empty() {
+        return Err(GraphError::EmptyGraph(graph.profile_id.clone()));
+    }
+    let start_node_id = *graph.node_id_map.get(&start_osm_id).ok_or_else(|| {
+        GraphError::RoutingError(format!("Start node {} not in graph", start_osm_id))
+    })?;
+
+    let mut reached: FxHashMap<u32, u32> = FxHashMap::default();
+    explore(
+        graph,
+        start_node_id,
+        options.metric,
+        max_cost,
+        |node_id, cost| {
+            reached.entry(node_id).or_insert(cost);
+            true
+        },
+    );
+    Ok(reached)
+}
+
+pub fn nearest_facility_costs(
+    graph: &ProcessedGraph,
+    origins: &[i64],
+    facilities: &[i64],
+    metric: Metric,
+) -> Result<Vec<Option<(i64, u32)>>> {
+    let internal_id = |osm_id: &i64| {
+        graph
+            .node_id_map
+            .get(osm_id)
+            .copied()
+            .ok_or_else(|| GraphError::RoutingError(format!("Node {} not in graph", osm_id)))
+    };
+    let origin_ids = origins
+        .iter()
+        .map(internal_id)
+        .collect::<Result<Vec<u32>>>()?;
+    let facility_ids = facilities
+        .iter()
+        .map(internal_id)
+        .collect::<Result<Vec<u32>>>()?;
+
+    let mut incoming_offsets = vec![0usize; graph.nodes.len() + 1];
+    for &target in graph.edges.iter() {
+        incoming_offsets[target as usize + 1] += 1;
+    }
+    for i in 1..incoming_offsets.len() {
+        incoming_offsets[i] += incoming_offsets[i - 1];
+    }
+    let mut cursor = incoming_offsets.clone();
+    let mut incoming = vec![(0u32, 0usize); graph.edges.len()];
+    for source in 0..graph.nodes.len() as u32 {
+        for edge_idx in graph.edge_range(source) {
+            let target = graph.edges[edge_idx] as usize;
+            incoming[cursor[target]] = (source, edge_idx);
+            cursor[target] += 1;
+        }
+    }
+
+    let mut pending: FxHashSet<u32> = origin_ids.iter().copied().collect();
+    let mut nearest: FxHashMap<u32, (u32, u32)> = FxHashMap::default();
+    let mut best: FxHashMap<(u32, Option<usize>), u32> = FxHashMap::default();
+    let mut open_set = BinaryHeap::new();
+    for (facility_idx, &facility) in facility_ids.iter().enumerate() {
+        if best.insert((facility, None), 0).is_none() {
+            open_set.push(Reverse((
+                0u32,
+                facility_idx as u32,
+                facility,
+                None::<usize>,
+            )));
+        }
+    }
+
+    while let Some(Reverse((cost, facility_idx, node_id, out_edge))) = open_set.pop() {
+        if pending.is_empty() {
+            break;
+        }
+        if cost > *best.get(&(node_id, out_edge)).unwrap_or(&u32::MAX) {
+            continue;
+        }
+        if pending.remove(&node_id) {
+            nearest.insert(node_id, (facility_idx, cost));
+        }
+
+        let next_node = out_edge.map(|edge_idx| graph.edges[edge_idx]);
+        for &(source, edge_idx) in
+            &incoming[incoming_offsets[node_id as usize]..incoming_offsets[node_id as usize + 1]]
+        {
+            let source_external_id = graph.nodes[source as usize].external_id;
+            let mut new_cost = cost.saturating_add(edge_weight(graph, edge_idx, metric));
+            if let (Some(next_node), Some(out_edge)) = (next_node, out_edge) {
+                if graph.nodes[next_node as usize].external_id == source_external_id
+                    || !graph.is_turn_allowed(Some(source_external_id), node_id, next_node)
+                {
+                    continue;
+                }
+                new_cost = new_cost.saturating_add(turn_cost(
+                    graph,
+                    Some(source_external_id),
+                    node_id,
+                    out_edge,
+                ));
+            }
+
+            let key = (source, Some(edge_idx));
+            if new_cost < *best.get(&key).unwrap_or(&u32::MAX) {
+                best.insert(key, new_cost);
+                open_set.push(Reverse((new_cost, facility_idx, source, Some(edge_idx))));
+            }
+        }
+    }
+
+    Ok(origin_ids
+        .iter()
+        .map(|origin| {
+            nearest
+                .get(origin)
+                .map(|&(facility_idx, cost)| (facilities[facility_idx as usize], cost))
+        })
+        .collect())
+}
+
+fn costs_from_source(graph: &ProcessedGraph, source: u32, targets: &[u32]) -> Vec<Option<u32>> {
+    let mut settled_targets: FxHashMap<u32, u32> = FxHashMap::default();
+    let pending: FxHashSet<u32> = targets.iter().copied().collect();
+
+    explore(
+        graph,
+        source,
+        Metric::Distance,
+        u32::MAX,
+        |node_id, cost| {
+            if pending.contains(&node_id) {
+                settled_targets.entry(node_id).or_insert(cost);
+            }
+            settled_targets.len() < pending.len()
+        },
+    );
+
+    targets
+        .iter()
+        .map(|target| settled_targets.get(target).copied())
+        .collect()
+}
+
+fn explore(
+    graph: &ProcessedGraph,
+    source: u32,
+    metric: Metric,
+    max_cost: u32,
+    mut settle: impl FnMut(u32, u32) -> bool,
+) {
+    let mut open_set = BinaryHeap::new();
+    let mut g_score: FxHashMap<VisitedKey, u32> = FxHashMap::default();
+
+    let start_key = VisitedKey {
+        node_id: source,
+        prev_external_id: None,
+    };
+    g_score.insert(start_key, 0);
+    open_set.push(State {
+        cost: 0,
+        estimated_total_cost: 0,
+        node_id: source,
+        prev_external_id: None,
+    });
+
+    while let Some(current) = open_set.pop() {
+        let current_key = VisitedKey {
+            node_id: current.node_id,
+            prev_external_id: current.prev_external_id,
+        };
+        if current.cost > *g_score.get(&current_key).unwrap_or(&u32::MAX) {
+            continue;
+        }
+
+        if !settle(current.node_id, current.cost) {
+            break;
+        }
+
+        let current_node_external_id = graph.nodes[current.node_id as usize].external_id;
+
+        for edge_idx in graph.edge_range(current.node_id) {
+            let neighbor_id = graph.edges[edge_idx];
+            let neighbor_node = &graph.nodes[neighbor_id as usize];
+            if Some(neighbor_node.external_id) == current.prev_external_id
+                || !graph.is_turn_allowed(current.prev_external_id, current.node_id, neighbor_id)
+            {
+                continue;
+            }
+
+            let new_cost = current
+                .cost
+                .saturating_add(edge_weight(graph, edge_idx, metric))
+                .saturating_add(turn_cost(
+                    graph,
+                    current.prev_external_id,
+                    current.node_id,
+                    edge_idx,
+                ));
+            if new_cost > max_cost {
+                continue;
+            }
+            let neighbor_key = VisitedKey {
+                node_id: neighbor_id,
+                prev_external_id: Some(current_node_external_id),
+            };
+
+            if new_cost < *g_score.get(&neighbor_key).unwrap_or(&u32::MAX) {
+                g_score.insert(neighbor_key, new_cost);
+                open_set.push(State {
+                    cost: new_cost,
+                    estimated_total_cost: new_cost,
+                    node_id: neighbor_id,
+                    prev_external_id: Some(current_node_external_id),
+                });
+            }
+        }
+    }
+}
+
+fn step_cost(
+    graph: &ProcessedGraph,
+    prev_external_id: Option<i64>,
+    via: u32,
+    edge_idx: usize,
+    options: &RouteOptions,
+) -> u32 {
+    edge_weight(graph, edge_idx, options.metric)
+        .saturating_add(turn_cost(graph, prev_external_id, via, edge_idx))
+        .saturating_add(if options.prefer_lit {
+            unlit_cost(graph, edge_idx, options.metric)
+        } else {
+            0
+        })
+        .saturating_add(match (prev_external_id, options.initial_bearing) {
+            (None, Some(bearing)) => bearing_cost(graph, via, edge_idx, bearing, options.metric),
+            _ => 0,
+        })
+}
+
+fn turn_cost(
+    graph: &ProcessedGraph,
+    prev_external_id: Option<i64>,
+    via: u32,
+    edge_idx: usize,
+) -> u32 {
+    match prev_external_id {
+        Some(from) if graph.turn_angle_weight > 0.0 => {
+            (graph.turn_angle(from, via, edge_idx) / 180.0 * graph.turn_angle_weight) as u32
+        }
+        _ => 0,
+    }
+}
+
+fn bearing_cost(
+    graph: &ProcessedGraph,
+    from: u32,
+    edge_idx: usize,
+    bearing: f32,
+    metric: Metric,
+) -> u32 {
+    let from_node = &graph.nodes[from as usize];
+    let to_point = graph
+        .edge_geometry(edge_idx)
+        .first()
+        .copied()
+        .unwrap_or_else(|| {
+            let node = &graph.nodes[graph.edges[edge_idx] as usize];
+            [node.lon, node.lat]
+        });
+
+    let edge_bearing = calculate_bearing(from_node.lat, from_node.lon, to_point[1], to_point[0]);
+    let deviation = (edge_bearing - bearing).rem_euclid(360.0);
+    let deviation = deviation.min(360.0 - deviation);
+
+    (edge_weight(graph, edge_idx, metric) as f32 * deviation / 90.0) as u32
+}
+
+fn unlit_cost(graph: &ProcessedGraph, edge_idx: usize, metric: Metric) -> u32 {
+    if graph.unlit_multiplier <= 1.0 || graph.is_edge_lit(edge_idx) {
+        return 0;
+    }
+    (edge_weight(graph, edge_idx, metric) as f32 * (graph.unlit_multiplier - 1.0)) as u32
+}
+
+fn edge_weight(graph: &ProcessedGraph, edge_idx: usize, metric: Metric) -> u32 {
+    let weight = match metric {
+        Metric::Distance | Metric::Minimax => graph.edge_costs[edge_idx] as u32,
+        Metric::Time => graph.edge_times[edge_idx],
+    };
+    if graph.weight_overlay.multipliers.is_empty() {
+        return weight;
+    }
+    match graph
+        .edge_ways
+        .get(edge_idx)
+        .and_then(|way_idx| graph.weight_overlay.multipliers.get(way_idx))
+    {
+        Some(multiplier) => (weight as f32 * multiplier) as u32,
+        None => weight,
+    }
 }
 
 fn reconstruct_path(
@@ -170,6 +781,328 @@ fn reconstruct_path(
     path
 }
 
-fn heuristic_cost(a: &RouteNode, b: &RouteNode) -> u32 {
-    (distance(a.lat, a.lon, b.lat, b.lon) / 13.8 * 1000.0) as u32
+fn heuristic_cost(graph: &ProcessedGraph, a: &RouteNode, b: &RouteNode, metric: Metric) -> u32 {
+    let distance_km = distance(a.lat, a.lon, b.lat, b.lon, graph.earth_radius_km)
+        * graph.weight_overlay.heuristic_scale();
+    match metric {
+        Metric::Distance => (distance_km / 13.8 * 1000.0) as u32,
+        Metric::Time if graph.max_speed_kmh > 0.0 => {
+            (distance_km / graph.max_speed_kmh * 3_600_000.0) as u32
+        }
+        Metric::Time | Metric::Minimax => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{build_graph, node, osm, way};
+
+    fn fast_detour() -> ProcessedGraph {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.005, 0.005),
+            way(
+                10,
+                &[1, 2],
+                &[("highway", "residential"), ("maxspeed", "10")],
+            ),
+            way(11, &[1, 3, 2], &[("highway", "primary")]),
+        ]);
+        build_graph(&xml, "{}", r#"{"speeds":{"primary":100}}"#)
+    }
+
+    #[test]
+    fn distance_and_time_pick_different_paths() {
+        let graph = fast_detour();
+        let route = |metric| {
+            find_route_through_waypoints(
+                &graph,
+                &[1, 2],
+                &RouteOptions {
+                    metric,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .found()
+            .unwrap()
+        };
+
+        assert_eq!(route(Metric::Distance).nodes, vec![1, 2]);
+        assert_eq!(route(Metric::Time).nodes, vec![1, 3, 2]);
+    }
+
+    #[test]
+    fn time_heuristic_matches_edge_time_units() {
+        let graph = fast_detour();
+        let (via, end) = (graph.node_id_map[&3], graph.node_id_map[&2]);
+        let edge_time = graph.edge_times[graph.find_edge(via, 2).unwrap()];
+
+        let estimate = heuristic_cost(
+            &graph,
+            &graph.nodes[via as usize],
+            &graph.nodes[end as usize],
+            Metric::Time,
+        );
+        assert!(estimate <= edge_time);
+        assert!(estimate as f64 >= edge_time as f64 * 0.99);
+    }
+
+    #[test]
+    fn over_restrictive_profile_reports_empty_graph() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            way(10, &[1, 2], &[("highway", "residential")]),
+        ]);
+        let graph = build_graph(&xml, "{}", r#"{"penalties":{"motorway":1}}"#);
+
+        let result = find_route_through_waypoints(&graph, &[1, 2], &RouteOptions::default());
+        assert!(matches!(result, Err(GraphError::EmptyGraph(id)) if id == "p"));
+        assert!(matches!(
+            graph.find_nearest_node(0.0, 0.0),
+            Err(GraphError::EmptyGraph(_))
+        ));
+    }
+
+    #[test]
+    fn reports_leg_leaving_one_way_island() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.0, 0.01),
+            node(5, 0.02, 0.0),
+            node(6, 0.03, 0.0),
+            way(10, &[3, 1, 2], &[("highway", "residential")]),
+            way(
+                11,
+                &[2, 5],
+                &[("highway", "residential"), ("oneway", "yes")],
+            ),
+            way(12, &[5, 6], &[("highway", "residential")]),
+        ]);
+        let graph = build_graph(&xml, "{}", "{}");
+
+        let outcome =
+            find_route_through_waypoints(&graph, &[1, 5, 3], &RouteOptions::default()).unwrap();
+        assert_eq!(outcome, RouteOutcome::NoRouteBetween(1, 2));
+    }
+
+    #[test]
+    fn turn_angle_weight_prefers_gentle_curve() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.02, 0.0),
+            node(11, 0.0, 0.004),
+            node(12, 0.02, 0.004),
+            node(21, 0.00134, 0.005),
+            node(22, 0.005, 0.00866),
+            node(23, 0.01, 0.01),
+            node(24, 0.015, 0.00866),
+            node(25, 0.01866, 0.005),
+            way(10, &[1, 11, 12, 2], &[("highway", "residential")]),
+            way(
+                20,
+                &[1, 21, 22, 23, 24, 25, 2],
+                &[("highway", "residential")],
+            ),
+        ]);
+        let route = |profile_json| {
+            let graph = build_graph(&xml, "{}", profile_json);
+            find_route_through_waypoints(&graph, &[1, 2], &RouteOptions::default())
+                .unwrap()
+                .found()
+                .unwrap()
+                .nodes
+        };
+
+        assert_eq!(route("{}"), vec![1, 11, 12, 2]);
+        assert_eq!(
+            route(r#"{"turn_angle_weight":10000}"#),
+            vec![1, 21, 22, 23, 24, 25, 2]
+        );
+    }
+
+    fn grid() -> ProcessedGraph {
+        let mut parts = Vec::new();
+        for row in 0..4 {
+            for col in 0..4 {
+                parts.push(node(
+                    row * 4 + col + 1,
+                    col as f64 * 0.001,
+                    row as f64 * 0.001,
+                ));
+            }
+        }
+        for line in 0..4 {
+            let across: Vec<i64> = (0..4).map(|col| line * 4 + col + 1).collect();
+            let down: Vec<i64> = (0..4).map(|row| row * 4 + line + 1).collect();
+            let oneway = if line % 2 == 0 { "yes" } else { "no" };
+            parts.push(way(
+                100 + line,
+                &across,
+                &[("highway", "residential"), ("oneway", oneway)],
+            ));
+            parts.push(way(200 + line, &down, &[("highway", "primary")]));
+        }
+        parts.push(node(90, 0.01, 0.01));
+        parts.push(node(91, 0.011, 0.01));
+        parts.push(way(300, &[90, 91], &[("highway", "residential")]));
+        build_graph(
+            &osm(&parts),
+            "{}",
+            r#"{"penalties":{"residential":3,"primary":1}}"#,
+        )
+    }
+
+    #[test]
+    fn reused_scratch_matches_fresh_scratch() {
+        let graph = grid();
+        let points: Vec<i64> = (1..=16).chain([90]).collect();
+        for metric in [Metric::Distance, Metric::Time] {
+            let options = RouteOptions {
+                metric,
+                ..RouteOptions::default()
+            };
+            for &from in &points {
+                for &to in &points {
+                    let (mut reused_stats, mut fresh_stats) =
+                        (SearchStats::default(), SearchStats::default());
+                    let reused =
+                        find_route_segment_astar(&graph, from, to, &options, &mut reused_stats)
+                            .unwrap();
+                    let fresh = search_segment(
+                        &graph,
+                        graph.node_id_map[&from],
+                        graph.node_id_map[&to],
+                        &options,
+                        &mut fresh_stats,
+                        &mut SearchScratch::default(),
+                    );
+
+                    assert_eq!(reused, fresh, "{} -> {}", from, to);
+                    assert_eq!(reused_stats.expanded, fresh_stats.expanded);
+                    SEARCH_SCRATCH.with(|scratch| {
+                        let scratch = scratch.borrow();
+                        assert!(scratch.open_set.is_empty() && scratch.g_score.is_empty());
+                    });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn prefer_lit_takes_longer_lit_route() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.005, 0.005),
+            way(10, &[1, 2], &[("highway", "residential")]),
+            way(
+                11,
+                &[1, 3, 2],
+                &[("highway", "residential"), ("lit", "yes")],
+            ),
+        ]);
+        let route = |profile_json, prefer_lit| {
+            let graph = build_graph(&xml, "{}", profile_json);
+            let options = RouteOptions {
+                prefer_lit,
+                ..Default::default()
+            };
+            find_route_through_waypoints(&graph, &[1, 2], &options)
+                .unwrap()
+                .found()
+                .unwrap()
+                .nodes
+        };
+
+        let night = r#"{"unlit_multiplier":3}"#;
+        assert_eq!(route(night, false), vec![1, 2]);
+        assert_eq!(route(night, true), vec![1, 3, 2]);
+        assert_eq!(route("{}", true), vec![1, 2]);
+    }
+
+    #[test]
+    fn cost_matrix_ignores_concurrency() {
+        let graph = grid();
+        let points: Vec<i64> = (1..=16).chain([90, 91]).collect();
+        let expected = cost_matrix(&graph, &points, None).unwrap();
+
+        assert_eq!(expected.len(), points.len());
+        assert_eq!(expected[0][0], Some(0));
+        assert_eq!(expected[0][16], None);
+        assert!(expected[3][0] > expected[0][3]);
+        for concurrency in [0, 1, 2, 3, 5, 17, 18, 64] {
+            assert_eq!(
+                cost_matrix(&graph, &points, Some(concurrency)).unwrap(),
+                expected,
+                "concurrency {}",
+                concurrency
+            );
+        }
+    }
+
+    #[test]
+    fn minimax_avoids_single_expensive_edge() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.0, 0.004),
+            node(4, 0.005, 0.004),
+            node(5, 0.01, 0.004),
+            way(10, &[1, 2], &[("highway", "residential")]),
+            way(11, &[1, 3, 4, 5, 2], &[("highway", "residential")]),
+        ]);
+        let graph = build_graph(&xml, "{}", "{}");
+        let route = |metric| {
+            find_route_through_waypoints(
+                &graph,
+                &[1, 2],
+                &RouteOptions {
+                    metric,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .found()
+            .unwrap()
+        };
+
+        assert_eq!(route(Metric::Distance).nodes, vec![1, 2]);
+        let minimax = route(Metric::Minimax);
+        assert_eq!(minimax.nodes, vec![1, 3, 4, 5, 2]);
+        let bottleneck = graph.edge_costs[graph.find_edge(graph.node_id_map[&3], 4).unwrap()];
+        assert_eq!(minimax.cost, bottleneck as u32);
+        assert!(
+            minimax.cost
+                < graph.edge_costs[graph.find_edge(graph.node_id_map[&1], 2).unwrap()] as u32
+        );
+    }
+
+    #[test]
+    fn waypoint_indices_point_at_each_waypoint() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.02, 0.0),
+            node(4, 0.03, 0.0),
+            node(5, 0.04, 0.0),
+            way(10, &[1, 2, 3, 4, 5], &[("highway", "residential")]),
+        ]);
+        let graph = build_graph(&xml, "{}", "{}");
+        let waypoints = [1, 3, 5];
+        let route = find_route_through_waypoints(&graph, &waypoints, &RouteOptions::default())
+            .unwrap()
+            .found()
+            .unwrap();
+
+        let indices = route.waypoint_indices();
+        assert_eq!(indices, vec![0, 2, 4]);
+        for (&index, &waypoint) in indices.iter().zip(&waypoints) {
+            assert_eq!(route.nodes[index], waypoint);
+        }
+    }
 }
@@ -0,0 +1,181 @@
+use rstar::primitives::GeomWithData;
+use rstar::{RTree, AABB};
+
+type IndexedPoint = GeomWithData<[f64; 2], usize>;
+
+pub fn concave_hull(points: &[[f32; 2]], concavity: f64) -> Vec<[f32; 2]> {
+    let mut unique: Vec<[f32; 2]> = points.to_vec();
+    unique.sort_by(|a, b| a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1])));
+    unique.dedup();
+
+    if unique.len() < 3 {
+        return unique;
+    }
+
+    let projected = project(&unique);
+    let hull = convex_hull(&projected);
+    if hull.len() < 3 {
+        return hull.iter().map(|&i| unique[i]).collect();
+    }
+
+    let ring = if concavity.is_finite() && concavity > 0.0 {
+        dig(&projected, hull, concavity)
+    } else {
+        hull
+    };
+
+    let mut polygon: Vec<[f32; 2]> = ring.iter().map(|&i| unique[i]).collect();
+    polygon.push(polygon[0]);
+    polygon
+}
+
+fn project(points: &[[f32; 2]]) -> Vec<[f64; 2]> {
+    let mean_lat = points.iter().map(|p| p[1] as f64).sum::<f64>() / points.len() as f64;
+    let lon_scale = mean_lat.to_radians().cos();
+    points
+        .iter()
+        .map(|p| [p[0] as f64 * lon_scale, p[1] as f64])
+        .collect()
+}
+
+fn cross(o: [f64; 2], a: [f64; 2], b: [f64; 2]) -> f64 {
+    (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+}
+
+fn convex_hull(points: &[[f64; 2]]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..points.len()).collect();
+    order.sort_by(|&a, &b| {
+        points[a][0]
+            .total_cmp(&points[b][0])
+            .then(points[a][1].total_cmp(&points[b][1]))
+    });
+
+    let mut hull: Vec<usize> = Vec::with_capacity(points.len() * 2);
+    for pass in 0..2 {
+        let start = hull.len();
+        for &i in &order {
+            while hull.len() >= start + 2
+                && cross(
+                    points[hull[hull.len() - 2]],
+                    points[hull[hull.len() - 1]],
+                    points[i],
+                ) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(i);
+        }
+        hull.pop();
+        if pass == 0 {
+            order.reverse();
+        }
+    }
+    hull
+}
+
+fn squared_distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)
+}
+
+fn squared_segment_distance(p: [f64; 2], a: [f64; 2], b: [f64; 2]) -> f64 {
+    let dx = b[0] - a[0];
+    let dy = b[1] - a[1];
+    let len_sq = dx * dx + dy * dy;
+    if len_sq == 0.0 {
+        return squared_distance(p, a);
+    }
+    let t = (((p[0] - a[0]) * dx + (p[1] - a[1]) * dy) / len_sq).clamp(0.0, 1.0);
+    squared_distance(p, [a[0] + t * dx, a[1] + t * dy])
+}
+
+fn segments_intersect(p1: [f64; 2], p2: [f64; 2], q1: [f64; 2], q2: [f64; 2]) -> bool {
+    let d1 = cross(q1, q2, p1);
+    let d2 = cross(q1, q2, p2);
+    let d3 = cross(p1, p2, q1);
+    let d4 = cross(p1, p2, q2);
+    ((d1 > 0.0 && d2 < 0.0) || (d1 < 0.0 && d2 > 0.0))
+        && ((d3 > 0.0 && d4 < 0.0) || (d3 < 0.0 && d4 > 0.0))
+}
+
+fn dig(points: &[[f64; 2]], hull: Vec<usize>, concavity: f64) -> Vec<usize> {
+    let mut next = vec![usize::MAX; points.len()];
+    let mut prev = vec![usize::MAX; points.len()];
+    for (i, &a) in hull.iter().enumerate() {
+        let b = hull[(i + 1) % hull.len()];
+        next[a] = b;
+        prev[b] = a;
+    }
+
+    let mut tree: RTree<IndexedPoint> = RTree::bulk_load(
+        (0..points.len())
+            .filter(|&i| next[i] == usize::MAX)
+            .map(|i| IndexedPoint::new(points[i], i))
+            .collect(),
+    );
+
+    let mut queue: Vec<(usize, usize)> = hull.iter().map(|&a| (a, next[a])).collect();
+    let mut ring_len = hull.len();
+
+    while let Some((a, b)) = queue.pop() {
+        if next[a] != b || tree.size() == 0 {
+            continue;
+        }
+
+        let (pa, pb) = (points[a], points[b]);
+        let max_sq_len = squared_distance(pa, pb) / (concavity * concavity);
+        let reach = max_sq_len.sqrt();
+        let envelope = AABB::from_corners(
+            [pa[0].min(pb[0]) - reach, pa[1].min(pb[1]) - reach],
+            [pa[0].max(pb[0]) + reach, pa[1].max(pb[1]) + reach],
+        );
+
+        let (before, after) = (points[prev[a]], points[next[b]]);
+        let mut candidates: Vec<(f64, usize)> = tree
+            .locate_in_envelope(&envelope)
+            .map(|p| (squared_segment_distance(*p.geom(), pa, pb), p.data))
+            .filter(|&(d, i)| {
+                let p = points[i];
+                d < squared_segment_distance(p, before, pa)
+                    && d < squared_segment_distance(p, pb, after)
+                    && squared_distance(p, pa).min(squared_distance(p, pb)) <= max_sq_len
+            })
+            .collect();
+        candidates.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+        let chosen = candidates.into_iter().map(|(_, i)| i).find(|&c| {
+            let pc = points[c];
+            let mut edge_start = a;
+            (0..ring_len).all(|_| {
+                let edge_end = next[edge_start];
+                let (s, e) = (points[edge_start], points[edge_end]);
+                let clear = (edge_start == a && edge_end == b)
+                    || (!segments_intersect(pa, pc, s, e) && !segments_intersect(pc, pb, s, e));
+                edge_start = edge_end;
+                clear
+            })
+        });
+
+        if let Some(c) = chosen {
+            tree.remove(&IndexedPoint::new(points[c], c));
+            next[a] = c;
+            prev[c] = a;
+            next[c] = b;
+            prev[b] = c;
+            ring_len += 1;
+            queue.push((a, c));
+            queue.push((c, b));
+        }
+    }
+
+    let start = hull[0];
+    let mut ring = Vec::with_capacity(ring_len);
+    let mut current = start;
+    loop {
+        ring.push(current);
+        current = next[current];
+        if current == start {
+            break;
+        }
+    }
+    ring
+}
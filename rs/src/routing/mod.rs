@@ -1,11 +1,70 @@
 pub mod algorithm;
+mod isochrone;
+pub mod osrm;
+mod self_test;
+mod tsp;
 
 use crate::core::errors::{GraphError, Result};
-use crate::graph::GraphContainer;
-use algorithm::find_route_through_waypoints;
+use crate::graph::{GraphContainer, ProcessedGraph, SnapDirection};
+use algorithm::{
+    cost_matrix, find_route_through_waypoints, nearest_facility_costs, reachable_costs, Metric,
+    Route, RouteLeg, RouteOptions, RouteOutcome,
+};
+use osrm::GeometryFormat;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+pub const EARTH_RADIUS_KM: f32 = 6371.0;
+const WARM_ROUTE_COUNT: usize = 8;
+
+pub struct OptimizedRoute {
+    pub order: Vec<usize>,
+    pub route: Route,
+}
+
+pub struct IsochroneBand {
+    pub budget: u32,
+    pub nodes: Vec<i64>,
+}
+
+pub struct ReachablePoi {
+    pub id: i64,
+    pub location: [f32; 2],
+    pub cost: u32,
+}
+
+pub struct NearestFacility {
+    pub origin: i64,
+    pub facility: Option<i64>,
+    pub cost: Option<u32>,
+}
+
+pub struct SelfTestReport {
+    pub samples: usize,
+    pub successes: usize,
+    pub median_latency_ms: f64,
+    pub median_expanded: usize,
+}
+
+pub struct RouteComparison {
+    pub route_a: Option<Route>,
+    pub route_b: Option<Route>,
+    pub distance_a: f32,
+    pub distance_b: f32,
+    pub overlap: f32,
+}
 
 impl GraphContainer {
-    pub fn route(&self, profile_id: &str, waypoints: &[i64]) -> Result<Option<Vec<i64>>> {
+    pub fn route(&self, profile_id: &str, waypoints: &[i64]) -> Result<Option<Route>> {
+        self.route_with_options(profile_id, waypoints, &RouteOptions::default())
+            .map(RouteOutcome::found)
+    }
+
+    pub fn route_with_options(
+        &self,
+        profile_id: &str,
+        waypoints: &[i64],
+        options: &RouteOptions,
+    ) -> Result<RouteOutcome> {
         if waypoints.len() < 2 {
             return Err(GraphError::RoutingError(
                 "At least two waypoints are required for routing.".to_string(),
@@ -16,11 +75,363 @@ impl GraphContainer {
             crate::core::errors::GraphError::ProfileNotFound(profile_id.to_string())
         })?;
 
-        find_route_through_waypoints(route_graph, waypoints)
+        if options.required_ways.is_empty() {
+            find_route_through_waypoints(route_graph, waypoints, options)
+        } else {
+            route_through_required_ways(route_graph, waypoints, options)
+        }
+    }
+
+    pub fn route_from_fixes(
+        &self,
+        profile_id: &str,
+        fixes: [[f32; 2]; 2],
+        waypoints: &[i64],
+        options: &RouteOptions,
+    ) -> Result<RouteOutcome> {
+        let route_graph = self
+            .profiles
+            .get(profile_id)
+            .ok_or_else(|| GraphError::ProfileNotFound(profile_id.to_string()))?;
+
+        let [previous, current] = fixes;
+        let origin =
+            route_graph.find_nearest_node_for(current[0], current[1], SnapDirection::Depart)?;
+
+        let mut options = options.clone();
+        if previous != current {
+            options.initial_bearing = Some(calculate_bearing(
+                previous[1],
+                previous[0],
+                current[1],
+                current[0],
+            ));
+        }
+
+        let mut full_waypoints = Vec::with_capacity(waypoints.len() + 1);
+        full_waypoints.push(origin);
+        full_waypoints.extend_from_slice(waypoints);
+
+        self.route_with_options(profile_id, &full_waypoints, &options)
+    }
+
+    pub fn route_osrm(
+        &self,
+        profile_id: &str,
+        coordinates: &[[f32; 2]],
+        geometries: GeometryFormat,
+        options: &RouteOptions,
+    ) -> Result<serde_json::Value> {
+        let route_graph = self
+            .profiles
+            .get(profile_id)
+            .ok_or_else(|| GraphError::ProfileNotFound(profile_id.to_string()))?;
+
+        if coordinates.len() < 2 {
+            return Ok(osrm::error_response(
+                "InvalidOptions",
+                "Number of coordinates needs to be at least two",
+            ));
+        }
+
+        let mut waypoints = Vec::with_capacity(coordinates.len());
+        for (i, &[lon, lat]) in coordinates.iter().enumerate() {
+            let direction = if i == 0 {
+                SnapDirection::Depart
+            } else if i == coordinates.len() - 1 {
+                SnapDirection::Arrive
+            } else {
+                SnapDirection::Any
+            };
+            match route_graph.find_nearest_node_for(lon, lat, direction) {
+                Ok(node_id) => waypoints.push(node_id),
+                Err(GraphError::InvalidCoordinates(message)) => {
+                    return Ok(osrm::error_response("InvalidValue", &message))
+                }
+                Err(GraphError::RoutingError(_)) => {
+                    return Ok(osrm::error_response(
+                        "NoSegment",
+                        &format!("Could not find a matching segment for coordinate {}", i),
+                    ))
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let outcome = self.route_with_options(profile_id, &waypoints, options)?;
+        Ok(osrm::response(
+            route_graph,
+            coordinates,
+            &outcome,
+            geometries,
+            options.metric,
+        ))
+    }
+
+    pub fn matrix(
+        &self,
+        profile_id: &str,
+        points: &[i64],
+        concurrency: Option<usize>,
+    ) -> Result<Vec<Vec<Option<u32>>>> {
+        let route_graph = self
+            .profiles
+            .get(profile_id)
+            .ok_or_else(|| GraphError::ProfileNotFound(profile_id.to_string()))?;
+
+        cost_matrix(route_graph, points, concurrency)
+    }
+
+    pub fn optimized_route(
+        &self,
+        profile_id: &str,
+        start: i64,
+        waypoints: &[i64],
+        end: i64,
+    ) -> Result<Option<OptimizedRoute>> {
+        let route_graph = self
+            .profiles
+            .get(profile_id)
+            .ok_or_else(|| GraphError::ProfileNotFound(profile_id.to_string()))?;
+
+        let mut points = Vec::with_capacity(waypoints.len() + 2);
+        points.push(start);
+        points.extend_from_slice(waypoints);
+        points.push(end);
+
+        let matrix = cost_matrix(route_graph, &points, None)?;
+        let order = match tsp::solve_open_tsp(&matrix) {
+            Some(order) => order,
+            None => return Ok(None),
+        };
+
+        let ordered_points: Vec<i64> = std::iter::once(start)
+            .chain(order.iter().map(|&idx| points[idx]))
+            .chain(std::iter::once(end))
+            .collect();
+
+        Ok(
+            find_route_through_waypoints(route_graph, &ordered_points, &RouteOptions::default())?
+                .found()
+                .map(|route| OptimizedRoute {
+                    order: order.into_iter().map(|idx| idx - 1).collect(),
+                    route,
+                }),
+        )
+    }
+
+    pub fn isochrone_polygon(
+        &self,
+        profile_id: &str,
+        start: i64,
+        max_cost: u32,
+        concavity: f64,
+        options: &RouteOptions,
+    ) -> Result<Vec<[f32; 2]>> {
+        let route_graph = self
+            .profiles
+            .get(profile_id)
+            .ok_or_else(|| GraphError::ProfileNotFound(profile_id.to_string()))?;
+
+        let points: Vec<[f32; 2]> = reachable_costs(route_graph, start, max_cost, options)?
+            .keys()
+            .map(|&node_id| {
+                let node = &route_graph.nodes[node_id as usize];
+                [node.lon, node.lat]
+            })
+            .collect();
+
+        Ok(isochrone::concave_hull(&points, concavity))
+    }
+
+    pub fn isochrone_bands(
+        &self,
+        profile_id: &str,
+        start: i64,
+        budgets: &[u32],
+        options: &RouteOptions,
+    ) -> Result<Vec<IsochroneBand>> {
+        let route_graph = self
+            .profiles
+            .get(profile_id)
+            .ok_or_else(|| GraphError::ProfileNotFound(profile_id.to_string()))?;
+
+        let max_budget = budgets.iter().copied().max().unwrap_or(0);
+        let mut best_costs: FxHashMap<i64, u32> = FxHashMap::default();
+        for (node_id, cost) in reachable_costs(route_graph, start, max_budget, options)? {
+            let external_id = route_graph.nodes[node_id as usize].external_id;
+            let best = best_costs.entry(external_id).or_insert(cost);
+            *best = (*best).min(cost);
+        }
+
+        let mut by_cost: Vec<(u32, i64)> = best_costs
+            .into_iter()
+            .map(|(external_id, cost)| (cost, external_id))
+            .collect();
+        by_cost.sort_unstable();
+
+        Ok(budgets
+            .iter()
+            .map(|&budget| {
+                let reached = by_cost.partition_point(|&(cost, _)| cost <= budget);
+                IsochroneBand {
+                    budget,
+                    nodes: by_cost[..reached].iter().map(|&(_, id)| id).collect(),
+                }
+            })
+            .collect())
+    }
+
+    pub fn reachable_pois(
+        &self,
+        profile_id: &str,
+        start: i64,
+        max_cost: u32,
+        tag_key: &str,
+        tag_value: Option<&str>,
+        options: &RouteOptions,
+    ) -> Result<Vec<ReachablePoi>> {
+        let route_graph = self
+            .profiles
+            .get(profile_id)
+            .ok_or_else(|| GraphError::ProfileNotFound(profile_id.to_string()))?;
+
+        let mut best: FxHashMap<i64, ReachablePoi> = FxHashMap::default();
+        for (node_id, cost) in reachable_costs(route_graph, start, max_cost, options)? {
+            let node = &route_graph.nodes[node_id as usize];
+            let matches = match route_graph.tag_value(&node.tags, tag_key) {
+                Some(value) => tag_value.is_none_or(|expected| expected == value),
+                None => false,
+            };
+            if !matches {
+                continue;
+            }
+
+            let poi = best.entry(node.external_id).or_insert(ReachablePoi {
+                id: node.external_id,
+                location: [node.lon, node.lat],
+                cost,
+            });
+            poi.cost = poi.cost.min(cost);
+        }
+
+        let mut pois: Vec<ReachablePoi> = best.into_values().collect();
+        pois.sort_unstable_by_key(|poi| (poi.cost, poi.id));
+        Ok(pois)
+    }
+
+    pub fn nearest_facilities(
+        &self,
+        profile_id: &str,
+        origins: &[i64],
+        facilities: &[i64],
+        options: &RouteOptions,
+    ) -> Result<Vec<NearestFacility>> {
+        let route_graph = self
+            .profiles
+            .get(profile_id)
+            .ok_or_else(|| GraphError::ProfileNotFound(profile_id.to_string()))?;
+
+        let costs = nearest_facility_costs(route_graph, origins, facilities, options.metric)?;
+        Ok(origins
+            .iter()
+            .zip(costs)
+            .map(|(&origin, nearest)| NearestFacility {
+                origin,
+                facility: nearest.map(|(facility, _)| facility),
+                cost: nearest.map(|(_, cost)| cost),
+            })
+            .collect())
+    }
+
+    pub fn self_test(&self, profile_id: &str, sample_count: usize) -> Result<SelfTestReport> {
+        let route_graph = self
+            .profiles
+            .get(profile_id)
+            .ok_or_else(|| GraphError::ProfileNotFound(profile_id.to_string()))?;
+
+        self_test::run(route_graph, sample_count)
+    }
+
+    pub fn warm(&self, profile_id: &str) -> Result<()> {
+        let route_graph = self
+            .profiles
+            .get(profile_id)
+            .ok_or_else(|| GraphError::ProfileNotFound(profile_id.to_string()))?;
+
+        std::hint::black_box(route_graph.touch_memory());
+        if route_graph.bounds().is_some() {
+            self_test::run(route_graph, WARM_ROUTE_COUNT)?;
+        }
+        Ok(())
+    }
+
+    pub fn compare_routes(
+        &self,
+        profile_a: &str,
+        profile_b: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<RouteComparison> {
+        let route_a = self.route(profile_a, &[start, end])?;
+        let route_b = self.route(profile_b, &[start, end])?;
+
+        let distance_a = route_a
+            .as_ref()
+            .map_or(0.0, |r| path_distance(&self.profiles[profile_a], &r.nodes));
+        let distance_b = route_b
+            .as_ref()
+            .map_or(0.0, |r| path_distance(&self.profiles[profile_b], &r.nodes));
+
+        let overlap = match (&route_a, &route_b) {
+            (Some(a), Some(b)) => {
+                let nodes_a: FxHashSet<i64> = a.nodes.iter().copied().collect();
+                let nodes_b: FxHashSet<i64> = b.nodes.iter().copied().collect();
+                let shared = nodes_a.intersection(&nodes_b).count();
+                let total = nodes_a.union(&nodes_b).count();
+                if total > 0 {
+                    shared as f32 / total as f32
+                } else {
+                    0.0
+                }
+            }
+            _ => 0.0,
+        };
+
+        Ok(RouteComparison {
+            route_a,
+            route_b,
+            distance_a,
+            distance_b,
+            overlap,
+        })
     }
 }
 
-pub fn distance(lat1: f32, lon1: f32, lat2: f32, lon2: f32) -> f32 {
+pub fn path_distance(graph: &ProcessedGraph, nodes: &[i64]) -> f32 {
+    graph
+        .shape(nodes)
+        .windows(2)
+        .filter_map(|pair| {
+            let (a, b) = (pair[0]?, pair[1]?);
+            Some(distance(a[1], a[0], b[1], b[0], graph.earth_radius_km) * 1000.0)
+        })
+        .sum()
+}
+
+pub fn cumulative_distances(graph: &ProcessedGraph, nodes: &[i64]) -> Vec<f32> {
+    let mut total = 0.0;
+    let mut distances = Vec::with_capacity(nodes.len());
+    for i in 0..nodes.len() {
+        if i > 0 {
+            total += path_distance(graph, &nodes[i - 1..=i]);
+        }
+        distances.push(total);
+    }
+    distances
+}
+
+pub fn distance(lat1: f32, lon1: f32, lat2: f32, lon2: f32, earth_radius_km: f32) -> f32 {
     let lat1_cos = lat1.to_radians().cos();
     let lat2_cos = lat2.to_radians().cos();
 
@@ -29,5 +440,193 @@ pub fn distance(lat1: f32, lon1: f32, lat2: f32, lon2: f32) -> f32 {
 
     let a = d_lat_half_sin * d_lat_half_sin + lat1_cos * lat2_cos * d_lon_half_sin * d_lon_half_sin;
 
-    12742.0 * a.sqrt().asin()
+    2.0 * earth_radius_km * a.sqrt().asin()
+}
+
+pub fn meters_per_degree(earth_radius_km: f32) -> f32 {
+    earth_radius_km.to_radians() * 1000.0
+}
+
+pub fn calculate_bearing(lat1: f32, lon1: f32, lat2: f32, lon2: f32) -> f32 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lon = (lon2 - lon1).to_radians();
+
+    let y = d_lon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lon.cos();
+
+    (y.atan2(x).to_degrees() + 360.0) % 360.0
+}
+
+fn route_through_required_ways(
+    graph: &ProcessedGraph,
+    waypoints: &[i64],
+    options: &RouteOptions,
+) -> Result<RouteOutcome> {
+    let mut inserted: Vec<i64> = Vec::new();
+    let mut previous = waypoints[0];
+    for &way_id in &options.required_ways {
+        let way = graph
+            .ways
+            .iter()
+            .find(|way| way.osm_id == way_id)
+            .ok_or_else(|| {
+                GraphError::RoutingError(format!("Required way {} not in graph", way_id))
+            })?;
+        let forward: Vec<i64> = way
+            .node_ids
+            .iter()
+            .map(|&node_id| graph.nodes[node_id as usize].external_id)
+            .collect();
+        let backward: Vec<i64> = forward.iter().rev().copied().collect();
+
+        let mut best: Option<(u32, Vec<i64>)> = None;
+        for way_nodes in [forward, backward] {
+            let mut points = Vec::with_capacity(way_nodes.len() + 1);
+            points.push(previous);
+            points.extend_from_slice(&way_nodes);
+            if let RouteOutcome::Found(route) =
+                find_route_through_waypoints(graph, &points, options)?
+            {
+                if best.as_ref().is_none_or(|(cost, _)| route.cost < *cost) {
+                    best = Some((route.cost, way_nodes));
+                }
+            }
+        }
+        let Some((_, way_nodes)) = best else {
+            return Ok(RouteOutcome::NoRouteBetween(0, 1));
+        };
+        previous = *way_nodes.last().unwrap_or(&previous);
+        inserted.extend(way_nodes);
+    }
+
+    let mut expanded = Vec::with_capacity(waypoints.len() + inserted.len());
+    expanded.push(waypoints[0]);
+    expanded.extend_from_slice(&inserted);
+    expanded.extend_from_slice(&waypoints[1..]);
+
+    let extra = inserted.len();
+    match find_route_through_waypoints(graph, &expanded, options)? {
+        RouteOutcome::NoRouteBetween(from, to) => Ok(RouteOutcome::NoRouteBetween(
+            from.saturating_sub(extra),
+            if to <= extra { 1 } else { to - extra },
+        )),
+        RouteOutcome::Found(mut route) => {
+            let first_legs: Vec<RouteLeg> = route.legs.drain(..=extra).collect();
+            let merged = RouteLeg {
+                cost: first_legs
+                    .iter()
+                    .fold(0, |total, leg| match options.metric {
+                        Metric::Minimax => total.max(leg.cost),
+                        _ => total.saturating_add(leg.cost),
+                    }),
+                node_start_index: first_legs[0].node_start_index,
+                node_end_index: first_legs[extra].node_end_index,
+            };
+            route.legs.insert(0, merged);
+            Ok(RouteOutcome::Found(route))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{build_graph, node, osm, way};
+
+    #[test]
+    fn bands_grow_with_budget() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.02, 0.0),
+            node(4, 0.03, 0.0),
+            way(10, &[1, 2, 3, 4], &[("highway", "residential")]),
+        ]);
+        let mut container = GraphContainer::new();
+        container
+            .profiles
+            .insert("p".to_string(), build_graph(&xml, "{}", "{}"));
+
+        let bands = container
+            .isochrone_bands("p", 1, &[0, 1500, 2500, 5000], &RouteOptions::default())
+            .unwrap();
+        let nodes: Vec<Vec<i64>> = bands.into_iter().map(|band| band.nodes).collect();
+        assert_eq!(
+            nodes,
+            vec![vec![1], vec![1, 2], vec![1, 2, 3], vec![1, 2, 3, 4]]
+        );
+    }
+
+    #[test]
+    fn distance_callers_agree_on_reference_pair() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.1, 0.0),
+            way(10, &[1, 2], &[("highway", "residential")]),
+        ]);
+        for (options_json, radius_km) in [
+            ("{}", EARTH_RADIUS_KM),
+            (r#"{"earth_radius_km":3000}"#, 3000.0),
+        ] {
+            let graph = build_graph(&xml, options_json, "{}");
+            let expected_m = radius_km * 0.1_f32.to_radians() * 1000.0;
+            let close = |actual: f32| (actual - expected_m).abs() < expected_m * 1e-3;
+
+            assert_eq!(graph.earth_radius_km, radius_km);
+            assert!(close(distance(0.0, 0.0, 0.0, 0.1, radius_km) * 1000.0));
+            assert!(close(meters_per_degree(radius_km) * 0.1));
+            assert!(close(path_distance(&graph, &[1, 2])));
+            let edge_idx = graph.find_edge(graph.node_id_map[&1], 2).unwrap();
+            assert!(close(graph.edge_costs[edge_idx] as f32));
+
+            let within = |radius_m: f32| {
+                graph
+                    .find_nodes_within_radius(0.0, 0.0, radius_m)
+                    .unwrap()
+                    .iter()
+                    .any(|node| node.external_id == 2)
+            };
+            assert!(within(expected_m * 1.01));
+            assert!(!within(expected_m * 0.99));
+        }
+    }
+
+    #[test]
+    fn required_way_pulls_route_through_detour() {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.03, 0.0),
+            node(3, 0.01, 0.01),
+            node(4, 0.02, 0.01),
+            node(5, 0.05, 0.0),
+            node(6, 0.06, 0.0),
+            way(10, &[1, 2], &[("highway", "residential")]),
+            way(11, &[4, 3], &[("highway", "residential")]),
+            way(12, &[1, 3], &[("highway", "residential")]),
+            way(13, &[4, 2], &[("highway", "residential")]),
+            way(14, &[5, 6], &[("highway", "residential")]),
+        ]);
+        let graph = build_graph(&xml, "{}", "{}");
+        let options = RouteOptions {
+            required_ways: vec![11],
+            ..Default::default()
+        };
+
+        let route = route_through_required_ways(&graph, &[1, 2, 1], &options)
+            .unwrap()
+            .found()
+            .unwrap();
+        assert_eq!(route.nodes, vec![1, 3, 4, 2, 1]);
+        assert_eq!(route.legs.len(), 2);
+        assert_eq!(route.waypoint_indices(), vec![0, 3, 4]);
+
+        assert!(matches!(
+            route_through_required_ways(&graph, &[1, 2, 5], &options).unwrap(),
+            RouteOutcome::NoRouteBetween(1, 2)
+        ));
+        assert!(matches!(
+            route_through_required_ways(&graph, &[5, 6], &options).unwrap(),
+            RouteOutcome::NoRouteBetween(0, 1)
+        ));
+    }
 }
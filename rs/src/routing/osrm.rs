@@ -0,0 +1,199 @@
+use super::algorithm::{Metric, Route, RouteOutcome};
+use super::{distance, path_distance};
+use crate::graph::{unwalkable_route, ProcessedGraph};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GeometryFormat {
+    #[default]
+    Polyline,
+    Polyline6,
+    GeoJson,
+}
+
+pub fn error_response(code: &str, message: &str) -> Value {
+    json!({ "code": code, "message": message })
+}
+
+pub fn response(
+    graph: &ProcessedGraph,
+    coordinates: &[[f32; 2]],
+    outcome: &RouteOutcome,
+    geometries: GeometryFormat,
+    metric: Metric,
+) -> Value {
+    let route = match outcome {
+        RouteOutcome::Found(route) => route,
+        RouteOutcome::NoRouteBetween(..) => {
+            return error_response("NoRoute", "Impossible route between points")
+        }
+    };
+
+    let edges = match graph.route_edges(&route.nodes) {
+        Ok(edges) => edges,
+        Err(index) => return error_response("NoRoute", &unwalkable_route(index).to_string()),
+    };
+    let duration = |from: usize, to: usize| {
+        let millis: u64 = edges
+            .get(from..to)
+            .unwrap_or_default()
+            .iter()
+            .map(|&edge_idx| graph.edge_times[edge_idx] as u64)
+            .sum();
+        round(millis as f64 / 1000.0, 1)
+    };
+
+    let spans: Vec<(usize, usize, u32)> = if route.legs.is_empty() {
+        vec![(0, route.nodes.len().saturating_sub(1), route.cost)]
+    } else {
+        route
+            .legs
+            .iter()
+            .map(|leg| (leg.node_start_index, leg.node_end_index, leg.cost))
+            .collect()
+    };
+    let legs: Vec<Value> = spans
+        .iter()
+        .map(|&(start, end, cost)| {
+            json!({
+                "distance": round(path_distance(graph, &route.nodes[start..=end]) as f64, 1),
+                "duration": duration(start, end),
+                "weight": cost,
+                "summary": "",
+                "steps": [],
+            })
+        })
+        .collect();
+
+    let shape: Vec<[f32; 2]> = graph.shape(&route.nodes).into_iter().flatten().collect();
+    let geometry = match geometries {
+        GeometryFormat::Polyline => json!(encode_polyline(&shape, 5)),
+        GeometryFormat::Polyline6 => json!(encode_polyline(&shape, 6)),
+        GeometryFormat::GeoJson => json!({
+            "type": "LineString",
+            "coordinates": shape
+                .iter()
+                .map(|&[lon, lat]| [round(lon as f64, 6), round(lat as f64, 6)])
+                .collect::<Vec<_>>(),
+        }),
+    };
+
+    json!({
+        "code": "Ok",
+        "routes": [{
+            "geometry": geometry,
+            "legs": legs,
+            "distance": round(path_distance(graph, &route.nodes) as f64, 1),
+            "duration": duration(0, edges.len()),
+            "weight_name": match metric {
+                Metric::Distance => "distance",
+                Metric::Time => "time",
+                Metric::Minimax => "minimax",
+            },
+            "weight": route.cost,
+        }],
+        "waypoints": waypoints(graph, coordinates, route, &edges),
+    })
+}
+
+fn waypoints(
+    graph: &ProcessedGraph,
+    coordinates: &[[f32; 2]],
+    route: &Route,
+    edges: &[usize],
+) -> Vec<Value> {
+    route
+        .waypoint_indices()
+        .iter()
+        .zip(coordinates)
+        .map(|(&node_index, &[lon, lat])| {
+            let node = &graph.nodes[graph.node_id_map[&route.nodes[node_index]] as usize];
+            let name = edges
+                .get(node_index.min(edges.len().saturating_sub(1)))
+                .and_then(|&edge_idx| graph.ways.get(graph.edge_ways[edge_idx] as usize))
+                .and_then(|way| graph.tag_value(&way.tags, "name"))
+                .unwrap_or_default();
+            let snap_distance = distance(lat, lon, node.lat, node.lon, graph.earth_radius_km);
+            json!({
+                "name": name,
+                "location": [round(node.lon as f64, 6), round(node.lat as f64, 6)],
+                "distance": round(snap_distance as f64 * 1000.0, 1),
+            })
+        })
+        .collect()
+}
+
+pub fn encode_polyline(points: &[[f32; 2]], precision: u32) -> String {
+    let factor = 10f64.powi(precision as i32);
+    let mut encoded = String::new();
+    let mut previous = [0i64; 2];
+    for &[lon, lat] in points {
+        let current = [
+            (lat as f64 * factor).round() as i64,
+            (lon as f64 * factor).round() as i64,
+        ];
+        for (value, last) in current.iter().zip(previous.iter_mut()) {
+            let delta = value - *last;
+            let mut chunks = ((delta << 1) ^ (delta >> 63)) as u64;
+            while chunks >= 0x20 {
+                encoded.push((((chunks & 0x1f) | 0x20) as u8 + 63) as char);
+                chunks >>= 5;
+            }
+            encoded.push((chunks as u8 + 63) as char);
+            *last = *value;
+        }
+    }
+    encoded
+}
+
+fn round(value: f64, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    (value * factor).round() / factor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{response, GeometryFormat};
+    use crate::routing::algorithm::{Metric, Route, RouteOutcome};
+    use crate::test_support::{build_graph, node, osm, way};
+
+    fn osrm_response(nodes: Vec<i64>) -> serde_json::Value {
+        let xml = osm(&[
+            node(1, 0.0, 0.0),
+            node(2, 0.001, 0.0),
+            node(3, 0.002, 0.0),
+            way(10, &[1, 2, 3], &[("highway", "residential")]),
+        ]);
+        let graph = build_graph(&xml, "{}", "{}");
+        let outcome = RouteOutcome::Found(Route {
+            nodes,
+            cost: 0,
+            legs: Vec::new(),
+        });
+        response(
+            &graph,
+            &[[0.0, 0.0], [0.002, 0.0]],
+            &outcome,
+            GeometryFormat::GeoJson,
+            Metric::Time,
+        )
+    }
+
+    #[test]
+    fn reports_duration_of_walkable_route() {
+        let response = osrm_response(vec![1, 2, 3]);
+        assert_eq!(response["code"], "Ok");
+        assert!(
+            response["routes"][0]["legs"][0]["duration"]
+                .as_f64()
+                .unwrap()
+                > 0.0
+        );
+    }
+
+    #[test]
+    fn rejects_route_that_cannot_be_walked() {
+        let response = osrm_response(vec![1, 99]);
+        assert_eq!(response["code"], "NoRoute");
+    }
+}
@@ -0,0 +1,76 @@
+use super::algorithm::{find_route_segment_astar, RouteOptions, SearchStats};
+use super::SelfTestReport;
+use crate::core::errors::{GraphError, Result};
+use crate::graph::ProcessedGraph;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+const MAX_DRAWS_PER_SAMPLE: usize = 8;
+
+struct XorShift(u64);
+
+impl XorShift {
+    fn seeded() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        XorShift(nanos | 1)
+    }
+
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+pub fn run(graph: &ProcessedGraph, sample_count: usize) -> Result<SelfTestReport> {
+    let bounds = graph
+        .bounds()
+        .ok_or_else(|| GraphError::EmptyGraph(graph.profile_id.clone()))?;
+    let (lower, upper) = (bounds.lower(), bounds.upper());
+
+    let mut rng = XorShift::seeded();
+    let mut random_node = || -> Result<i64> {
+        let lon = lower[0] + (upper[0] - lower[0]) * rng.next_f32();
+        let lat = lower[1] + (upper[1] - lower[1]) * rng.next_f32();
+        graph.find_nearest_node(lon, lat)
+    };
+
+    let options = RouteOptions::default();
+    let mut successes = 0;
+    let mut latencies_ms: Vec<f64> = Vec::with_capacity(sample_count);
+    let mut expanded: Vec<usize> = Vec::with_capacity(sample_count);
+
+    for _ in 0..sample_count {
+        let start = random_node()?;
+        let mut end = random_node()?;
+        for _ in 0..MAX_DRAWS_PER_SAMPLE {
+            if end != start {
+                break;
+            }
+            end = random_node()?;
+        }
+
+        let mut stats = SearchStats::default();
+        let started_at = Instant::now();
+        let route = find_route_segment_astar(graph, start, end, &options, &mut stats)?;
+        latencies_ms.push(started_at.elapsed().as_secs_f64() * 1000.0);
+        expanded.push(stats.expanded);
+
+        if route.is_some() {
+            successes += 1;
+        }
+    }
+
+    latencies_ms.sort_by(f64::total_cmp);
+    expanded.sort_unstable();
+
+    Ok(SelfTestReport {
+        samples: sample_count,
+        successes,
+        median_latency_ms: latencies_ms.get(sample_count / 2).copied().unwrap_or(0.0),
+        median_expanded: expanded.get(sample_count / 2).copied().unwrap_or(0),
+    })
+}
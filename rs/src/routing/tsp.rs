@@ -0,0 +1,260 @@
+const EXACT_LIMIT: usize = 10;
+const INFINITE: u64 = u64::MAX / 4;
+
+fn edge_cost(matrix: &[Vec<Option<u32>>], from: usize, to: usize) -> u64 {
+    matrix[from][to].map_or(INFINITE, |c| c as u64)
+}
+
+fn path_cost(matrix: &[Vec<Option<u32>>], path: &[usize]) -> u64 {
+    path.windows(2)
+        .map(|w| edge_cost(matrix, w[0], w[1]))
+        .fold(0u64, |acc, c| acc.saturating_add(c))
+}
+
+pub fn solve_open_tsp(matrix: &[Vec<Option<u32>>]) -> Option<Vec<usize>> {
+    let n = matrix.len();
+    if n < 2 {
+        return None;
+    }
+
+    let middle = n - 2;
+    let order = if middle <= EXACT_LIMIT {
+        held_karp(matrix)?
+    } else {
+        two_opt(matrix, nearest_neighbor(matrix))
+    };
+
+    let mut path = Vec::with_capacity(n);
+    path.push(0);
+    path.extend(&order);
+    path.push(n - 1);
+
+    if order.len() != middle || path_cost(matrix, &path) >= INFINITE {
+        return None;
+    }
+    Some(order)
+}
+
+fn held_karp(matrix: &[Vec<Option<u32>>]) -> Option<Vec<usize>> {
+    let n = matrix.len();
+    let end = n - 1;
+    let middle = n - 2;
+    if middle == 0 {
+        return Some(Vec::new());
+    }
+
+    let full_mask = (1usize << middle) - 1;
+    let mut dp = vec![vec![INFINITE; middle]; 1 << middle];
+    let mut parent = vec![vec![usize::MAX; middle]; 1 << middle];
+
+    for j in 0..middle {
+        dp[1 << j][j] = edge_cost(matrix, 0, j + 1);
+    }
+
+    for mask in 1..=full_mask {
+        for last in 0..middle {
+            if mask & (1 << last) == 0 || dp[mask][last] >= INFINITE {
+                continue;
+            }
+            for next in 0..middle {
+                if mask & (1 << next) != 0 {
+                    continue;
+                }
+                let next_mask = mask | (1 << next);
+                let cost = dp[mask][last].saturating_add(edge_cost(matrix, last + 1, next + 1));
+                if cost < dp[next_mask][next] {
+                    dp[next_mask][next] = cost;
+                    parent[next_mask][next] = last;
+                }
+            }
+        }
+    }
+
+    let mut best_last = 0;
+    let mut best_cost = u64::MAX;
+    for (last, &partial) in dp[full_mask].iter().enumerate() {
+        let cost = partial.saturating_add(edge_cost(matrix, last + 1, end));
+        if cost < best_cost {
+            best_cost = cost;
+            best_last = last;
+        }
+    }
+    // With a waypoint that cannot be reached, no tour has a finite cost and
+    // the parent chain below would stop short of visiting every point.
+    if best_cost >= INFINITE {
+        return None;
+    }
+
+    let mut order = Vec::with_capacity(middle);
+    let mut mask = full_mask;
+    let mut current = best_last;
+    while current != usize::MAX {
+        order.push(current + 1);
+        let prev = parent[mask][current];
+        mask &= !(1 << current);
+        current = prev;
+    }
+    order.reverse();
+    (order.len() == middle).then_some(order)
+}
+
+fn nearest_neighbor(matrix: &[Vec<Option<u32>>]) -> Vec<usize> {
+    let n = matrix.len();
+    let mut remaining: Vec<usize> = (1..n - 1).collect();
+    let mut order = Vec::with_capacity(remaining.len());
+    let mut current = 0;
+
+    while !remaining.is_empty() {
+        let (idx, _) = remaining
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, &candidate)| edge_cost(matrix, current, candidate))
+            .unwrap();
+        current = remaining.swap_remove(idx);
+        order.push(current);
+    }
+    order
+}
+
+fn two_opt(matrix: &[Vec<Option<u32>>], order: Vec<usize>) -> Vec<usize> {
+    let n = matrix.len();
+    let mut path = Vec::with_capacity(n);
+    path.push(0);
+    path.extend(order);
+    path.push(n - 1);
+
+    let mut best_cost = path_cost(matrix, &path);
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 1..path.len() - 2 {
+            for j in i + 1..path.len() - 1 {
+                path[i..=j].reverse();
+                let cost = path_cost(matrix, &path);
+                if cost < best_cost {
+                    best_cost = cost;
+                    improved = true;
+                } else {
+                    path[i..=j].reverse();
+                }
+            }
+        }
+    }
+
+    path[1..path.len() - 1].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{path_cost, solve_open_tsp, EXACT_LIMIT};
+
+    fn pseudo_random_matrix(n: usize, seed: u64) -> Vec<Vec<Option<u32>>> {
+        let mut state = seed;
+        (0..n)
+            .map(|from| {
+                (0..n)
+                    .map(|to| {
+                        state = state
+                            .wrapping_mul(6364136223846793005)
+                            .wrapping_add(1442695040888963407);
+                        (from != to).then_some((state >> 33) as u32 % 1000 + 1)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn full_path(n: usize, order: &[usize]) -> Vec<usize> {
+        std::iter::once(0)
+            .chain(order.iter().copied())
+            .chain(std::iter::once(n - 1))
+            .collect()
+    }
+
+    fn brute_force_cost(matrix: &[Vec<Option<u32>>]) -> u64 {
+        fn visit(
+            matrix: &[Vec<Option<u32>>],
+            order: &mut Vec<usize>,
+            left: &mut Vec<usize>,
+        ) -> u64 {
+            if left.is_empty() {
+                return path_cost(matrix, &full_path(matrix.len(), order));
+            }
+            let mut best = u64::MAX;
+            for i in 0..left.len() {
+                let point = left.remove(i);
+                order.push(point);
+                best = best.min(visit(matrix, order, left));
+                order.pop();
+                left.insert(i, point);
+            }
+            best
+        }
+        let n = matrix.len();
+        visit(matrix, &mut Vec::new(), &mut (1..n - 1).collect())
+    }
+
+    fn assert_visits_every_point(n: usize, order: &[usize]) {
+        let mut sorted = order.to_vec();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (1..n - 1).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn exact_order_matches_brute_force() {
+        for n in 2..=10 {
+            for seed in 0..3 {
+                let matrix = pseudo_random_matrix(n, seed * 31 + n as u64);
+                let order = solve_open_tsp(&matrix).unwrap();
+
+                assert_visits_every_point(n, &order);
+                assert_eq!(
+                    path_cost(&matrix, &full_path(n, &order)),
+                    brute_force_cost(&matrix),
+                    "n = {}, seed = {}",
+                    n,
+                    seed
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn heuristic_orders_points_along_a_line() {
+        let n = EXACT_LIMIT + 6;
+        // Start at position 0, end at the far right, with the stops between
+        // them shuffled so the input order is no help.
+        let positions: Vec<i64> = std::iter::once(0)
+            .chain((1..n as i64 - 1).map(|i| (i * 5) % (n as i64 - 2) + 1))
+            .chain(std::iter::once(n as i64 - 1))
+            .collect();
+        let matrix: Vec<Vec<Option<u32>>> = positions
+            .iter()
+            .map(|a| {
+                positions
+                    .iter()
+                    .map(|b| Some((a - b).unsigned_abs() as u32 * 10))
+                    .collect()
+            })
+            .collect();
+
+        let order = solve_open_tsp(&matrix).unwrap();
+        assert_visits_every_point(n, &order);
+        assert_eq!(
+            path_cost(&matrix, &full_path(n, &order)),
+            (n as u64 - 1) * 10
+        );
+    }
+
+    #[test]
+    fn unreachable_stop_has_no_order() {
+        for n in [5, EXACT_LIMIT + 4] {
+            let mut matrix = pseudo_random_matrix(n, 7);
+            for row in matrix.iter_mut() {
+                row[2] = None;
+            }
+
+            assert_eq!(solve_open_tsp(&matrix), None, "n = {}", n);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use crate::core::types::{LoadOptions, Profile};
+use crate::graph::ProcessedGraph;
+use crate::parser::parse_osm_xml;
+use crate::processing::GraphBuilder;
+
+pub fn profile(json: &str) -> Profile {
+    let mut value: serde_json::Value = serde_json::from_str(
+        r#"{"id":"p","key":"highway","penalties":{"residential":1,"primary":1,"footway":1,"service":1},"access_tags":["motorcar","access"],"oneway_tags":["oneway"]}"#,
+    )
+    .unwrap();
+    let extra: serde_json::Value = serde_json::from_str(json).unwrap();
+    for (key, field) in extra.as_object().unwrap() {
+        value[key] = field.clone();
+    }
+    serde_json::from_value(value).unwrap()
+}
+
+pub fn options(json: &str, profiles: Vec<Profile>) -> LoadOptions {
+    let mut value: serde_json::Value =
+        serde_json::from_str(r#"{"file_path":"graph.bin","ttl_days":0}"#).unwrap();
+    let extra: serde_json::Value = serde_json::from_str(json).unwrap();
+    for (key, field) in extra.as_object().unwrap() {
+        value[key] = field.clone();
+    }
+    value["profiles"] = serde_json::to_value(&profiles).unwrap();
+    serde_json::from_value(value).unwrap()
+}
+
+pub fn build_graph(xml: &str, options_json: &str, profile_json: &str) -> ProcessedGraph {
+    let (nodes, ways, relations) = parse_osm_xml(xml).unwrap();
+    let profile = profile(profile_json);
+    let options = options(options_json, vec![profile.clone()]);
+    GraphBuilder::new(&profile, &options, &nodes, &ways, &relations)
+        .build()
+        .unwrap()
+}
+
+fn tags_xml(tags: &[(&str, &str)]) -> String {
+    tags.iter()
+        .map(|(k, v)| format!(r#"<tag k="{}" v="{}"/>"#, k, v))
+        .collect()
+}
+
+pub fn node(id: i64, lon: f64, lat: f64) -> String {
+    format!(r#"<node id="{}" lat="{}" lon="{}"/>"#, id, lat, lon)
+}
+
+pub fn tagged_node(id: i64, lon: f64, lat: f64, tags: &[(&str, &str)]) -> String {
+    format!(
+        r#"<node id="{}" lat="{}" lon="{}">{}</node>"#,
+        id,
+        lat,
+        lon,
+        tags_xml(tags)
+    )
+}
+
+pub fn way(id: i64, nodes: &[i64], tags: &[(&str, &str)]) -> String {
+    let refs: String = nodes
+        .iter()
+        .map(|n| format!(r#"<nd ref="{}"/>"#, n))
+        .collect();
+    format!(r#"<way id="{}">{}{}</way>"#, id, refs, tags_xml(tags))
+}
+
+pub fn relation(id: i64, members: &[(&str, i64, &str)], tags: &[(&str, &str)]) -> String {
+    let members: String = members
+        .iter()
+        .map(|(kind, id, role)| {
+            format!(r#"<member type="{}" ref="{}" role="{}"/>"#, kind, id, role)
+        })
+        .collect();
+    format!(
+        r#"<relation id="{}">{}{}</relation>"#,
+        id,
+        members,
+        tags_xml(tags)
+    )
+}
+
+pub fn osm(parts: &[String]) -> String {
+    format!("<osm>{}</osm>", parts.concat())
+}
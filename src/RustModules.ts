@@ -1,17 +1,98 @@
-import { Location, OsmNode, OsmWay, QueueStatus, RouteResult } from "./typings";
+import {
+    BuildReport,
+    GraphInfo,
+    GraphStats,
+    IsochroneBand,
+    Location,
+    MatrixOptions,
+    NearestFacility,
+    NearestWayMatch,
+    OptimizedRouteResult,
+    OptionsValidation,
+    OsmId,
+    OsmNode,
+    OsmWay,
+    OsrmResponse,
+    OsrmRouteOptions,
+    QueueStatus,
+    ReachablePoi,
+    RouteComparison,
+    RouteOptions,
+    RouteRequestOptions,
+    RouteResult,
+    SelfTestReport,
+    SnapDirection,
+    TurnRestrictionInfo,
+    WayGeometry,
+} from "./typings";
 
 const binding = require("../index.node");
 
 export const loadGraph: (optionsJson: string) => number = binding.loadGraph;
+export const validateLoadOptions: (optionsJson: string) => OptionsValidation = binding.validateLoadOptions;
 export const unloadGraph: (graphId: number) => boolean = binding.unloadGraph;
+export const pinGraph: (graphId: number) => boolean = binding.pinGraph;
+export const unpinGraph: (graphId: number) => boolean = binding.unpinGraph;
+export const isGraphPinned: (graphId: number) => boolean = binding.isGraphPinned;
+export const getLastBuildReport: (graphId: number) => BuildReport = binding.getLastBuildReport;
+export const getGraphInfo: (graphId: number) => GraphInfo = binding.getGraphInfo;
 
-export const getNode: (graphId: number, profileId: string, nodeId: number) => OsmNode | null =
+export const getNode: (graphId: number, profileId: string, nodeId: OsmId) => OsmNode | null =
     binding.getNode;
 
-export const getShape: (graphId: number, profileId: string, nodes: number[]) => Location[] = binding.getShape;
+export const getNodes: (graphId: number, profileId: string, nodeIds: OsmId[]) => (OsmNode | null)[] =
+    binding.getNodes;
 
-export const getNearestNode: (graphId: number, profileId: string, lon: number, lat: number) => number | null =
-    binding.getNearestNode;
+export const getRestrictionsAtNode: (
+    graphId: number,
+    profileId: string,
+    nodeId: OsmId
+) => TurnRestrictionInfo[] = binding.getRestrictionsAtNode;
+
+export const validateRoute: (graphId: number, profileId: string, nodeIds: OsmId[]) => number | null =
+    binding.validateRoute;
+
+export const getShape: (graphId: number, profileId: string, nodes: OsmId[]) => Location[] = binding.getShape;
+
+export const getNodeDensity: (graphId: number, profileId: string, precision: number) => Record<string, number> =
+    binding.getNodeDensity;
+
+export const getGraphStats: (graphId: number, profileId: string) => GraphStats = binding.getGraphStats;
+
+export const applyEdgeWeights: (graphId: number, profileId: string, csv: string | Buffer) => Promise<number> =
+    binding.applyEdgeWeights;
+
+export const warmGraph: (graphId: number, profileId: string) => Promise<void> = binding.warmGraph;
+
+export const selfTest: (graphId: number, profileId: string, sampleCount: number) => Promise<SelfTestReport> =
+    binding.selfTest;
+
+export const getNearestNode: (
+    graphId: number,
+    profileId: string,
+    lon: number,
+    lat: number,
+    direction?: SnapDirection
+) => OsmId | null = binding.getNearestNode;
+
+export const getRepresentativeNode: (graphId: number, profileId: string) => OsmId | null =
+    binding.getRepresentativeNode;
+
+export const getNearestWayGeometry: (
+    graphId: number,
+    profileId: string,
+    lon: number,
+    lat: number
+) => WayGeometry | null = binding.getNearestWayGeometry;
+
+export const getNearestWayMatching: (
+    graphId: number,
+    profileId: string,
+    lon: number,
+    lat: number,
+    tagKey: string,
+    tagValue?: string
+) => NearestWayMatch | null = binding.getNearestWayMatching;
 
 export const getNodesInRadius: (
     graphId: number,
@@ -32,13 +113,82 @@ export const getWaysInRadius: (
 export const getRoute: (
     graphId: number,
     profileId: string,
-    waypoints: number[]
+    waypoints: OsmId[],
+    options?: RouteRequestOptions
 ) => Promise<RouteResult | null> = binding.getRoute;
 
+export const getRouteOsrm: (
+    graphId: number,
+    profileId: string,
+    coordinates: Location[],
+    options?: OsrmRouteOptions
+) => Promise<OsrmResponse> = binding.getRouteOsrm;
+
+export const getOptimizedRoute: (
+    graphId: number,
+    profileId: string,
+    start: OsmId,
+    waypoints: OsmId[],
+    end: OsmId
+) => Promise<OptimizedRouteResult | null> = binding.getOptimizedRoute;
+
+export const getMatrix: (
+    graphId: number,
+    profileId: string,
+    points: OsmId[],
+    options?: MatrixOptions
+) => Promise<(number | null)[][]> = binding.getMatrix;
+
+export const getIsochronePolygon: (
+    graphId: number,
+    profileId: string,
+    startNode: OsmId,
+    maxCost: number,
+    concavity: number,
+    options?: RouteOptions
+) => Promise<Location[]> = binding.getIsochronePolygon;
+
+export const getIsochroneBands: (
+    graphId: number,
+    profileId: string,
+    startNode: OsmId,
+    budgets: number[],
+    options?: RouteOptions
+) => Promise<IsochroneBand[]> = binding.getIsochroneBands;
+
+export const findReachablePois: (
+    graphId: number,
+    profileId: string,
+    startNode: OsmId,
+    maxCost: number,
+    tagKey: string,
+    tagValue?: string,
+    options?: RouteOptions
+) => Promise<ReachablePoi[]> = binding.findReachablePois;
+
+export const findNodesByTag: (graphId: number, profileId: string, tagKey: string, tagValue?: string) => OsmId[] =
+    binding.findNodesByTag;
+
+export const getNearestFacilityCost: (
+    graphId: number,
+    profileId: string,
+    originNodes: OsmId[],
+    facilityNodes: OsmId[],
+    options?: RouteOptions
+) => Promise<NearestFacility[]> = binding.getNearestFacilityCost;
+
+export const compareRoutes: (
+    graphId: number,
+    profileIdA: string,
+    profileIdB: string,
+    start: OsmId,
+    end: OsmId
+) => Promise<RouteComparison> = binding.compareRoutes;
+
 export const createRouteQueue: (graphId: number, profileId: string, maxConcurrency?: number) => number =
     binding.createRouteQueue;
 
-export const enqueueRoute: (queueId: number, routeId: string, waypoints: number[]) => string =
+export const enqueueRoute: (queueId: number, routeId: string, waypoints: OsmId[]) => string =
     binding.enqueueRoute;
 
 export const processQueue: (
@@ -1,6 +1,15 @@
-import { loadGraph, unloadGraph } from "../RustModules";
+import {
+    getGraphInfo,
+    getLastBuildReport,
+    isGraphPinned,
+    loadGraph,
+    pinGraph,
+    unloadGraph,
+    unpinGraph,
+    validateLoadOptions,
+} from "../RustModules";
 import Profile, { ProfileOptions } from "./Profile";
-import { Location, RawProfile } from "../typings";
+import { GraphInfo, Location, OptionsValidation, RawProfile } from "../typings";
 import { existsSync, mkdirSync } from "fs";
 import { dirname } from "path";
 
@@ -23,6 +32,16 @@ type ProtobufGraphOptions = {
 export type GraphOptions = {
     filePath: string;
     ttlDays: number;
+    snapToleranceM?: number;
+    contractShapeNodes?: boolean;
+    edgeBased?: boolean;
+    maxClonesPerNode?: number;
+    mergeCoincidentNodes?: boolean;
+    shareTopology?: boolean;
+    shareInterner?: boolean;
+    indexTags?: string[];
+    earthRadiusKm?: number;
+    maxOutsideBoundsKm?: number;
 } & (
     | { overpassGraph: OverpassGraphOptions; protobufGraph?: never }
     | { protobufGraph: ProtobufGraphOptions; overpassGraph?: never }
@@ -55,15 +74,11 @@ class Graph {
             mkdirSync(dir, { recursive: true });
         }
 
-        return (this.graphId = loadGraph(
-            JSON.stringify({
-                file_path: this.options.filePath,
-                ttl_days: this.options.ttlDays,
-                profiles: this.profiles,
-                overpass: this.overpassConfig,
-                protobuf: this.protobufConfig,
-            })
-        ));
+        return (this.graphId = loadGraph(this.optionsJson));
+    };
+
+    validateOptions = (): OptionsValidation => {
+        return validateLoadOptions(this.optionsJson);
     };
 
     unloadGraph = () => {
@@ -72,6 +87,56 @@ class Graph {
         return unloadGraph(this.graphId);
     };
 
+    pinGraph = () => {
+        if (this.graphId === null) throw new Error("Graph is not loaded.");
+
+        return pinGraph(this.graphId);
+    };
+
+    unpinGraph = () => {
+        if (this.graphId === null) throw new Error("Graph is not loaded.");
+
+        return unpinGraph(this.graphId);
+    };
+
+    getLastBuildReport = () => {
+        if (this.graphId === null) throw new Error("Graph is not loaded.");
+
+        return getLastBuildReport(this.graphId);
+    };
+
+    getGraphInfo = (): GraphInfo => {
+        if (this.graphId === null) throw new Error("Graph is not loaded.");
+
+        return getGraphInfo(this.graphId);
+    };
+
+    isPinned = () => {
+        if (this.graphId === null) return false;
+
+        return isGraphPinned(this.graphId);
+    };
+
+    private get optionsJson() {
+        return JSON.stringify({
+            file_path: this.options.filePath,
+            ttl_days: this.options.ttlDays,
+            profiles: this.profiles,
+            overpass: this.overpassConfig,
+            protobuf: this.protobufConfig,
+            snap_tolerance_m: this.options.snapToleranceM ?? 0,
+            contract_shape_nodes: this.options.contractShapeNodes ?? false,
+            edge_based: this.options.edgeBased ?? false,
+            max_clones_per_node: this.options.maxClonesPerNode ?? null,
+            merge_coincident_nodes: this.options.mergeCoincidentNodes ?? false,
+            share_topology: this.options.shareTopology ?? false,
+            share_interner: this.options.shareInterner ?? false,
+            index_tags: this.options.indexTags ?? [],
+            earth_radius_km: this.options.earthRadiusKm ?? null,
+            max_outside_bounds_km: this.options.maxOutsideBoundsKm ?? null,
+        });
+    }
+
     private get protobufConfig() {
         if (!this.options.protobufGraph) return undefined;
         const protoOptions = this.options.protobufGraph;
@@ -1,12 +1,54 @@
 import {
+    applyEdgeWeights,
+    compareRoutes,
+    findNodesByTag,
+    findReachablePois,
+    getGraphStats,
+    getNearestFacilityCost,
     getNearestNode,
+    getNearestWayGeometry,
+    getNearestWayMatching,
+    getIsochroneBands,
+    getIsochronePolygon,
     getNode,
+    getNodeDensity,
+    getNodes,
     getNodesInRadius,
+    getMatrix,
+    getOptimizedRoute,
+    getRepresentativeNode,
+    getRestrictionsAtNode,
     getRoute,
+    getRouteOsrm,
     getShape,
     getWaysInRadius,
+    selfTest,
+    validateRoute,
+    warmGraph,
 } from "../RustModules";
-import { Location, OsmNode, OsmWay, RawProfile, RouteResult } from "../typings";
+import {
+    GraphStats,
+    Location,
+    MatrixOptions,
+    NearestFacility,
+    NearestWayMatch,
+    OptimizedRouteResult,
+    OsmId,
+    OsmNode,
+    OsmWay,
+    OsrmResponse,
+    OsrmRouteOptions,
+    RawProfile,
+    ReachablePoi,
+    RouteComparison,
+    RouteOptions,
+    RouteRequestOptions,
+    RouteResult,
+    SelfTestReport,
+    SnapDirection,
+    TurnRestrictionInfo,
+    WayGeometry,
+} from "../typings";
 import Graph from "./Graph";
 import RouteQueue from "./RouteQueue";
 
@@ -62,6 +104,20 @@ export type ProfileOptions = (
     accessTags?: string[];
     onewayTags?: string[];
     exceptTags?: string[];
+    pedestrianAreas?: boolean;
+    accessHierarchy?: Record<string, string>;
+    penaltyMatch?: "exact" | "prefix" | "suffix";
+    unknownHighwayPolicy?: "default" | "exclude" | "error-on-build";
+    speeds?: Record<string, number>;
+    defaultSpeed?: number;
+    excludeMotorroads?: boolean;
+    penaltyExpression?: string;
+    ignoreAccessRestrictions?: boolean;
+    ignoreTurnRestrictions?: boolean;
+    turnAngleWeight?: number;
+    unlitMultiplier?: number;
+    minWidth?: number;
+    narrowWayMultiplier?: number;
 };
 
 class Profile {
@@ -88,13 +144,45 @@ class Profile {
             access_tags: Array.from(new Set([...(profile.accessTags ?? []), "access"])),
             oneway_tags: Array.from(new Set([...(profile.onewayTags ?? []), "oneway"])),
             except_tags: profile.exceptTags ?? [],
+            pedestrian_areas: profile.pedestrianAreas ?? false,
+            access_hierarchy: profile.accessHierarchy ?? {},
+            penalty_match: profile.penaltyMatch ?? "exact",
+            unknown_highway_policy: profile.unknownHighwayPolicy ?? "default",
+            speeds: profile.speeds ?? {},
+            default_speed: profile.defaultSpeed,
+            exclude_motorroads: profile.excludeMotorroads ?? false,
+            penalty_expression: profile.penaltyExpression,
+            ignore_access_restrictions: profile.ignoreAccessRestrictions ?? false,
+            ignore_turn_restrictions: profile.ignoreTurnRestrictions ?? false,
+            turn_angle_weight: profile.turnAngleWeight ?? 0,
+            unlit_multiplier: profile.unlitMultiplier ?? 1,
+            min_width: profile.minWidth,
+            narrow_way_multiplier: profile.narrowWayMultiplier,
         };
     }
 
-    getNearestNode = ([lon, lat]: Location): number | null => {
+    getNearestNode = ([lon, lat]: Location, direction?: SnapDirection): OsmId | null => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return getNearestNode(this.graph.graphId, this.rawProfile.id, lon, lat, direction);
+    };
+
+    getRepresentativeNode = (): OsmId | null => {
         if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
 
-        return getNearestNode(this.graph.graphId, this.rawProfile.id, lon, lat);
+        return getRepresentativeNode(this.graph.graphId, this.rawProfile.id);
+    };
+
+    getNearestWayGeometry = ([lon, lat]: Location): WayGeometry | null => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return getNearestWayGeometry(this.graph.graphId, this.rawProfile.id, lon, lat);
+    };
+
+    getNearestWayMatching = ([lon, lat]: Location, tagKey: string, tagValue?: string): NearestWayMatch | null => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return getNearestWayMatching(this.graph.graphId, this.rawProfile.id, lon, lat, tagKey, tagValue);
     };
 
     getNodesInRadius = ([lon, lat]: Location, radiusMeters: number): OsmNode[] => {
@@ -109,24 +197,140 @@ class Profile {
         return getWaysInRadius(this.graph.graphId, this.rawProfile.id, lon, lat, radiusMeters);
     };
 
-    getRoute = async (waypoints: number[]) => {
+    getRoute = async (waypoints: OsmId[], options?: RouteRequestOptions) => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return getRoute(this.graph.graphId, this.rawProfile.id, waypoints, options);
+    };
+
+    getRouteOsrm = async (coordinates: Location[], options?: OsrmRouteOptions): Promise<OsrmResponse> => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return getRouteOsrm(this.graph.graphId, this.rawProfile.id, coordinates, options);
+    };
+
+    getOptimizedRoute = async (
+        start: OsmId,
+        waypoints: OsmId[],
+        end: OsmId
+    ): Promise<OptimizedRouteResult | null> => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return getOptimizedRoute(this.graph.graphId, this.rawProfile.id, start, waypoints, end);
+    };
+
+    getMatrix = async (points: OsmId[], options?: MatrixOptions): Promise<(number | null)[][]> => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return getMatrix(this.graph.graphId, this.rawProfile.id, points, options);
+    };
+
+    compareRoutes = async (other: Profile, start: OsmId, end: OsmId): Promise<RouteComparison> => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return compareRoutes(this.graph.graphId, this.rawProfile.id, other.rawProfile.id, start, end);
+    };
+
+    getIsochronePolygon = async (startNode: OsmId, maxCost: number, concavity = 2, options?: RouteOptions) => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return getIsochronePolygon(this.graph.graphId, this.rawProfile.id, startNode, maxCost, concavity, options);
+    };
+
+    getIsochroneBands = async (startNode: OsmId, budgets: number[], options?: RouteOptions) => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return getIsochroneBands(this.graph.graphId, this.rawProfile.id, startNode, budgets, options);
+    };
+
+    findReachablePois = async (
+        startNode: OsmId,
+        maxCost: number,
+        tagKey: string,
+        tagValue?: string,
+        options?: RouteOptions
+    ): Promise<ReachablePoi[]> => {
         if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
 
-        return getRoute(this.graph.graphId, this.rawProfile.id, waypoints);
+        return findReachablePois(this.graph.graphId, this.rawProfile.id, startNode, maxCost, tagKey, tagValue, options);
     };
 
-    getNode = (node: number): OsmNode | null => {
+    findNodesByTag = (tagKey: string, tagValue?: string): OsmId[] => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return findNodesByTag(this.graph.graphId, this.rawProfile.id, tagKey, tagValue);
+    };
+
+    getNearestFacilityCost = async (
+        originNodes: OsmId[],
+        facilityNodes: OsmId[],
+        options?: RouteOptions
+    ): Promise<NearestFacility[]> => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return getNearestFacilityCost(this.graph.graphId, this.rawProfile.id, originNodes, facilityNodes, options);
+    };
+
+    getNode = (node: OsmId): OsmNode | null => {
         if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
 
         return getNode(this.graph.graphId, this.rawProfile.id, node);
     };
 
+    getNodes = (nodes: OsmId[]): (OsmNode | null)[] => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return getNodes(this.graph.graphId, this.rawProfile.id, nodes);
+    };
+
+    getRestrictionsAtNode = (nodeId: OsmId): TurnRestrictionInfo[] => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return getRestrictionsAtNode(this.graph.graphId, this.rawProfile.id, nodeId);
+    };
+
+    validateRoute = (nodeIds: OsmId[]): number | null => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return validateRoute(this.graph.graphId, this.rawProfile.id, nodeIds);
+    };
+
     getShape = ({ nodes }: RouteResult): Location[] => {
         if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
 
         return getShape(this.graph.graphId, this.rawProfile.id, nodes);
     };
 
+    getGraphStats = (): GraphStats => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return getGraphStats(this.graph.graphId, this.rawProfile.id);
+    };
+
+    applyEdgeWeights = (csv: string | Buffer): Promise<number> => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return applyEdgeWeights(this.graph.graphId, this.rawProfile.id, csv);
+    };
+
+    selfTest = (sampleCount: number = 100): Promise<SelfTestReport> => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return selfTest(this.graph.graphId, this.rawProfile.id, sampleCount);
+    };
+
+    warmGraph = (): Promise<void> => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return warmGraph(this.graph.graphId, this.rawProfile.id);
+    };
+
+    getNodeDensity = (precision: number): Record<string, number> => {
+        if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
+
+        return getNodeDensity(this.graph.graphId, this.rawProfile.id, precision);
+    };
+
     createRouteQueue = (enableProgressBar?: boolean, maxConcurrency?: number) => {
         if (this.graph.graphId === null) throw new Error("Graph is not loaded.");
 
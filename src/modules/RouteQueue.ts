@@ -1,4 +1,4 @@
-import { RouteResult } from "../typings";
+import { OsmId, RouteResult } from "../typings";
 import cliProgress from "cli-progress";
 import { cpus } from "os";
 import {
@@ -24,7 +24,7 @@ class RouteQueue {
         this.enableProgressBar = enableProgressBar;
     }
 
-    enqueueRoute = (routeId: string, waypoints: number[]) => {
+    enqueueRoute = (routeId: string, waypoints: OsmId[]) => {
         if (this.processing) throw new Error("Queue is already processing. Cannot enqueue new routes.");
         return enqueueRoute(this.queueId, routeId, waypoints);
     };
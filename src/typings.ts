@@ -1,21 +1,214 @@
 export type Location = [lon: number, lat: number];
 
+export type OsmId = number | string;
+
+export type SnapDirection = "start" | "end";
+
+export type RouteMetric = "distance" | "time" | "minimax";
+
+export interface RouteOptions {
+    metric?: RouteMetric;
+}
+
+export interface RouteRequestOptions extends RouteOptions {
+    initialBearing?: number;
+    originFixes?: [previous: Location, current: Location];
+    simplifyEpsilon?: number;
+    preferLit?: boolean;
+    requiredWays?: OsmId[];
+    includeElevations?: boolean;
+    includeWarnings?: boolean;
+}
+
+export interface OsrmRouteOptions extends RouteOptions {
+    initialBearing?: number;
+    preferLit?: boolean;
+    requiredWays?: OsmId[];
+    geometries?: "polyline" | "polyline6" | "geojson";
+}
+
+export interface OsrmLeg {
+    distance: number;
+    duration: number;
+    weight: number;
+    summary: string;
+    steps: [];
+}
+
+export interface OsrmRoute {
+    geometry: string | { type: "LineString"; coordinates: Location[] };
+    legs: OsrmLeg[];
+    distance: number;
+    duration: number;
+    weight_name: RouteMetric;
+    weight: number;
+}
+
+export interface OsrmWaypoint {
+    name: string;
+    location: Location;
+    distance: number;
+}
+
+export interface OsrmResponse {
+    code: "Ok" | "NoRoute" | "NoSegment" | "InvalidValue" | "InvalidOptions";
+    message?: string;
+    routes?: OsrmRoute[];
+    waypoints?: OsrmWaypoint[];
+}
+
+export interface RouteWarnings {
+    ferry: boolean;
+    toll: boolean;
+    unpaved: boolean;
+    private: boolean;
+}
+
+export interface RouteLeg {
+    cost: number;
+    distanceMeters: number;
+    nodeStartIndex: number;
+    nodeEndIndex: number;
+}
+
 export interface RouteResult {
-    nodes: number[];
+    nodes: OsmId[];
+    waypointIndices?: number[];
+    coordinates?: Location[];
+    legs?: RouteLeg[];
+    elevations?: (number | null)[];
+    distances?: number[];
+    warnings?: RouteWarnings;
+    noRouteBetween?: [number, number];
+}
+
+export interface MatrixOptions {
+    concurrency?: number;
+}
+
+export interface OptimizedRouteResult {
+    order: number[];
+    nodes: OsmId[];
+    cost: number;
+}
+
+export interface ComparedRoute {
+    nodes: OsmId[];
+    cost: number;
+    distanceMeters: number;
+}
+
+export interface RouteComparison {
+    routeA: ComparedRoute | null;
+    routeB: ComparedRoute | null;
+    overlap: number;
+    costDelta: number | null;
+    distanceDelta: number | null;
 }
 
 export interface OsmNode {
-    id: number;
+    id: OsmId;
     location: Location;
     tags: Record<string, string>;
 }
 
 export interface OsmWay {
-    id: number;
+    id: OsmId;
     tags: Record<string, string>;
     nodes: OsmNode[];
 }
 
+export interface NearestWayMatch {
+    id: OsmId;
+    name: string | null;
+    distanceMeters: number;
+}
+
+export interface WayGeometry {
+    id: OsmId;
+    name: string | null;
+    geometry: Location[];
+}
+
+export interface IsochroneBand {
+    budget: number;
+    nodes: OsmId[];
+}
+
+export interface ReachablePoi {
+    id: OsmId;
+    location: Location;
+    cost: number;
+}
+
+export interface NearestFacility {
+    originId: OsmId;
+    nearestFacilityId: OsmId | null;
+    cost: number | null;
+}
+
+export interface GraphStats {
+    nodes: number;
+    edges: number;
+    ways: number;
+    connectors: number;
+}
+
+export interface TurnRestrictionInfo {
+    id: OsmId;
+    type: string;
+    fromWay: OsmId;
+    viaWays: OsmId[];
+    viaNodes: OsmId[];
+    toWay: OsmId;
+}
+
+export interface SelfTestReport {
+    samples: number;
+    successRate: number;
+    medianLatencyMs: number;
+    medianExpanded: number;
+}
+
+export interface ProfileBuildReport {
+    warnings: string[];
+    skippedWays: number;
+    skippedRestrictions: number;
+    restrictionsOverCloneCap: number;
+    mergedNodes: number;
+    sharedTopologyWith: string | null;
+    unknownHighwayValues: string[];
+}
+
+export interface BuildReport {
+    fromCache: boolean;
+    profiles: Record<string, ProfileBuildReport>;
+}
+
+export interface GraphSource {
+    type: "overpass" | "protobuf";
+    location: string;
+}
+
+export interface GraphInfo {
+    builtAt: number;
+    source: GraphSource | null;
+    cachePath: string;
+    ttlDays: number;
+    fromCache: boolean;
+}
+
+export interface OptionsIssue {
+    path: string;
+    message: string;
+}
+
+export interface OptionsValidation {
+    valid: boolean;
+    errors: OptionsIssue[];
+    warnings: OptionsIssue[];
+}
+
 export interface QueueStatus {
     queuedTasks: number;
     activeTasks: number;
@@ -29,4 +222,18 @@ export type RawProfile = {
     access_tags: string[];
     oneway_tags: string[];
     except_tags: string[];
+    pedestrian_areas: boolean;
+    access_hierarchy: Record<string, string>;
+    penalty_match: "exact" | "prefix" | "suffix";
+    unknown_highway_policy: "default" | "exclude" | "error-on-build";
+    speeds: Record<string, number>;
+    default_speed?: number;
+    exclude_motorroads: boolean;
+    penalty_expression?: string;
+    ignore_access_restrictions: boolean;
+    ignore_turn_restrictions: boolean;
+    turn_angle_weight: number;
+    unlit_multiplier: number;
+    min_width?: number;
+    narrow_way_multiplier?: number;
 };